@@ -0,0 +1,704 @@
+//! The UnixFS tree in the CAR file.
+//!
+//! The CAR file is opened as the read-only virtual directory, the directories are read
+//! when the CAR file is opened, the files are read from the CAR blocks on demand instead
+//! of being extracted, so the files are never loaded into the memory as a whole.
+//! The files are written into the CAR file as a UnixFS tree.
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    fs::File,
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use anyhow::{bail, Context};
+use log::error;
+use rust_car::{
+    codec::Encoder,
+    header::CarHeader,
+    reader::{self, CarReader},
+    unixfs::{FileType, Link, UnixFs},
+    utils::ipld_write,
+    writer::{self as car_writer, CarWriter},
+    Ipld,
+};
+use tempdir::TempDir;
+use wasi_common::{BlockContent, BlocklessConfig, ContentReader, VirtualDir, VirtualEntry};
+
+use crate::AnyResult;
+
+/// the multicodec of the raw block, the file in a single block.
+const RAW: u64 = 0x55;
+
+/// the deepest directory or file tree in the CAR, the blocks are not verified,
+/// so a forged CAR can link a block to its ancestor.
+const MAX_DEPTH: usize = 64;
+
+/// the most leaf blocks of a file in the CAR.
+const MAX_LEAVES: usize = 1 << 22;
+
+/// open the CAR file as the virtual directory of its root.
+pub fn open_car_dir<R>(inner: R) -> AnyResult<VirtualDir>
+where
+    R: Read + Seek + Send + 'static,
+{
+    car_root_dir(reader::new_v1(inner)?)
+}
+
+/// the virtual directory of the root of the CAR, the reader is kept to read the files.
+pub fn car_root_dir<T>(mut reader: T) -> AnyResult<VirtualDir>
+where
+    T: CarReader + Send + 'static,
+{
+    let root = match reader.header().roots().first() {
+        Some(cid) => *cid,
+        None => bail!("the car file has no root"),
+    };
+    let unixfs: UnixFs = (root, reader.ipld(&root)?).try_into()?;
+    if !matches!(unixfs.file_type(), FileType::Directory) {
+        bail!("the root {root} is not a directory");
+    }
+    unixfs_dir(
+        &Arc::new(Mutex::new(reader)),
+        unixfs,
+        0,
+        &mut HashMap::new(),
+    )
+}
+
+/// the directory tree, the directories linked more than once are read once and shared,
+/// the key of `dirs` is the cid.
+fn unixfs_dir<T>(
+    reader: &Arc<Mutex<T>>,
+    unixfs: UnixFs,
+    depth: usize,
+    dirs: &mut HashMap<String, Arc<VirtualDir>>,
+) -> AnyResult<VirtualDir>
+where
+    T: CarReader + Send + 'static,
+{
+    if depth > MAX_DEPTH {
+        bail!("the directory is deeper than {MAX_DEPTH}");
+    }
+    let mut dir = VirtualDir::default();
+    for link in unixfs.links() {
+        let cid = link.hash;
+        if let Some(shared) = dirs.get(&cid.to_string()) {
+            dir.insert(&link.name, VirtualEntry::Dir(shared.clone()));
+            continue;
+        }
+        let is_raw = cid.codec() == RAW;
+        // the raw block is always a file, the others are decoded to find the directories.
+        let child: Option<UnixFs> = match is_raw {
+            true => None,
+            false => match reader.lock().unwrap().ipld(&cid)? {
+                ipld @ Ipld::Map(_) => Some((cid, ipld).try_into()?),
+                _ => None,
+            },
+        };
+        let entry = match child {
+            Some(child) if matches!(child.file_type(), FileType::Directory) => {
+                let child = Arc::new(unixfs_dir(reader, child, depth + 1, dirs)?);
+                dirs.insert(cid.to_string(), child.clone());
+                VirtualEntry::Dir(child)
+            }
+            Some(child) if matches!(child.file_type(), FileType::HAMTShard) => {
+                bail!(
+                    "the HAMT sharded directory `{}` is not supported",
+                    link.name
+                )
+            }
+            _ => {
+                let file = Link::new(cid, String::new(), link.tsize);
+                VirtualEntry::File(Arc::new(car_file(reader, file, is_raw)))
+            }
+        };
+        dir.insert(&link.name, entry);
+    }
+    Ok(dir)
+}
+
+/// the file in the CAR, only the index of the leaf blocks is kept and the leaf
+/// blocks are read on demand.
+fn car_file<T>(reader: &Arc<Mutex<T>>, file: Link, is_raw: bool) -> BlockContent
+where
+    T: CarReader + Send + 'static,
+{
+    let (cid, tsize) = (file.hash, file.tsize);
+    // the size of the raw block is the file size.
+    let size = is_raw.then_some(tsize);
+    let leaves = Arc::new(OnceLock::new());
+    let index = {
+        let reader = reader.clone();
+        let leaves = leaves.clone();
+        move || {
+            let mut list = Vec::new();
+            let file = Link::new(cid, String::new(), tsize);
+            file_leaves(&mut *reader.lock().unwrap(), file, 0, &mut list)?;
+            let lens = list.iter().map(|leaf| leaf.tsize).collect();
+            let _ = leaves.set(list);
+            Ok(lens)
+        }
+    };
+    let reader = reader.clone();
+    BlockContent::new(size, index, move |i| {
+        let leaf = match leaves.get().and_then(|list: &Vec<Link>| list.get(i)) {
+            Some(leaf) => leaf.hash,
+            None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        };
+        let mut data = Vec::new();
+        let mut reader = reader.lock().unwrap();
+        ipld_write(&mut *reader, leaf, &mut data).map_err(io::Error::other)?;
+        Ok(data)
+    })
+}
+
+/// the leaf blocks of the file in order, the `tsize` of the leaf is its length.
+/// The raw leaf is not read, the `tsize` of its link is the length.
+fn file_leaves<T: CarReader>(
+    reader: &mut T,
+    file: Link,
+    depth: usize,
+    leaves: &mut Vec<Link>,
+) -> io::Result<()> {
+    if depth > MAX_DEPTH || leaves.len() >= MAX_LEAVES {
+        let msg =
+            format!("the file is deeper than {MAX_DEPTH} or has more than {MAX_LEAVES} blocks");
+        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    }
+    let cid = file.hash;
+    if cid.codec() == RAW {
+        leaves.push(file);
+        return Ok(());
+    }
+    if let ipld @ Ipld::Map(_) = reader.ipld(&cid).map_err(io::Error::other)? {
+        let unixfs: UnixFs = (cid, ipld).try_into().map_err(io::Error::other)?;
+        let children = unixfs
+            .links()
+            .into_iter()
+            .map(|link| Link::new(link.hash, String::new(), link.tsize))
+            .collect::<Vec<_>>();
+        if !children.is_empty() {
+            for child in children {
+                file_leaves(reader, child, depth + 1, leaves)?;
+            }
+            return Ok(());
+        }
+    }
+    // the leaf with the data in the node.
+    let mut data = Vec::new();
+    ipld_write(reader, cid, &mut data).map_err(io::Error::other)?;
+    leaves.push(Link::new(cid, String::new(), data.len() as u64));
+    Ok(())
+}
+
+/// open the CAR file mounted by the configure, the CAR file in the package
+/// served from the memory is read from the package blocks on demand.
+pub(crate) fn mount_car_dir(cfg: &BlocklessConfig, car: &str) -> AnyResult<VirtualDir> {
+    let dir = match cfg.package_ref().and_then(|p| p.open_file(car)) {
+        Some(content) => content
+            .map_err(anyhow::Error::from)
+            .and_then(|content| open_car_dir(ContentReader::new(content))),
+        None => File::open(car)
+            .map_err(anyhow::Error::from)
+            .and_then(open_car_dir),
+    };
+    dir.with_context(|| format!("failed to mount the car file: {car}"))
+}
+
+/// The CAR file extracted for the components, which only preopen the host directories.
+/// Besides the read-only preopen, the extracted tree is read-only on the disk,
+/// and it's removed when it's dropped after the run.
+pub(crate) struct ExtractedCar {
+    dir: TempDir,
+}
+
+impl ExtractedCar {
+    pub(crate) fn new(cfg: &BlocklessConfig, car: &str) -> AnyResult<Self> {
+        let vdir = mount_car_dir(cfg, car)?;
+        let dir = TempDir::new("bls-car")?;
+        vdir.write_to(dir.path())
+            .and_then(|_| set_readonly(dir.path(), true))
+            .with_context(|| format!("failed to extract the car file: {car}"))?;
+        Ok(Self { dir })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Drop for ExtractedCar {
+    fn drop(&mut self) {
+        // the tree is writable again to be removed.
+        if let Err(e) = set_readonly(self.dir.path(), false) {
+            error!("failed to remove the extracted car file: {}", e);
+        }
+    }
+}
+
+/// set the files and the directories in the tree read-only or writable by the owner.
+fn set_readonly(path: &Path, readonly: bool) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        for entry in fs::read_dir(path)? {
+            set_readonly(&entry?.path(), readonly)?;
+        }
+    }
+    let mut perms = meta.permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = match readonly {
+            true => perms.mode() & !0o222,
+            false => perms.mode() | 0o200,
+        };
+        perms.set_mode(mode);
+    }
+    #[cfg(not(unix))]
+    perms.set_readonly(readonly);
+    fs::set_permissions(path, perms)
+}
+
+/// write the files into the CAR file, the key is the path in the root directory,
+/// e.g. `assets/logo.png`, the root cid is returned.
+pub fn write_files_car<W>(files: &BTreeMap<String, Vec<u8>>, output: W) -> AnyResult<String>
+where
+    W: Write + Seek,
+{
+    fn parent(path: &str) -> (&str, &str) {
+        path.rsplit_once('/').unwrap_or(("", path))
+    }
+    let mut writer = car_writer::new_v1_default_roots(output)?;
+    // the directories and the total size of the files in them.
+    let mut dirs = BTreeMap::new();
+    dirs.insert(String::new(), (UnixFs::new_directory(), 0u64));
+    for (path, data) in files.iter() {
+        let (dir, name) = parent(path);
+        let mut ancestor = dir;
+        while !dirs.contains_key(ancestor) {
+            dirs.insert(ancestor.to_string(), (UnixFs::new_directory(), 0));
+            ancestor = parent(ancestor).0;
+        }
+        let cid = writer.write_ipld(Ipld::Bytes(data.clone()))?;
+        let (unixfs, size) = dirs.get_mut(dir).unwrap();
+        unixfs.add_link(Link::new(cid, name.to_string(), data.len() as u64));
+        *size += data.len() as u64;
+    }
+    // write the deepest directories first, the root is the last one.
+    let mut names = dirs.keys().cloned().collect::<Vec<_>>();
+    names.sort_by_key(|name| Reverse(name.split('/').filter(|s| !s.is_empty()).count()));
+    let mut root_cid = None;
+    for path in names {
+        let (unixfs, dir_size) = dirs.remove(&path).unwrap();
+        let cid = writer.write_ipld(unixfs.encode()?)?;
+        if path.is_empty() {
+            root_cid = Some(cid);
+            continue;
+        }
+        let (dir, name) = parent(&path);
+        let (parent_fs, size) = dirs.get_mut(dir).unwrap();
+        parent_fs.add_link(Link::new(cid, name.to_string(), dir_size));
+        *size += dir_size;
+    }
+    let root_cid = root_cid.expect("the root directory must be written");
+    writer.rewrite_header(CarHeader::new_v1(vec![root_cid]))?;
+    writer.flush()?;
+    Ok(root_cid.to_string())
+}
+
+/// the largest block of the file packed from the host directory.
+const CHUNK_SIZE: usize = 256 << 10;
+
+/// write the files in the host directory into the CAR file, the root cid is returned.
+/// The files are read in chunks of 256KiB, a file of several chunks is a UnixFS file
+/// node linking the chunks. The symbolic links and the empty directories are skipped.
+pub fn write_dir_car<W>(dir: impl AsRef<Path>, output: W) -> AnyResult<String>
+where
+    W: Write + Seek,
+{
+    let dir = dir.as_ref();
+    let mut writer = car_writer::new_v1_default_roots(output)?;
+    let root = write_dir(&mut writer, dir)
+        .with_context(|| format!("failed to read the directory {}", dir.display()))?;
+    let root_cid = match root {
+        Some(link) => link.hash,
+        None => writer.write_ipld(UnixFs::new_directory().encode()?)?,
+    };
+    writer.rewrite_header(CarHeader::new_v1(vec![root_cid]))?;
+    writer.flush()?;
+    Ok(root_cid.to_string())
+}
+
+/// write the directory and returns its link, `None` if there is no file in it.
+fn write_dir(writer: &mut impl CarWriter, path: &Path) -> AnyResult<Option<Link>> {
+    let mut entries = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    let mut unixfs = UnixFs::new_directory();
+    let mut size = 0;
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let file_type = entry.file_type()?;
+        let link = if file_type.is_dir() {
+            write_dir(writer, &entry.path())?
+        } else if file_type.is_file() {
+            Some(write_file(writer, &entry.path())?)
+        } else {
+            None
+        };
+        if let Some(link) = link {
+            size += link.tsize;
+            unixfs.add_link(Link::new(link.hash, name, link.tsize));
+        }
+    }
+    if unixfs.links().is_empty() {
+        return Ok(None);
+    }
+    let cid = writer.write_ipld(unixfs.encode()?)?;
+    Ok(Some(Link::new(cid, String::new(), size)))
+}
+
+/// write the file by the chunks, only one chunk is in the memory.
+fn write_file(writer: &mut impl CarWriter, path: &Path) -> AnyResult<Link> {
+    let mut file = File::open(path)?;
+    let mut chunks = Vec::new();
+    loop {
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        (&mut file)
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)?;
+        let len = chunk.len() as u64;
+        // the empty file is a single empty block.
+        if len == 0 && !chunks.is_empty() {
+            break;
+        }
+        let cid = writer.write_ipld(Ipld::Bytes(chunk))?;
+        chunks.push(Link::new(cid, String::new(), len));
+        if len < CHUNK_SIZE as u64 {
+            break;
+        }
+    }
+    if chunks.len() == 1 {
+        return Ok(chunks.remove(0));
+    }
+    let size = chunks.iter().map(|chunk| chunk.tsize).sum();
+    let cid = writer.write_ipld(file_node(&chunks, size))?;
+    Ok(Link::new(cid, String::new(), size))
+}
+
+/// the dag-pb node of the UnixFS file linking the chunks.
+fn file_node(chunks: &[Link], size: u64) -> Ipld {
+    fn varint(buf: &mut Vec<u8>, mut n: u64) {
+        while n >= 0x80 {
+            buf.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        buf.push(n as u8);
+    }
+    // the UnixFS `Data` message: `Type` is `File`, then `filesize` and `blocksizes`.
+    let mut data = vec![0x08, 0x02, 0x18];
+    varint(&mut data, size);
+    for chunk in chunks {
+        data.push(0x20);
+        varint(&mut data, chunk.tsize);
+    }
+    let links = chunks
+        .iter()
+        .map(|chunk| {
+            Ipld::Map(BTreeMap::from([
+                ("Hash".to_string(), Ipld::Link(chunk.hash)),
+                ("Name".to_string(), Ipld::String(String::new())),
+                ("Tsize".to_string(), Ipld::Integer(chunk.tsize as i128)),
+            ]))
+        })
+        .collect();
+    Ipld::Map(BTreeMap::from([
+        ("Data".to_string(), Ipld::Bytes(data)),
+        ("Links".to_string(), Ipld::List(links)),
+    ]))
+}
+
+/// The CAR output only computing the root cid, nothing is kept.
+#[derive(Default)]
+struct Discard {
+    pos: u64,
+    len: u64,
+}
+
+impl Write for Discard {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pos += buf.len() as u64;
+        self.len = self.len.max(self.pos);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Discard {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            io::SeekFrom::Start(n) => Some(n),
+            io::SeekFrom::End(n) => self.len.checked_add_signed(n),
+            io::SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        self.pos = pos.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        Ok(self.pos)
+    }
+}
+
+/// The guest output directory can't be packed after the run.
+#[derive(Debug)]
+pub struct OutputError {
+    pub dir: String,
+    pub message: String,
+}
+
+impl std::error::Error for OutputError {}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to pack the output {}: {}",
+            self.dir, self.message
+        )
+    }
+}
+
+/// the host path of the guest path, resolved by the deepest mapped directory,
+/// the root file system is mapped at `/`. The guest path with `..` is not resolved.
+fn guest_host_path(cfg: &BlocklessConfig, guest: &str) -> Option<PathBuf> {
+    let guest = Path::new("/").join(guest);
+    if guest
+        .components()
+        .any(|c| matches!(c, std::path::Component::ParentDir))
+    {
+        return None;
+    }
+    let root = cfg.fs_root_path_ref().map(|root| (root, "/"));
+    cfg.dirs
+        .iter()
+        .map(|(host, guest)| (host.as_str(), guest.as_str()))
+        .chain(root)
+        .filter_map(|(host, mapped)| {
+            let mapped = Path::new("/").join(mapped);
+            let rest = guest.strip_prefix(&mapped).ok()?;
+            Some((mapped.components().count(), Path::new(host).join(rest)))
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, path)| path)
+}
+
+/// pack the guest output directory of the configure into the CAR file after the run,
+/// the CAR file is not written if `output_car` is not set, the root cid is returned.
+pub(crate) fn pack_output(cfg: &BlocklessConfig, guest: &str) -> Result<String, OutputError> {
+    let error = |message: String| OutputError {
+        dir: guest.to_string(),
+        message,
+    };
+    let host = guest_host_path(cfg, guest)
+        .ok_or_else(|| error("the directory is not mapped into the app".into()))?;
+    let rs = match cfg.output_car.as_ref() {
+        Some(car) => File::create(car)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| write_dir_car(&host, file)),
+        None => write_dir_car(&host, Discard::default()),
+    };
+    rs.map_err(|e| error(format!("{e:#}")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_car::{
+        codec::Encoder,
+        header::CarHeader,
+        unixfs::Link,
+        writer::{self as car_writer, CarWriter},
+    };
+    use std::io::Cursor;
+    use wasi_common::{FileContent, PackageFs};
+
+    /// the CAR of `readme.txt` and `data/a.txt`.
+    fn dataset() -> Vec<u8> {
+        let mut car = Cursor::new(Vec::new());
+        let mut writer = car_writer::new_v1_default_roots(&mut car).unwrap();
+        let a = writer.write_ipld(Ipld::Bytes(b"aaa".to_vec())).unwrap();
+        let mut data = UnixFs::new_directory();
+        data.add_link(Link::new(a, "a.txt".into(), 3));
+        let data = writer.write_ipld(data.encode().unwrap()).unwrap();
+        let readme = writer.write_ipld(Ipld::Bytes(b"readme".to_vec())).unwrap();
+        let mut root = UnixFs::new_directory();
+        root.add_link(Link::new(readme, "readme.txt".into(), 6));
+        root.add_link(Link::new(data, "data".into(), 3));
+        let root = writer.write_ipld(root.encode().unwrap()).unwrap();
+        writer
+            .rewrite_header(CarHeader::new_v1(vec![root]))
+            .unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        car.into_inner()
+    }
+
+    #[test]
+    fn test_open_car_dir() {
+        let dir = Arc::new(open_car_dir(Cursor::new(dataset())).unwrap());
+        match dir.lookup("readme.txt").unwrap() {
+            VirtualEntry::File(content) => {
+                assert_eq!(content.len().unwrap(), 6);
+                assert_eq!(content.read_all().unwrap(), b"readme");
+            }
+            VirtualEntry::Dir(_) => panic!("should be the file"),
+        }
+        match dir.lookup("data/a.txt").unwrap() {
+            VirtualEntry::File(content) => assert_eq!(content.read_all().unwrap(), b"aaa"),
+            VirtualEntry::Dir(_) => panic!("should be the file"),
+        }
+        assert!(matches!(dir.lookup("data"), Ok(VirtualEntry::Dir(_))));
+        assert!(open_car_dir(Cursor::new(b"not a car".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_car_dir_bounded() {
+        let mut car = Cursor::new(Vec::new());
+        let mut writer = car_writer::new_v1_default_roots(&mut car).unwrap();
+        let leaf = writer.write_ipld(Ipld::Bytes(b"a".to_vec())).unwrap();
+        let mut data = UnixFs::new_directory();
+        data.add_link(Link::new(leaf, "a.txt".into(), 1));
+        let data = writer.write_ipld(data.encode().unwrap()).unwrap();
+        // the same directory is linked twice.
+        let mut root = UnixFs::new_directory();
+        root.add_link(Link::new(data, "x".into(), 1));
+        root.add_link(Link::new(data, "y".into(), 1));
+        let root = writer.write_ipld(root.encode().unwrap()).unwrap();
+        // the file nodes nested deeper than the limit.
+        let mut file = leaf;
+        for _ in 0..=MAX_DEPTH {
+            file = writer
+                .write_ipld(file_node(&[Link::new(file, String::new(), 1)], 1))
+                .unwrap();
+        }
+        writer
+            .rewrite_header(CarHeader::new_v1(vec![root]))
+            .unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let car = car.into_inner();
+        let dir = Arc::new(open_car_dir(Cursor::new(car.clone())).unwrap());
+        match (dir.lookup("x").unwrap(), dir.lookup("y").unwrap()) {
+            (VirtualEntry::Dir(x), VirtualEntry::Dir(y)) => assert!(Arc::ptr_eq(&x, &y)),
+            _ => panic!("should be the directories"),
+        }
+        let mut reader = reader::new_v1(Cursor::new(car)).unwrap();
+        let mut leaves = Vec::new();
+        let file = Link::new(file, String::new(), 1);
+        assert!(file_leaves(&mut reader, file, 0, &mut leaves).is_err());
+    }
+
+    #[test]
+    fn test_mount_car_in_package() {
+        let mut package = VirtualDir::default();
+        package.insert("data.car", VirtualEntry::File(Arc::new(dataset())));
+        let mut cfg = BlocklessConfig::builder("main.wasm").build();
+        cfg.set_package(Some(PackageFs::new("/env/bafy", package)));
+        // the CAR in the package is read by the blocks, not loaded as a whole.
+        let dir = Arc::new(mount_car_dir(&cfg, "/env/bafy/data.car").unwrap());
+        match dir.lookup("readme.txt").unwrap() {
+            VirtualEntry::File(content) => {
+                let mut buf = [0u8; 4];
+                assert_eq!(content.read_at(&mut buf, 2).unwrap(), 4);
+                assert_eq!(&buf, b"adme");
+                assert_eq!(content.read_at(&mut buf, 6).unwrap(), 0);
+            }
+            VirtualEntry::Dir(_) => panic!("should be the file"),
+        }
+        assert!(mount_car_dir(&cfg, "/env/bafy/none.car").is_err());
+    }
+
+    #[test]
+    fn test_extracted_car() {
+        let root = tempdir::TempDir::new("bls-root").unwrap();
+        let car = root.path().join("data.car");
+        fs::write(&car, dataset()).unwrap();
+        let cfg = BlocklessConfig::builder("main.wasm").build();
+        let extracted = ExtractedCar::new(&cfg, car.to_str().unwrap()).unwrap();
+        let path = extracted.path().to_path_buf();
+        assert_eq!(fs::read(path.join("data/a.txt")).unwrap(), b"aaa");
+        // the extracted tree is read-only on the disk.
+        for file in [path.join("readme.txt"), path.join("data"), path.clone()] {
+            assert!(fs::metadata(file).unwrap().permissions().readonly());
+        }
+        drop(extracted);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_pack_output() {
+        let root = tempdir::TempDir::new("bls-root").unwrap();
+        let data = tempdir::TempDir::new("bls-data").unwrap();
+        fs::create_dir_all(data.path().join("out/img")).unwrap();
+        fs::write(data.path().join("out/result.json"), b"{}").unwrap();
+        fs::write(data.path().join("out/img/a.png"), b"png").unwrap();
+        let car = root.path().join("result.car");
+        let mut cfg = BlocklessConfig::builder("main.wasm")
+            .fs_root_path(root.path().to_str().unwrap())
+            .map_dir(data.path().to_str().unwrap(), "/data")
+            .build();
+        cfg.output_car = Some(car.to_str().unwrap().to_string());
+
+        // the deepest mapped directory is used.
+        assert_eq!(
+            guest_host_path(&cfg, "/data/out"),
+            Some(data.path().join("out"))
+        );
+        assert_eq!(guest_host_path(&cfg, "tmp"), Some(root.path().join("tmp")));
+
+        let cid = pack_output(&cfg, "/data/out").unwrap();
+        let dir = Arc::new(open_car_dir(File::open(&car).unwrap()).unwrap());
+        match dir.lookup("img/a.png").unwrap() {
+            VirtualEntry::File(content) => assert_eq!(content.read_all().unwrap(), b"png"),
+            VirtualEntry::Dir(_) => panic!("should be the file"),
+        }
+        // the same content has the same cid.
+        cfg.output_car = None;
+        assert_eq!(pack_output(&cfg, "/data/out").unwrap(), cid);
+        assert!(pack_output(&cfg, "/data/none").is_err());
+        // the output can't escape the mapped directory.
+        assert_eq!(guest_host_path(&cfg, "/data/../etc"), None);
+        assert!(pack_output(&cfg, "/data/out/..").is_err());
+    }
+
+    #[test]
+    fn test_write_dir_car_chunks() {
+        let data = tempdir::TempDir::new("bls-data").unwrap();
+        let large = (0..CHUNK_SIZE * 2 + 100)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        fs::write(data.path().join("large.bin"), &large).unwrap();
+        fs::write(data.path().join("empty.txt"), b"").unwrap();
+        fs::create_dir_all(data.path().join("none")).unwrap();
+        let mut car = Cursor::new(Vec::new());
+        let cid = write_dir_car(data.path(), &mut car).unwrap();
+        // only the root cid is computed without the output.
+        assert_eq!(write_dir_car(data.path(), Discard::default()).unwrap(), cid);
+        let dir = Arc::new(open_car_dir(Cursor::new(car.into_inner())).unwrap());
+        match dir.lookup("large.bin").unwrap() {
+            VirtualEntry::File(content) => {
+                assert_eq!(content.len().unwrap(), large.len() as u64);
+                assert_eq!(content.read_all().unwrap(), large);
+            }
+            VirtualEntry::Dir(_) => panic!("should be the file"),
+        }
+        match dir.lookup("empty.txt").unwrap() {
+            VirtualEntry::File(content) => assert!(content.read_all().unwrap().is_empty()),
+            VirtualEntry::Dir(_) => panic!("should be the file"),
+        }
+        assert!(dir.lookup("none").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use std::sync::{Arc, Mutex};
 
+use crate::car::ExtractedCar;
 use wasi_common::PermissionsConfig;
 use wasmtime::StoreLimits;
 use wasmtime_wasi::preview1::WasiP1Ctx;
@@ -9,6 +10,9 @@ use wasmtime_wasi_threads::WasiThreadsCtx;
 pub(crate) struct BlocklessContext {
     pub(crate) preview1_ctx: Option<wasi_common::WasiCtx>,
 
+    // the contexts of the library modules with their own permissions.
+    pub(crate) module_ctxs: Vec<wasi_common::WasiCtx>,
+
     pub(crate) preview2_ctx: Option<Arc<Mutex<WasiP1Ctx>>>,
 
     pub(crate) wasi_threads: Option<Arc<WasiThreadsCtx<BlocklessContext>>>,
@@ -18,6 +22,9 @@ pub(crate) struct BlocklessContext {
     pub(crate) wasi_nn_witx: Option<Arc<wasmtime_wasi_nn::witx::WasiNnCtx>>,
 
     pub(crate) store_limits: StoreLimits,
+
+    // the CAR files mounted for the components, removed when the context is dropped.
+    pub(crate) car_mounts: Vec<Arc<ExtractedCar>>,
 }
 
 impl Default for BlocklessContext {
@@ -26,9 +33,11 @@ impl Default for BlocklessContext {
             wasi_nn_wit: None,
             wasi_nn_witx: None,
             preview1_ctx: None,
+            module_ctxs: Vec::new(),
             preview2_ctx: None,
             wasi_threads: None,
             store_limits: Default::default(),
+            car_mounts: Vec::new(),
         }
     }
 }
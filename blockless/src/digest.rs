@@ -0,0 +1,229 @@
+//! The checksums of the modules.
+//!
+//! The `digest` of the module is multihash-style, the algorithm and the hex encoded
+//! checksum joined with `:`, e.g. `sha256:9f86d081...` or `blake3:4878ca04...`.
+//! The legacy `md5` key is still verified when the module has no `digest`.
+use std::{fmt, str::FromStr};
+
+use sha2::{Digest as _, Sha256};
+use wasi_common::BlocklessModule;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Md5,
+    Sha256,
+    Blake3,
+}
+
+impl DigestAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "md5",
+            DigestAlgorithm::Sha256 => "sha256",
+            DigestAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// the length of the checksum in bytes.
+    fn len(&self) -> usize {
+        match self {
+            DigestAlgorithm::Md5 => 16,
+            DigestAlgorithm::Sha256 | DigestAlgorithm::Blake3 => 32,
+        }
+    }
+
+    fn compute(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            DigestAlgorithm::Md5 => md5::compute(data).0.to_vec(),
+            DigestAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            DigestAlgorithm::Blake3 => blake3::hash(data).as_bytes().to_vec(),
+        }
+    }
+}
+
+impl FromStr for DigestAlgorithm {
+    type Err = DigestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "md5" => Ok(DigestAlgorithm::Md5),
+            "sha256" | "sha2-256" => Ok(DigestAlgorithm::Sha256),
+            "blake3" => Ok(DigestAlgorithm::Blake3),
+            _ => Err(DigestError::Malformed(format!(
+                "unknown digest algorithm `{s}`, expected one of sha256, blake3, md5"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DigestError {
+    /// the digest is not well formed.
+    Malformed(String),
+    /// the checksum of the content does not match the digest.
+    Mismatch { expected: String, actual: String },
+}
+
+impl std::error::Error for DigestError {}
+
+impl fmt::Display for DigestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DigestError::Malformed(msg) => write!(f, "malformed digest: {msg}"),
+            DigestError::Mismatch { expected, actual } => {
+                write!(
+                    f,
+                    "the checksum mismatch, expected {expected}, got {actual}"
+                )
+            }
+        }
+    }
+}
+
+/// The checksum of the module, the algorithm and the checksum bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModuleDigest {
+    algorithm: DigestAlgorithm,
+    checksum: Vec<u8>,
+}
+
+impl ModuleDigest {
+    /// compute the digest of the content with the algorithm.
+    pub fn compute(algorithm: DigestAlgorithm, data: &[u8]) -> Self {
+        Self {
+            algorithm,
+            checksum: algorithm.compute(data),
+        }
+    }
+
+    /// the legacy md5 checksum, the hex encoded checksum without the algorithm.
+    pub fn from_md5(md5: &str) -> Result<Self, DigestError> {
+        Self::new(DigestAlgorithm::Md5, md5)
+    }
+
+    fn new(algorithm: DigestAlgorithm, checksum: &str) -> Result<Self, DigestError> {
+        let checksum = hex::decode(checksum.trim())
+            .ok()
+            .filter(|c| c.len() == algorithm.len())
+            .ok_or_else(|| {
+                DigestError::Malformed(format!(
+                    "the {} checksum should be {} hex encoded bytes",
+                    algorithm.name(),
+                    algorithm.len()
+                ))
+            })?;
+        Ok(Self {
+            algorithm,
+            checksum,
+        })
+    }
+
+    pub fn algorithm(&self) -> DigestAlgorithm {
+        self.algorithm
+    }
+
+    /// verify the checksum of the content.
+    pub fn verify(&self, data: &[u8]) -> Result<(), DigestError> {
+        let actual = Self::compute(self.algorithm, data);
+        if actual != *self {
+            return Err(DigestError::Mismatch {
+                expected: self.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ModuleDigest {
+    type Err = DigestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((algorithm, checksum)) => Self::new(algorithm.parse()?, checksum),
+            None => Err(DigestError::Malformed(format!(
+                "`{s}` should be of the form `ALGORITHM:HEX`"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ModuleDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.algorithm.name(),
+            hex::encode(&self.checksum)
+        )
+    }
+}
+
+/// the digest of the module, the `digest` key takes precedence over the legacy `md5` key,
+/// `None` if the module has neither.
+pub fn module_digest(module: &BlocklessModule) -> Result<Option<ModuleDigest>, DigestError> {
+    if !module.digest.is_empty() {
+        module.digest.parse().map(Some)
+    } else if !module.md5.is_empty() {
+        ModuleDigest::from_md5(&module.md5).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// verify the content of the module with its digest, the module without digest is skipped.
+pub fn verify_module(module: &BlocklessModule, data: &[u8]) -> Result<(), DigestError> {
+    match module_digest(module)? {
+        Some(digest) => digest.verify(data),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wasi_common::ModuleType;
+
+    #[test]
+    fn test_module_digest() {
+        let digest: ModuleDigest =
+            "sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+                .parse()
+                .unwrap();
+        assert_eq!(digest.algorithm(), DigestAlgorithm::Sha256);
+        assert!(digest.verify(b"test").is_ok());
+        assert!(matches!(
+            digest.verify(b"tset"),
+            Err(DigestError::Mismatch { .. })
+        ));
+
+        let digest = ModuleDigest::compute(DigestAlgorithm::Blake3, b"test");
+        assert_eq!(digest.to_string().parse::<ModuleDigest>().unwrap(), digest);
+
+        assert!("sha256".parse::<ModuleDigest>().is_err());
+        assert!("sha1:00".parse::<ModuleDigest>().is_err());
+        assert!("sha256:00".parse::<ModuleDigest>().is_err());
+    }
+
+    #[test]
+    fn test_verify_module() {
+        let mut module = BlocklessModule {
+            module_type: ModuleType::Entry,
+            name: String::new(),
+            file: "main.wasm".into(),
+            md5: String::new(),
+            digest: String::new(),
+            permissions: None,
+        };
+        assert!(verify_module(&module, b"test").is_ok());
+
+        module.md5 = format!("{:x}", md5::compute(b"test"));
+        assert!(verify_module(&module, b"test").is_ok());
+        assert!(verify_module(&module, b"tset").is_err());
+
+        // the digest takes precedence over the md5.
+        module.digest = ModuleDigest::compute(DigestAlgorithm::Sha256, b"tset").to_string();
+        assert!(verify_module(&module, b"tset").is_ok());
+        assert!(verify_module(&module, b"test").is_err());
+    }
+}
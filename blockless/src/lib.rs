@@ -1,10 +1,13 @@
+pub mod car;
 mod context;
+pub mod digest;
 pub mod error;
 mod modules;
+pub mod signature;
 
 pub use anyhow::Result as AnyResult;
 use anyhow::{bail, Context};
-use blockless_drivers::{CdylibDriver, DriverConetxt};
+use blockless_drivers::{http_driver, CdylibDriver, DriverConetxt};
 use blockless_env;
 pub use blockless_multiaddr::MultiAddr;
 use cap_std::ambient_authority;
@@ -12,6 +15,8 @@ use context::BlocklessContext;
 pub use error::*;
 use log::{debug, error};
 use modules::ModuleLinker;
+use signature::SignaturePolicy;
+use std::collections::HashMap;
 use std::fs::File;
 use std::sync::Mutex;
 use std::{env, path::Path, sync::Arc};
@@ -33,6 +38,14 @@ const ENTRY: &str = "_start";
 pub struct ExitStatus {
     pub fuel: Option<u64>,
     pub code: i32,
+    // the root cid of the CAR file packed from the `output_dir`.
+    pub output_cid: Option<String>,
+    // the `output_dir` can't be packed, the exit code is still the app's.
+    pub output_error: Option<car::OutputError>,
+    // the accesses denied to the app.
+    pub denied: Vec<DeniedAccess>,
+    // the usage of the host resources counted against the quotas.
+    pub usage: ResourceUsage,
 }
 
 pub enum BlsRunTarget {
@@ -143,8 +156,15 @@ impl BlocklessConfig2Preview1WasiBuilder for BlocklessConfig {
     /// create the preview1_builder by the configure.
     fn preview1_builder(&self) -> anyhow::Result<WasiCtxBuilder> {
         let b_conf = self;
-        let root_dir = b_conf.fs_root_path_ref().and_then(|path| {
-            wasi_common::sync::Dir::open_ambient_dir(path, ambient_authority()).ok()
+        let package = b_conf.package_ref();
+        // the root in the package is served from the memory.
+        let root_dir: Option<Box<dyn WasiDir>> = b_conf.fs_root_path_ref().and_then(|path| {
+            match package.and_then(|p| p.open_dir(path)) {
+                Some(dir) => dir.ok(),
+                None => wasi_common::sync::Dir::open_ambient_dir(path, ambient_authority())
+                    .ok()
+                    .map(|d| Box::new(wasi_common::sync::dir::Dir::from_cap_std(d)) as _),
+            }
         });
         let mut builder = WasiCtxBuilder::new();
         //stdout file process for setting.
@@ -156,17 +176,32 @@ impl BlocklessConfig2Preview1WasiBuilder for BlocklessConfig {
         let mut args = vec![entry_module];
         args.extend_from_slice(&b_conf.stdin_args_ref()[..]);
         builder.args(&args[..])?;
-        builder.envs(&b_conf.envs_ref()[..])?;
+        builder.envs(&b_conf.guest_envs()[..])?;
         let mut max_fd = 3;
-        // map host to guest dir in runtime.
+        // map host to guest dir in runtime, the dirs in the package are served from the memory.
         for (host, guest) in b_conf.dirs.iter() {
-            let host = Dir::open_ambient_dir(host, ambient_authority())?;
-            builder.preopened_dir(host, guest)?;
+            match package.and_then(|p| p.open_dir(host)) {
+                Some(dir) => {
+                    let dir =
+                        dir.with_context(|| format!("failed to open {host} in the package"))?;
+                    builder.preopened_wasi_dir(dir, guest)?;
+                }
+                None => {
+                    let host = Dir::open_ambient_dir(host, ambient_authority())?;
+                    builder.preopened_dir(host, guest)?;
+                }
+            }
+            max_fd += 1;
+        }
+        // the CAR files are mounted read-only, the files are read from the blocks on demand.
+        for (car, guest) in b_conf.car_mounts.iter() {
+            let dir = car::mount_car_dir(b_conf, car)?;
+            builder.preopened_wasi_dir(Box::new(ReadOnlyDir::new(Arc::new(dir))), guest)?;
             max_fd += 1;
         }
         // map root fs
         if let Some(d) = root_dir {
-            builder.preopened_dir(d, "/")?;
+            builder.preopened_wasi_dir(d, "/")?;
             max_fd += 1;
         }
         //set the tcp listener.
@@ -295,6 +330,9 @@ impl BlocklessConfig2Preview1WasiBuilder for BlocklessConfig {
     }
 
     fn preview2_builder(&self) -> anyhow::Result<wasmtime_wasi::WasiCtxBuilder> {
+        if self.package_ref().is_some() {
+            bail!("the package served from the memory is not supported by the components.");
+        }
         let mut builder = wasmtime_wasi::WasiCtxBuilder::new();
         for (host_path, guest_path) in self.dirs.iter() {
             builder.preopened_dir(host_path, guest_path, DirPerms::all(), FilePerms::all())?;
@@ -345,6 +383,8 @@ impl BlocklessRunner {
                 String::from(current_exe_path.to_str().unwrap())
             });
         DriverConetxt::init_built_in_drivers(drivers_root_path);
+        http_driver::init_http_client(&b_conf.http_client)
+            .context("failed to build the http client")?;
         let conf = b_conf.preview1_engine_config();
         let engine = Engine::new(&conf)?;
         let support_thread = b_conf.feature_thread();
@@ -352,6 +392,7 @@ impl BlocklessRunner {
         let drivers = b_conf.drivers_ref();
         Self::load_driver(drivers);
         let entry: String = b_conf.entry_ref().into();
+        let verified = Self::verify_modules(b_conf)?;
         let store_limits = b_conf.store_limits();
         let fule = b_conf.get_limited_fuel();
 
@@ -364,8 +405,9 @@ impl BlocklessRunner {
         if let Some(f) = fule {
             store.set_fuel(f).unwrap();
         }
-        let (mut linker, mut run_target, entry) =
-            self.module_linker(entry, &engine, &mut store).await?;
+        let (mut linker, mut run_target, entry) = self
+            .module_linker(entry, &engine, &mut store, verified)
+            .await?;
         let mut is_component = false;
         if b_conf.nn {
             self.nn_setup(&mut linker, &mut store)?;
@@ -373,7 +415,7 @@ impl BlocklessRunner {
         // prepare linker.
         match linker {
             BlsLinker::Core(ref mut linker) => {
-                Self::preview1_linker_setup(linker);
+                Self::preview1_linker_setup(linker, |s| s.preview1_ctx.as_mut().unwrap());
                 //preview1 setup the permissions with options
                 store.data_mut().set_permisions(&b_conf.permissions_config);
             }
@@ -403,12 +445,74 @@ impl BlocklessRunner {
                 0
             }
         };
+        // the files written by the app are packed after it exits.
+        let (output_cid, output_error) = match b_conf.output_dir.as_ref() {
+            Some(dir) => match car::pack_output(b_conf, dir) {
+                Ok(cid) => (Some(cid), None),
+                Err(e) => (None, Some(e)),
+            },
+            None => (None, None),
+        };
+        let denied = store
+            .data()
+            .preview1_ctx
+            .as_ref()
+            .map(|ctx| ctx.denied_accesses())
+            .unwrap_or_default();
         Ok(ExitStatus {
             fuel: store.get_fuel().ok(),
             code: exit_code,
+            output_cid,
+            output_error,
+            denied,
+            usage: b_conf.resource_usage.usage(),
         })
     }
 
+    /// verify the checksums of the modules and the signatures with the signature policy
+    /// of the configure, the verified contents are returned by the file to be compiled,
+    /// so the file changed after the verification is never run.
+    fn verify_modules(cfg: &BlocklessConfig) -> AnyResult<HashMap<String, Vec<u8>>> {
+        let policy = SignaturePolicy::from_config(cfg)?;
+        let files: Vec<(&str, Option<&BlocklessModule>)> = match cfg.version() {
+            BlocklessConfigVersion::Version0 => vec![(cfg.entry_ref(), None)],
+            BlocklessConfigVersion::Version1 => cfg
+                .modules
+                .iter()
+                .map(|m| (m.file.as_str(), Some(m)))
+                .collect(),
+        };
+        let mut verified = HashMap::new();
+        for (file, module) in files {
+            // the module read from stdin is unsigned.
+            let wasm = match file {
+                "-" => Vec::new(),
+                _ => Self::read_module_file(cfg, file)?,
+            };
+            if let Some(module) = module {
+                digest::verify_module(module, &wasm).with_context(|| {
+                    format!("failed to verify the checksum of the module {file}")
+                })?;
+            }
+            policy
+                .check_wasm(&wasm)
+                .with_context(|| format!("failed to verify the module {file}"))?;
+            if file != "-" {
+                verified.insert(file.to_string(), wasm);
+            }
+        }
+        Ok(verified)
+    }
+
+    /// read the module file, the file in the package is read from the memory.
+    fn read_module_file(cfg: &BlocklessConfig, file: &str) -> AnyResult<Vec<u8>> {
+        let data = match cfg.package_ref().and_then(|p| p.read_file(file)) {
+            Some(data) => data,
+            None => std::fs::read(file),
+        };
+        data.with_context(|| format!("failed to read file: {file}"))
+    }
+
     fn preview1_setup(&self, ctx: &mut BlocklessContext) -> AnyResult<()> {
         let mut builder = self.0.preview1_builder()?;
         let mut preview1_ctx = builder.build();
@@ -419,8 +523,15 @@ impl BlocklessRunner {
 
     fn preview2_setup(&self, ctx: &mut BlocklessContext) -> AnyResult<()> {
         let mut builder = self.0.preview2_builder()?;
+        // the components only preopen the host directories, the CAR files are extracted
+        // into the read-only temporary directories removed after the run.
+        for (car, guest) in self.0.car_mounts.iter() {
+            let extracted = car::ExtractedCar::new(&self.0, car)?;
+            builder.preopened_dir(extracted.path(), guest, DirPerms::READ, FilePerms::READ)?;
+            ctx.car_mounts.push(Arc::new(extracted));
+        }
         builder.inherit_stdio().args(&self.0.stdin_args);
-        builder.envs(&self.0.envs);
+        builder.envs(&self.0.guest_envs());
         let preview2_ctx = builder.build_p1();
         ctx.preview2_ctx = Some(Arc::new(Mutex::new(preview2_ctx)));
         Ok(())
@@ -609,17 +720,26 @@ impl BlocklessRunner {
             Err(_) => {
                 let bytes = std::fs::read(path)
                     .with_context(|| format!("failed to read file: {}", path.display()))?;
-                Self::load_module_contents(
-                    engine,
-                    path,
-                    &bytes,
-                    || unsafe { Module::deserialize(engine, &bytes) },
-                    || unsafe { Component::deserialize(engine, &bytes) },
-                )
+                Self::load_module_bytes(engine, path, &bytes)
             }
         }
     }
 
+    /// load the module from the memory, e.g. the module in the package.
+    pub fn load_module_bytes(
+        engine: &Engine,
+        path: &Path,
+        bytes: &[u8],
+    ) -> AnyResult<BlsRunTarget> {
+        Self::load_module_contents(
+            engine,
+            path,
+            bytes,
+            || unsafe { Module::deserialize(engine, bytes) },
+            || unsafe { Component::deserialize(engine, bytes) },
+        )
+    }
+
     pub fn load_module_contents(
         engine: &Engine,
         path: &Path,
@@ -645,11 +765,15 @@ impl BlocklessRunner {
         })
     }
 
-    fn preview1_linker_setup(linker: &mut Linker<BlocklessContext>) {
+    /// add the host functions to the linker, the functions are run with the context of `get_cx`.
+    fn preview1_linker_setup<F>(linker: &mut Linker<BlocklessContext>, get_cx: F)
+    where
+        F: Fn(&mut BlocklessContext) -> &mut wasi_common::WasiCtx + Send + Sync + Copy + 'static,
+    {
         // define the macro of extends.
         macro_rules! add_to_linker {
             ($method:expr) => {
-                $method(linker, |s| s.preview1_ctx.as_mut().unwrap()).unwrap()
+                $method(linker, get_cx).unwrap()
             };
         }
         add_to_linker!(blockless_env::add_drivers_to_linker);
@@ -659,8 +783,7 @@ impl BlocklessRunner {
         add_to_linker!(blockless_env::add_memory_to_linker);
         add_to_linker!(blockless_env::add_cgi_to_linker);
         add_to_linker!(blockless_env::add_socket_to_linker);
-        wasi_common::sync::add_to_linker(linker, |host| host.preview1_ctx.as_mut().unwrap())
-            .unwrap();
+        wasi_common::sync::add_to_linker(linker, get_cx).unwrap();
     }
 
     fn preview1_setup_thread_support(
@@ -683,12 +806,17 @@ impl BlocklessRunner {
         mut entry: String,
         engine: &Engine,
         store: &'a mut Store<BlocklessContext>,
+        verified: HashMap<String, Vec<u8>>,
     ) -> anyhow::Result<(BlsLinker, BlsRunTarget, String)> {
         let version = self.0.version();
         match version {
             // this is older configure for bls-runtime, this only run single wasm.
             BlocklessConfigVersion::Version0 => {
-                let module = Self::load_module(engine, &entry)?;
+                // the module is compiled from the verified contents, only stdin is read here.
+                let module = match verified.get(&entry) {
+                    Some(bytes) => Self::load_module_bytes(engine, entry.as_ref(), bytes)?,
+                    None => Self::load_module(engine, &entry)?,
+                };
                 let linker = match module {
                     BlsRunTarget::Module(_) => {
                         self.preview1_setup(store.data_mut())?;
@@ -707,7 +835,7 @@ impl BlocklessRunner {
                 // must setup before link_modules.
                 self.preview1_setup(store.data_mut())?;
                 let mut linker = wasmtime::Linker::new(engine);
-                let mut module_linker = ModuleLinker::new(&mut linker, store);
+                let mut module_linker = ModuleLinker::new(&mut linker, store, verified);
                 let module = module_linker.link_modules().await.context("")?;
                 Ok((BlsLinker::Core(linker), BlsRunTarget::Module(module), entry))
             }
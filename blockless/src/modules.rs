@@ -5,7 +5,7 @@ use std::future::Future;
 use std::sync::Arc;
 use std::{cmp::min, collections::HashMap};
 use tokio::sync::Mutex;
-use wasi_common::{BlocklessModule, ModuleType};
+use wasi_common::{BlocklessConfig, BlocklessModule, ModuleType};
 use wasmtime::{
     AsContext, AsContextMut, Caller, Extern, Func, Linker, Memory, Module, Store, StoreContextMut,
     TypedFunc,
@@ -13,6 +13,7 @@ use wasmtime::{
 
 use crate::context::BlocklessContext as BSContext;
 use crate::error::McallError;
+use crate::{BlocklessConfig2Preview1WasiBuilder, BlocklessRunner};
 
 lazy_static! {
     static ref INS_CTX: Mutex<InstanceCtx> = Mutex::new(InstanceCtx::new());
@@ -256,11 +257,21 @@ impl<'a> ResponseErrorJson<'a> {
 pub(crate) struct ModuleLinker<'a> {
     linker: &'a mut Linker<BSContext>,
     store: &'a mut Store<BSContext>,
+    // the verified contents of the module files.
+    verified: HashMap<String, Vec<u8>>,
 }
 
 impl<'a> ModuleLinker<'a> {
-    pub(crate) fn new(linker: &'a mut Linker<BSContext>, store: &'a mut Store<BSContext>) -> Self {
-        Self { linker, store }
+    pub(crate) fn new(
+        linker: &'a mut Linker<BSContext>,
+        store: &'a mut Store<BSContext>,
+        verified: HashMap<String, Vec<u8>>,
+    ) -> Self {
+        Self {
+            linker,
+            store,
+            verified,
+        }
     }
 
     fn parse_mcall(param: &str) -> anyhow::Result<(String, String)> {
@@ -393,7 +404,7 @@ impl<'a> ModuleLinker<'a> {
     /// export the ```blockless.mcall``` and ```blockless.register``` in the runtime.
     /// The modules can be use the register to register the moudle's function for mcall.
     pub(crate) async fn link_modules(&mut self) -> anyhow::Result<Module> {
-        let mut modules: Vec<BlocklessModule> = {
+        let cfg: BlocklessConfig = {
             let preview1 = self
                 .store
                 .data()
@@ -401,9 +412,10 @@ impl<'a> ModuleLinker<'a> {
                 .as_ref()
                 .ok_or(anyhow!("get preview1_ctx fail"))?;
             let lock = preview1.blockless_config.lock().unwrap();
-            let cfg = lock.as_ref().ok_or(anyhow!("get the lock fail"))?;
-            cfg.modules_ref().iter().map(|m| (*m).clone()).collect()
+            lock.as_ref().ok_or(anyhow!("get the lock fail"))?.clone()
         };
+        let mut modules: Vec<BlocklessModule> =
+            cfg.modules_ref().iter().map(|m| (*m).clone()).collect();
         modules.sort_by(|a, b| a.module_type.partial_cmp(&b.module_type).unwrap());
         let mut entry = None;
         self.linker.func_wrap_async(
@@ -427,20 +439,61 @@ impl<'a> ModuleLinker<'a> {
                 ModuleType::Module => (m.name.as_str(), false),
                 ModuleType::Entry => ("", true),
             };
-            let module = Module::from_file(self.store.engine(), &m.file)?;
+            // the module is compiled from the contents verified before the run.
+            let bytes = self
+                .verified
+                .get(&m.file)
+                .ok_or_else(|| anyhow!("the module {} is not verified", m.file))?;
+            let module = Module::new(self.store.engine(), bytes)?;
             if is_entry {
                 entry = Some(module);
             } else {
-                self.instance_module(m_name, &module).await?;
+                let mut linker = self.module_host_linker(&cfg, &m)?;
+                self.instance_module(&mut linker, m_name, &module).await?;
             }
         }
         entry.ok_or(anyhow!("can't find the entry"))
     }
 
+    /// the linker of the library module with the host functions, the module with its own
+    /// permissions is run with its own context granted only its own permissions, so it's not
+    /// granted what the entry module is. The module without permissions shares the app's context.
+    fn module_host_linker(
+        &mut self,
+        cfg: &BlocklessConfig,
+        m: &BlocklessModule,
+    ) -> anyhow::Result<Linker<BSContext>> {
+        let mut linker = self.linker.clone();
+        let m_cfg = match cfg.module_config(m) {
+            Some(m_cfg) => m_cfg,
+            None => {
+                BlocklessRunner::preview1_linker_setup(&mut linker, |s| {
+                    s.preview1_ctx.as_mut().unwrap()
+                });
+                return Ok(linker);
+            }
+        };
+        let mut ctx = m_cfg.preview1_builder()?.build();
+        ctx.set_blockless_config(Some(m_cfg.clone()));
+        ctx.set_permissions_config(&m_cfg.permissions_config)?;
+        let data = self.store.data_mut();
+        if let Some(app_ctx) = data.preview1_ctx.as_ref() {
+            ctx.forward_audit_to(app_ctx);
+        }
+        let idx = data.module_ctxs.len();
+        data.module_ctxs.push(ctx);
+        BlocklessRunner::preview1_linker_setup(&mut linker, move |s| &mut s.module_ctxs[idx]);
+        Ok(linker)
+    }
+
     ///instance module and inital the context.
-    async fn instance_module(&mut self, m_name: &str, module: &Module) -> anyhow::Result<()> {
-        let instance = self
-            .linker
+    async fn instance_module(
+        &mut self,
+        linker: &mut Linker<BSContext>,
+        m_name: &str,
+        module: &Module,
+    ) -> anyhow::Result<()> {
+        let instance = linker
             .instantiate_async(self.store.as_context_mut(), module)
             .await?;
         let mut initial = None;
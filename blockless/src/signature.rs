@@ -0,0 +1,301 @@
+//! The ed25519 signatures of the packages and the modules.
+//!
+//! A signature is the public key of the publisher followed by the ed25519 signature,
+//! 96 bytes in all. The signature of a CAR file is detached in `<car file>.sig` and
+//! signs the whole CAR file. The signature of a wasm module is the last custom section
+//! named `bls.signature` and signs the module bytes before the section.
+use std::{fmt, fs, path::Path};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use wasi_common::BlocklessConfig;
+
+/// The name of the wasm custom section of the signature.
+pub const SIGNATURE_SECTION: &str = "bls.signature";
+
+/// The length of the signature, the public key and the ed25519 signature.
+pub const SIGNATURE_LEN: usize = 32 + 64;
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+#[derive(Debug, PartialEq)]
+pub enum SignatureError {
+    /// the signature is required but the package or the module is not signed.
+    Unsigned,
+    /// the signature is good, but the key is not trusted.
+    UntrustedKey(String),
+    /// the content is tampered or the signature is broken.
+    Invalid,
+    /// the key or the signature is not well formed.
+    Malformed(String),
+}
+
+impl std::error::Error for SignatureError {}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Unsigned => write!(f, "the signature is required, but not signed"),
+            SignatureError::UntrustedKey(key) => write!(f, "the signing key {key} is not trusted"),
+            SignatureError::Invalid => write!(f, "the signature does not match the content"),
+            SignatureError::Malformed(msg) => write!(f, "malformed signature: {msg}"),
+        }
+    }
+}
+
+/// parse the hex encoded ed25519 public key.
+pub fn parse_public_key(key: &str) -> Result<VerifyingKey, SignatureError> {
+    let bytes: [u8; 32] = hex::decode(key.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| SignatureError::Malformed(format!("invalid public key `{key}`")))?;
+    VerifyingKey::from_bytes(&bytes)
+        .map_err(|_| SignatureError::Malformed(format!("invalid public key `{key}`")))
+}
+
+/// parse the hex encoded ed25519 secret key.
+pub fn parse_signing_key(key: &str) -> Result<SigningKey, SignatureError> {
+    let bytes: [u8; 32] = hex::decode(key.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| SignatureError::Malformed("invalid secret key".into()))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// generate a new signing key.
+pub fn generate_signing_key() -> SigningKey {
+    SigningKey::generate(&mut rand_core::OsRng)
+}
+
+/// The signature policy, the signed content must be signed by one of the trusted keys.
+#[derive(Debug, Default, Clone)]
+pub struct SignaturePolicy {
+    trusted_keys: Vec<VerifyingKey>,
+    require: bool,
+}
+
+impl SignaturePolicy {
+    pub fn new(trusted_keys: Vec<VerifyingKey>, require: bool) -> Self {
+        Self {
+            trusted_keys,
+            require,
+        }
+    }
+
+    /// the policy of the `trusted_keys` and `require_signature` of the configure.
+    pub fn from_config(cfg: &BlocklessConfig) -> Result<Self, SignatureError> {
+        let trusted_keys = cfg
+            .trusted_keys
+            .iter()
+            .map(|k| parse_public_key(k))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(trusted_keys, cfg.require_signature))
+    }
+
+    /// check the signature of the message:
+    /// 1. the unsigned message is refused when the signature is required.
+    /// 2. the tampered message is always refused.
+    /// 3. the key must be trusted when the signature is required or any key is trusted.
+    pub fn check(&self, signature: Option<&[u8]>, message: &[u8]) -> Result<(), SignatureError> {
+        let signature = match signature {
+            Some(signature) => signature,
+            None if self.require => return Err(SignatureError::Unsigned),
+            None => return Ok(()),
+        };
+        let key = verify(signature, message)?;
+        let trusted = self.trusted_keys.iter().any(|k| *k == key);
+        if !trusted && self.is_enforced() {
+            return Err(SignatureError::UntrustedKey(hex::encode(key.as_bytes())));
+        }
+        Ok(())
+    }
+
+    /// check the signature of the wasm module, the module without signature section is unsigned.
+    pub fn check_wasm(&self, wasm: &[u8]) -> Result<(), SignatureError> {
+        match find_signature_section(wasm)? {
+            Some((offset, signature)) => self.check(Some(signature), &wasm[..offset]),
+            None => self.check(None, wasm),
+        }
+    }
+
+    /// whether the signatures are checked against the trusted keys or required.
+    pub fn is_enforced(&self) -> bool {
+        self.require || !self.trusted_keys.is_empty()
+    }
+
+    /// check the car file with the detached signature file `<car file>.sig`, the checked
+    /// content is returned, the caller must load the package from it instead of the file.
+    pub fn check_car(&self, car_file: impl AsRef<Path>) -> Result<Vec<u8>, SignatureError> {
+        let car_file = car_file.as_ref();
+        let content = fs::read(car_file).map_err(|e| SignatureError::Malformed(e.to_string()))?;
+        let signature = fs::read(car_signature_path(car_file)).ok();
+        self.check(signature.as_deref(), &content)?;
+        Ok(content)
+    }
+}
+
+/// the path of the detached signature of the car file.
+pub fn car_signature_path(car_file: impl AsRef<Path>) -> std::path::PathBuf {
+    let mut path = car_file.as_ref().as_os_str().to_os_string();
+    path.push(".sig");
+    path.into()
+}
+
+/// sign the message, the public key and the signature are returned.
+pub fn sign(key: &SigningKey, message: &[u8]) -> Vec<u8> {
+    let mut signature = key.verifying_key().as_bytes().to_vec();
+    signature.extend_from_slice(&key.sign(message).to_bytes());
+    signature
+}
+
+/// verify the signature, the public key of the signature is returned.
+pub fn verify(signature: &[u8], message: &[u8]) -> Result<VerifyingKey, SignatureError> {
+    if signature.len() != SIGNATURE_LEN {
+        let msg = format!("the length should be {SIGNATURE_LEN}");
+        return Err(SignatureError::Malformed(msg));
+    }
+    let key_bytes: [u8; 32] = signature[..32].try_into().unwrap();
+    let key = VerifyingKey::from_bytes(&key_bytes)
+        .map_err(|_| SignatureError::Malformed("invalid public key".into()))?;
+    let sig = Signature::from_slice(&signature[32..])
+        .map_err(|_| SignatureError::Malformed("invalid signature".into()))?;
+    key.verify(message, &sig)
+        .map_err(|_| SignatureError::Invalid)?;
+    Ok(key)
+}
+
+fn read_leb_u32(bytes: &[u8], pos: &mut usize) -> Option<u32> {
+    let mut result = 0u32;
+    for i in 0..5 {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        result |= u32::from(byte & 0x7f) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some(result);
+        }
+    }
+    None
+}
+
+fn write_leb_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// find the signature section of the wasm module, the offset of the section and the
+/// signature are returned. The signature section must be the last section.
+/// The non-binary module, e.g. the wat text or the precompiled module, is unsigned.
+pub fn find_signature_section(wasm: &[u8]) -> Result<Option<(usize, &[u8])>, SignatureError> {
+    if wasm.len() < 8 || &wasm[..4] != WASM_MAGIC {
+        return Ok(None);
+    }
+    let malformed = || SignatureError::Malformed("invalid wasm module".into());
+    let mut pos = 8;
+    let mut found = None;
+    while pos < wasm.len() {
+        let offset = pos;
+        let id = wasm[pos];
+        pos += 1;
+        let size = read_leb_u32(wasm, &mut pos).ok_or_else(malformed)? as usize;
+        let end = pos.checked_add(size).filter(|end| *end <= wasm.len());
+        let end = end.ok_or_else(malformed)?;
+        found = None;
+        if id == 0 {
+            let mut name_pos = pos;
+            let name_len = read_leb_u32(wasm, &mut name_pos).ok_or_else(malformed)? as usize;
+            let name_end = name_pos + name_len;
+            if name_end <= end && &wasm[name_pos..name_end] == SIGNATURE_SECTION.as_bytes() {
+                found = Some((offset, &wasm[name_end..end]));
+            }
+        }
+        pos = end;
+    }
+    Ok(found)
+}
+
+/// sign the wasm module, the old signature section is replaced.
+pub fn sign_wasm(key: &SigningKey, wasm: &[u8]) -> Result<Vec<u8>, SignatureError> {
+    if wasm.len() < 8 || &wasm[..4] != WASM_MAGIC {
+        return Err(SignatureError::Malformed(
+            "only the wasm binary can be signed".into(),
+        ));
+    }
+    let content = match find_signature_section(wasm)? {
+        Some((offset, _)) => &wasm[..offset],
+        None => wasm,
+    };
+    let signature = sign(key, content);
+    let mut section = Vec::new();
+    write_leb_u32(&mut section, SIGNATURE_SECTION.len() as u32);
+    section.extend_from_slice(SIGNATURE_SECTION.as_bytes());
+    section.extend_from_slice(&signature);
+    let mut signed = content.to_vec();
+    signed.push(0);
+    write_leb_u32(&mut signed, section.len() as u32);
+    signed.extend_from_slice(&section);
+    Ok(signed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // the empty wasm module with a custom section named "a".
+    const WASM: &[u8] = b"\0asm\x01\0\0\0\0\x02\x01a";
+
+    #[test]
+    fn test_sign_wasm() {
+        let key = generate_signing_key();
+        let signed = sign_wasm(&key, WASM).unwrap();
+        let (offset, signature) = find_signature_section(&signed).unwrap().unwrap();
+        assert_eq!(offset, WASM.len());
+        assert_eq!(verify(signature, WASM).unwrap(), key.verifying_key());
+        // sign again replaces the signature.
+        assert_eq!(sign_wasm(&key, &signed).unwrap(), signed);
+        assert_eq!(find_signature_section(WASM).unwrap(), None);
+        assert_eq!(find_signature_section(b"(module)").unwrap(), None);
+    }
+
+    #[test]
+    fn test_signature_policy() {
+        let key = generate_signing_key();
+        let other = generate_signing_key();
+        let signed = sign_wasm(&key, WASM).unwrap();
+        let mut tampered = signed.clone();
+        tampered[WASM.len() - 1] = b'b';
+
+        let policy = SignaturePolicy::default();
+        assert!(policy.check_wasm(WASM).is_ok());
+        assert!(policy.check_wasm(&signed).is_ok());
+        assert_eq!(policy.check_wasm(&tampered), Err(SignatureError::Invalid));
+
+        let policy = SignaturePolicy::new(vec![key.verifying_key()], true);
+        assert!(policy.check_wasm(&signed).is_ok());
+        assert_eq!(policy.check_wasm(WASM), Err(SignatureError::Unsigned));
+
+        let policy = SignaturePolicy::new(vec![other.verifying_key()], false);
+        assert!(matches!(
+            policy.check_wasm(&signed),
+            Err(SignatureError::UntrustedKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_key() {
+        let key = generate_signing_key();
+        let public_key = hex::encode(key.verifying_key().as_bytes());
+        assert_eq!(parse_public_key(&public_key).unwrap(), key.verifying_key());
+        let secret_key = hex::encode(key.to_bytes());
+        assert_eq!(
+            parse_signing_key(&secret_key).unwrap().to_bytes(),
+            key.to_bytes()
+        );
+        assert!(parse_public_key("00").is_err());
+    }
+}
@@ -3,7 +3,10 @@ use std::fs;
 
 use common::run_blockless;
 use tempdir::TempDir;
-use wasi_common::{BlocklessConfig, BlocklessConfigVersion, BlocklessModule, ModuleType};
+use wasi_common::{
+    BlocklessConfig, BlocklessConfigVersion, BlocklessModule, ModulePermissions, ModuleType,
+    Permission, PrompterKind,
+};
 
 #[test]
 fn test_linker_module() {
@@ -54,6 +57,8 @@ fn test_linker_module() {
         name: "".to_string(),
         file: guest_path.to_str().unwrap().to_string(),
         md5: format!("{:x}", md5::compute(guest_wasm)),
+        digest: String::new(),
+        permissions: None,
     }];
     let mut config = BlocklessConfig::new("_start");
     config.set_version(BlocklessConfigVersion::Version1);
@@ -107,6 +112,8 @@ fn test_blockless_extension_http_req() {
         name: "".to_string(),
         file: guest_path.to_str().unwrap().to_string(),
         md5: format!("{:x}", md5::compute(guest_wasm)),
+        digest: String::new(),
+        permissions: None,
     }];
     let mut config = BlocklessConfig::new("_start");
     config.set_version(BlocklessConfigVersion::Version1);
@@ -150,12 +157,16 @@ fn test_blockless_run_primary_module_can_call_reactor_module() {
             name: "".to_string(),
             file: primary_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(primary_code)),
+            digest: String::new(),
+            permissions: None,
         },
         BlocklessModule {
             module_type: ModuleType::Module,
             name: "reactor1".to_string(),
             file: reactor_1_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(reactor_1_code)),
+            digest: String::new(),
+            permissions: None,
         },
     ];
     let mut config = BlocklessConfig::new("_start");
@@ -217,18 +228,24 @@ fn test_blockless_primary_module_can_call_multiple_reactor_modules() {
             name: "".to_string(),
             file: primary_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(primary_code)),
+            digest: String::new(),
+            permissions: None,
         },
         BlocklessModule {
             module_type: ModuleType::Module,
             name: "reactor1".to_string(),
             file: reactor_1_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(reactor_1_code)),
+            digest: String::new(),
+            permissions: None,
         },
         BlocklessModule {
             module_type: ModuleType::Module,
             name: "reactor2".to_string(),
             file: reactor_2_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(reactor_2_code)),
+            digest: String::new(),
+            permissions: None,
         },
     ];
     let mut config = BlocklessConfig::new("_start");
@@ -285,6 +302,8 @@ fn test_blockless_reactor_module_can_call_reactor_module() {
             name: "".to_string(),
             file: primary_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(primary_code)),
+            digest: String::new(),
+            permissions: None,
         },
         // ensure we load/link reactor2 before reactor1 since reactor1 depends on it
         BlocklessModule {
@@ -292,12 +311,16 @@ fn test_blockless_reactor_module_can_call_reactor_module() {
             name: "reactor2".to_string(),
             file: reactor_2_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(reactor_2_code)),
+            digest: String::new(),
+            permissions: None,
         },
         BlocklessModule {
             module_type: ModuleType::Module,
             name: "reactor1".to_string(),
             file: reactor_1_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(reactor_1_code)),
+            digest: String::new(),
+            permissions: None,
         },
     ];
     let mut config = BlocklessConfig::new("_start");
@@ -360,18 +383,24 @@ fn test_blockless_reactor_module_can_call_reactor_module_with_callback_support()
             name: "".to_string(),
             file: primary_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(primary_code)),
+            digest: String::new(),
+            permissions: None,
         },
         BlocklessModule {
             module_type: ModuleType::Module,
             name: "reactor1".to_string(),
             file: reactor_1_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(reactor_1_code)),
+            digest: String::new(),
+            permissions: None,
         },
         BlocklessModule {
             module_type: ModuleType::Module,
             name: "reactor2".to_string(),
             file: reactor_2_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(reactor_2_code)),
+            digest: String::new(),
+            permissions: None,
         },
     ];
     let mut config = BlocklessConfig::new("_start");
@@ -429,18 +458,24 @@ fn test_blockless_reactor_module_can_call_reactor_module_with_callback_endless_l
             name: "".to_string(),
             file: primary_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(primary_code)),
+            digest: String::new(),
+            permissions: None,
         },
         BlocklessModule {
             module_type: ModuleType::Module,
             name: "reactor1".to_string(),
             file: reactor_1_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(reactor_1_code)),
+            digest: String::new(),
+            permissions: None,
         },
         BlocklessModule {
             module_type: ModuleType::Module,
             name: "reactor2".to_string(),
             file: reactor_2_path.to_str().unwrap().to_string(),
             md5: format!("{:x}", md5::compute(reactor_2_code)),
+            digest: String::new(),
+            permissions: None,
         },
     ];
     let mut config = BlocklessConfig::new("_start");
@@ -449,3 +484,81 @@ fn test_blockless_reactor_module_can_call_reactor_module_with_callback_endless_l
     let code = run_blockless(config).unwrap();
     assert_eq!(code.code, 0);
 }
+
+#[test]
+fn test_blockless_library_module_has_its_own_permissions() {
+    // the entry module traps if the request of the library is not denied.
+    let primary_code = r#"
+    (module
+        (import "lib" "fetch" (func $fetch (result i32)))
+        (func (export "_start")
+            call $fetch
+            i32.const 14 ;; permission_deny
+            i32.ne
+            if
+                unreachable
+            end
+        )
+    )
+    "#;
+    let lib_code = r#"
+    (module
+        (type $http_req_ty (func (param i32 i32 i32 i32 i32 i32) (result i32)))
+        (import "blockless_http" "http_req" (func $http_req (type $http_req_ty)))
+
+        (memory (export "memory") 1)
+
+        (func (export "fetch") (result i32)
+            (call $http_req
+                (i32.const 0)
+                (i32.const 30)
+                (i32.const 64)
+                (i32.const 31)
+                (i32.const 128)
+                (i32.const 136)
+            )
+        )
+
+        (data (i32.const 0) "https://reqres.in/api/products")
+        (data (i32.const 64) "{\"method\":\"get\",\"headers\":\"{}\"}")
+    )
+    "#;
+    let temp_dir = TempDir::new("blockless_run").unwrap();
+
+    let primary_path = temp_dir.path().join("run.wasm");
+    let lib_path = temp_dir.path().join("lib.wasm");
+
+    fs::write(&primary_path, primary_code).unwrap();
+    fs::write(&lib_path, lib_code).unwrap();
+
+    let modules = vec![
+        BlocklessModule {
+            module_type: ModuleType::Entry,
+            name: "".to_string(),
+            file: primary_path.to_str().unwrap().to_string(),
+            md5: String::new(),
+            digest: String::new(),
+            permissions: None,
+        },
+        BlocklessModule {
+            module_type: ModuleType::Module,
+            name: "lib".to_string(),
+            file: lib_path.to_str().unwrap().to_string(),
+            md5: String::new(),
+            digest: String::new(),
+            permissions: Some(ModulePermissions::default()),
+        },
+    ];
+    let mut config = BlocklessConfig::new("_start");
+    config.set_version(BlocklessConfigVersion::Version1);
+    config.set_modules(modules);
+    // the url is granted to the app, not to the library.
+    config.set_permisions(vec![
+        Permission::parse("https://reqres.in/api/products").unwrap()
+    ]);
+    config.permissions_config.prompter = Some(PrompterKind::Deny);
+    let status = run_blockless(config).unwrap();
+    assert_eq!(status.code, 0);
+    assert_eq!(status.denied.len(), 1);
+    assert_eq!(status.denied[0].api_name, "http_req");
+}
@@ -1,26 +1,76 @@
 #![allow(unused)]
 use anyhow::{bail, Result};
+use blockless::signature::{parse_public_key, SignaturePolicy};
 use blockless::{
     BlocklessConfig, BlocklessModule, BlsNnGraph, BlsOptions, ModuleType, OptimizeOpts,
-    OptionParser, Permission, PermissionGrant, PermissionsConfig, Stderr, Stdin, Stdout,
+    OptionParser, Permission, PermissionGrant, PermissionsConfig, PrompterKind, Stderr, Stdin,
+    Stdout,
 };
 use clap::{
     builder::{TypedValueParser, ValueParser},
-    Arg, ArgMatches, Command, Parser,
+    Arg, ArgMatches, Command, Parser, Subcommand,
 };
 use std::{
     collections::HashMap,
     net::{IpAddr, SocketAddr, TcpListener, ToSocketAddrs},
     option,
-    path::{Path, PathBuf},
+    path::PathBuf,
     str::FromStr,
+    time::Duration,
 };
 use url::Url;
 
-use crate::config::CliConfig;
+use crate::config::{read_env_file, CliConfig, ConfigFormat, LoadOptions};
+use crate::ipfs::DEFAULT_GATEWAY;
+use crate::workspace::{parse_age, parse_size};
 
 const INPUT_HELP: &str = "The input file can be a WASM file, a configuration file, or a CAR file.";
 
+const VALIDATE_HELP: &str =
+    "Validate the configuration file or the CAR file without running the app.";
+
+const CONFIG_HELP: &str = "Inspect the configuration.";
+
+const CONFIG_SHOW_HELP: &str =
+    "Print the configuration merged from the extends chain, the profile and the variables.";
+
+const CONFIG_SHOW_FORMAT_HELP: &str = "The output format: json, toml or yaml. Default is json.";
+
+const PROFILE_HELP: &str = "The profile of the configuration file to merge.";
+
+const PACK_HELP: &str =
+    "Pack the configuration file, the modules and the assets into a CAR file which can be run directly.";
+
+const PACK_CONFIG_HELP: &str = "The configuration file to pack.";
+
+const PACK_ASSET_HELP: &str =
+    "The file or directory added into the package root with its name, can be used multiple times.";
+
+const PACK_OUTPUT_HELP: &str =
+    "The output CAR file. Default is the configuration file name with the .car extension.";
+
+const CONVERT_HELP: &str = "Convert the configuration file between JSON, TOML and YAML.";
+
+const CONVERT_TO_HELP: &str =
+    "The target format: json, toml or yaml. Default is the format of the output file extension.";
+
+const CONVERT_OUTPUT_HELP: &str =
+    "The output file. The converted configuration is printed if not specified.";
+
+const GC_HELP: &str =
+    "Prune the extracted packages and the run directories in the workspace beyond the retention.";
+
+const GC_ROOT_HELP: &str =
+    "The workspace root directory. Default is the ENV_ROOT_PATH environment variable.";
+
+const GC_MAX_AGE_HELP: &str =
+    "Prune the entries unused for longer than the age, e.g. 30m, 12h or 7d. Default is 7d.";
+
+const GC_MAX_SIZE_HELP: &str =
+    "Prune the least recently used entries until the workspace is under the size, e.g. 512M or 10G.";
+
+const GC_DRY_RUN_HELP: &str = "Print the entries to prune without removing them.";
+
 const DEBUG_INFO_HELP: &str = "Runtime debugging information.";
 
 const APP_ARGS_HELP: &str = "Application arguments will be passed into the app.";
@@ -42,6 +92,9 @@ const LIMITED_FUEL_HELP: &str = "The limited fuel for runtime, default is infine
 
 const ENVS_HELP: &str = "Application environment variables will be passed into the app.";
 
+const HOST_ENV_HELP: &str =
+    "The host environment variable exposed to the app, the app reads it by `env_var_read`.";
+
 const ENV_FILE_HELP: &str = "Path to an environment file (.env) to load variables from";
 
 const OPTS_HELP: &str = "Optimization and tuning related options for wasm performance";
@@ -62,6 +115,14 @@ const STDIN_HELP: &str =
 const MAP_DIR_HELP: &str =
     "Grant access to a host directory for a guest. If specified as HOST_DIR, the corresponding directory on the host will be made available within the guest.";
 
+const MOUNT_CAR_HELP: &str =
+    "Mount the UnixFS tree in the CAR file as a read-only guest directory, the files are read from the CAR blocks on demand.";
+
+const OUTPUT_DIR_HELP: &str =
+    "The guest directory packed into a CAR file after the app exits, the result CID is reported.";
+
+const OUTPUT_CAR_HELP: &str = "The CAR file of the --output-dir.";
+
 const V86_HELP: &str =
     "V86 model flag when the v86 flag the car file must be v86 configure and image.";
 
@@ -78,7 +139,39 @@ const CLI_EXIT_WITH_CODE_HELP: &str =
 const NETWORK_ERROR_CODE_HELP: &str =
     "Enable WASI APIs marked as: @unstable(feature = network-error-code).";
 
-const MAX_MEMORY_SIZE_HELP: &str = "The max memory size limited.";
+const TRUSTED_KEY_HELP: &str =
+    "The hex ed25519 public key of a trusted publisher, can be used multiple times.";
+
+const REQUIRE_SIGNATURE_HELP: &str =
+    "Refuse to run the unsigned CAR files and modules, or the ones signed by untrusted keys.";
+
+const IN_MEMORY_HELP: &str =
+    "Run the CAR file from the memory, the modules and the package files are served from the CAR blocks without extracting to the disk.";
+
+const IPFS_GATEWAY_HELP: &str =
+    "The IPFS HTTP gateway to fetch the package when the input is a CID or an ipfs:// url.";
+
+const KEYGEN_HELP: &str = "Generate an ed25519 key pair for signing the packages.";
+
+const KEYGEN_OUTPUT_HELP: &str =
+    "The secret key file, the public key is written to the file with the .pub extension. Default is bls.key.";
+
+const KEYGEN_FORCE_HELP: &str = "Overwrite the existing key files.";
+
+const SIGN_HELP: &str =
+    "Sign the CAR file into the detached <CAR>.sig file, or the wasm file with the bls.signature section.";
+
+const SIGN_INPUT_HELP: &str = "The CAR file or the wasm file to sign.";
+
+const SIGN_KEY_HELP: &str = "The secret key file generated by the keygen command.";
+
+const SIGN_OUTPUT_HELP: &str =
+    "The output file. Default is <CAR>.sig for the CAR file, the wasm file is signed in place.";
+
+const MAX_MEMORY_SIZE_HELP: &str =
+    "The max memory size limited, same as --limited-memory and wins over it.";
+
+const MAX_LINEAR_MEMORY_SIZE_HELP: &str = "The max linear memory size of the app in bytes.";
 
 const NN_HELP: &str = "Enable support for WASI neural network imports .";
 
@@ -98,6 +191,36 @@ const DENY_WRITE_HELP: &str = "Deny the app to write permissions.";
 
 const ALLOW_WRITE_ALL_HELP: &str = "Allow the app to all write permissions.";
 
+const ALLOW_NET_HELP: &str =
+    "Allow the app to connect the hosts, e.g. `api.example.com:443`, all hosts if no host is given.";
+
+const ALLOW_LISTEN_HELP: &str =
+    "Allow the app to listen on the addresses, e.g. `127.0.0.1:8080`, all addresses if no address is given.";
+
+const ALLOW_CONNECT_HELP: &str =
+    "Allow the app to connect the addresses by the sockets, e.g. `127.0.0.1:5432`, all addresses if no address is given.";
+
+const ALLOW_RUN_HELP: &str =
+    "Allow the app to run the cgi extensions of the aliases, all extensions if no alias is given.";
+
+const ALLOW_ENV_HELP: &str =
+    "Allow the app to read the environment variables, all variables if no name is given.";
+
+const DENY_ENV_HELP: &str =
+    "Deny the app to read the environment variables, all variables if no name is given.";
+
+const PROMPTER_HELP: &str =
+    "The prompter asked for the permissions not granted: tty, deny, fd:<N>, unix:<SOCKET> or policy:<FILE>. [default: tty]";
+
+const AUDIT_LOG_HELP: &str =
+    "The file the permission decisions are appended to as JSON lines, the denied accesses are summarized after the app exits.";
+
+const QUOTA_HELP: &str =
+    "The quota of the host resources in the run, e.g. max_open_handles, max_requests, max_bytes_sent, max_bytes_received or max_cgi_processes.";
+
+const DENY_NET_HELP: &str =
+    "Deny the app to connect the hosts, all hosts if no host is given. It wins over the allowed hosts.";
+
 fn parse_envs(envs: &str) -> Result<(String, String)> {
     let parts: Vec<_> = envs.splitn(2, "=").collect();
     if parts.len() != 2 {
@@ -165,6 +288,18 @@ fn parser_allow(allow: &str) -> Result<PermissionGrant> {
     PermissionGrant::parse(&allow)
 }
 
+fn parse_trusted_key(key: &str) -> Result<String> {
+    parse_public_key(key)?;
+    Ok(key.trim().to_ascii_lowercase())
+}
+
+fn parse_config_format(format: &str) -> Result<ConfigFormat> {
+    match ConfigFormat::from_name(format) {
+        Some(format) => Ok(format),
+        None => bail!("unknown format `{format}`, only json, toml and yaml accepted"),
+    }
+}
+
 fn parse_module(module: &str) -> Result<BlocklessModule> {
     let mods: Vec<_> = module.splitn(2, "=").collect();
     Ok(BlocklessModule {
@@ -172,6 +307,8 @@ fn parse_module(module: &str) -> Result<BlocklessModule> {
         name: mods[0].into(),
         file: mods[1].into(),
         md5: String::new(), //didn't need check.
+        digest: String::new(),
+        permissions: None,
     })
 }
 
@@ -221,6 +358,15 @@ fn parse_dirs(s: &str) -> Result<(String, String)> {
     Ok((host.into(), guest.into()))
 }
 
+fn parse_car_mount(s: &str) -> Result<(String, String)> {
+    match s.split_once("::") {
+        Some((car, guest)) if !car.is_empty() && !guest.is_empty() => {
+            Ok((car.into(), guest.into()))
+        }
+        _ => bail!("the car mount should be of the form `CAR_FILE::GUEST_DIR`"),
+    }
+}
+
 #[derive(Debug)]
 pub enum RuntimeType {
     V86,
@@ -241,6 +387,33 @@ pub struct PermissionFlags {
     #[clap(long = "deny-write", id="deny-write", num_args=(0..) , value_name = "PATH[,]", help = DENY_WRITE_HELP, value_parser = parser_allow)]
     pub deny_write: Option<PermissionGrant>,
 
+    #[clap(long = "allow-net", id="allow-net", num_args=(0..) , value_name = "HOST[:PORT][,]", help = ALLOW_NET_HELP, value_parser = parser_allow)]
+    pub allow_net: Option<PermissionGrant>,
+
+    #[clap(long = "deny-net", id="deny-net", num_args=(0..) , value_name = "HOST[:PORT][,]", help = DENY_NET_HELP, value_parser = parser_allow)]
+    pub deny_net: Option<PermissionGrant>,
+
+    #[clap(long = "allow-listen", id="allow-listen", num_args=(0..) , value_name = "HOST[:PORT][,]", help = ALLOW_LISTEN_HELP, value_parser = parser_allow)]
+    pub allow_listen: Option<PermissionGrant>,
+
+    #[clap(long = "allow-connect", id="allow-connect", num_args=(0..) , value_name = "HOST[:PORT][,]", help = ALLOW_CONNECT_HELP, value_parser = parser_allow)]
+    pub allow_connect: Option<PermissionGrant>,
+
+    #[clap(long = "allow-run", id="allow-run", num_args=(0..) , value_name = "ALIAS[,]", help = ALLOW_RUN_HELP, value_parser = parser_allow)]
+    pub allow_run: Option<PermissionGrant>,
+
+    #[clap(long = "allow-env", id="allow-env", num_args=(0..) , value_name = "NAME[,]", help = ALLOW_ENV_HELP, value_parser = parser_allow)]
+    pub allow_env: Option<PermissionGrant>,
+
+    #[clap(long = "deny-env", id="deny-env", num_args=(0..) , value_name = "NAME[,]", help = DENY_ENV_HELP, value_parser = parser_allow)]
+    pub deny_env: Option<PermissionGrant>,
+
+    #[clap(long = "prompter", value_name = "PROMPTER", help = PROMPTER_HELP)]
+    pub prompter: Option<PrompterKind>,
+
+    #[clap(long = "audit-log", value_name = "AUDIT_FILE", help = AUDIT_LOG_HELP)]
+    pub audit_log: Option<PathBuf>,
+
     #[clap(long = "allow-all", id = "allow-all", help = "Allow all permissions.")]
     pub allow_all: bool,
 }
@@ -252,6 +425,16 @@ impl Into<PermissionsConfig> for PermissionFlags {
             deny_read: self.deny_read,
             allow_write: self.allow_write,
             deny_write: self.deny_write,
+            allow_net: self.allow_net,
+            deny_net: self.deny_net,
+            allow_listen: self.allow_listen,
+            allow_connect: self.allow_connect,
+            allow_run: self.allow_run,
+            allow_env: self.allow_env,
+            deny_env: self.deny_env,
+            run_rules: Default::default(),
+            prompter: self.prompter,
+            audit_log: self.audit_log,
             allow_all: self.allow_all,
         };
         permissions
@@ -311,14 +494,120 @@ pub fn get_styles() -> clap::builder::Styles {
         )
 }
 
+#[derive(Subcommand, Debug)]
+pub(crate) enum CliSubCommand {
+    #[command(about = VALIDATE_HELP)]
+    Validate {
+        #[clap(value_name = "INPUT", help = INPUT_HELP)]
+        input: String,
+
+        #[clap(long = "profile", value_name = "PROFILE", help = PROFILE_HELP)]
+        profile: Option<String>,
+    },
+
+    #[command(about = KEYGEN_HELP)]
+    Keygen {
+        #[clap(long = "output", short = 'o', value_name = "OUTPUT", help = KEYGEN_OUTPUT_HELP)]
+        output: Option<PathBuf>,
+
+        #[clap(long = "force", help = KEYGEN_FORCE_HELP)]
+        force: bool,
+    },
+
+    #[command(about = SIGN_HELP)]
+    Sign {
+        #[clap(value_name = "INPUT", help = SIGN_INPUT_HELP)]
+        input: PathBuf,
+
+        #[clap(long = "key", value_name = "KEY_FILE", help = SIGN_KEY_HELP)]
+        key: PathBuf,
+
+        #[clap(long = "output", short = 'o', value_name = "OUTPUT", help = SIGN_OUTPUT_HELP)]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = PACK_HELP)]
+    Pack {
+        #[clap(value_name = "CONFIG", help = PACK_CONFIG_HELP)]
+        config: PathBuf,
+
+        #[clap(long = "asset", value_name = "PATH", help = PACK_ASSET_HELP)]
+        assets: Vec<PathBuf>,
+
+        #[clap(long = "output", short = 'o', value_name = "OUTPUT", help = PACK_OUTPUT_HELP)]
+        output: Option<PathBuf>,
+
+        #[clap(long = "profile", value_name = "PROFILE", help = PROFILE_HELP)]
+        profile: Option<String>,
+
+        #[clap(long = "env-file", value_name = "ENV_FILE", help = ENV_FILE_HELP)]
+        env_file: Option<PathBuf>,
+    },
+
+    #[command(about = CONFIG_HELP)]
+    Config {
+        #[command(subcommand)]
+        command: ConfigSubCommand,
+    },
+
+    #[command(about = CONVERT_HELP)]
+    Convert {
+        #[clap(value_name = "INPUT", help = INPUT_HELP)]
+        input: String,
+
+        #[clap(long = "to", value_name = "FORMAT", help = CONVERT_TO_HELP, value_parser = parse_config_format)]
+        to: Option<ConfigFormat>,
+
+        #[clap(long = "output", short = 'o', value_name = "OUTPUT", help = CONVERT_OUTPUT_HELP)]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = GC_HELP)]
+    Gc {
+        #[clap(long = "root", value_name = "ROOT", help = GC_ROOT_HELP)]
+        root: Option<PathBuf>,
+
+        #[clap(long = "max-age", value_name = "AGE", help = GC_MAX_AGE_HELP, value_parser = parse_age)]
+        max_age: Option<Duration>,
+
+        #[clap(long = "max-size", value_name = "SIZE", help = GC_MAX_SIZE_HELP, value_parser = parse_size)]
+        max_size: Option<u64>,
+
+        #[clap(long = "dry-run", help = GC_DRY_RUN_HELP)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum ConfigSubCommand {
+    #[command(about = CONFIG_SHOW_HELP)]
+    Show {
+        #[clap(value_name = "INPUT", help = INPUT_HELP)]
+        input: String,
+
+        #[clap(long = "profile", value_name = "PROFILE", help = PROFILE_HELP)]
+        profile: Option<String>,
+
+        #[clap(long = "env-file", value_name = "ENV_FILE", help = ENV_FILE_HELP)]
+        env_file: Option<PathBuf>,
+
+        #[clap(long = "format", value_name = "FORMAT", help = CONFIG_SHOW_FORMAT_HELP, value_parser = parse_config_format)]
+        format: Option<ConfigFormat>,
+    },
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version = SHORT_VERSION, styles=get_styles(), arg_required_else_help = true, long_version = SHORT_VERSION, about = "Blockless WebAssembly Runtime")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub(crate) struct CliCommandOpts {
+    #[command(subcommand)]
+    pub command: Option<CliSubCommand>,
+
     #[clap(long = "v86", value_name = "V86", required = false, help = V86_HELP )]
     pub v86: bool,
 
     #[clap(value_name = "INPUT", required = true, help = INPUT_HELP )]
-    pub input: String,
+    pub input: Option<String>,
 
     #[clap(long = "debug-info", value_name = "DEBUG-INFO", help = DEBUG_INFO_HELP)]
     pub debug_info: bool,
@@ -335,6 +624,15 @@ pub(crate) struct CliCommandOpts {
     #[arg(long = "dir", value_name = "HOST_DIR[::GUEST_DIR]", help = MAP_DIR_HELP,value_parser = parse_dirs)]
     pub dirs: Vec<(String, String)>,
 
+    #[arg(long = "mount-car", value_name = "CAR_FILE::GUEST_DIR", help = MOUNT_CAR_HELP, value_parser = parse_car_mount)]
+    pub car_mounts: Vec<(String, String)>,
+
+    #[clap(long = "output-dir", value_name = "GUEST_DIR", help = OUTPUT_DIR_HELP)]
+    pub output_dir: Option<String>,
+
+    #[clap(long = "output-car", value_name = "CAR_FILE", requires = "output_dir", help = OUTPUT_CAR_HELP)]
+    pub output_car: Option<String>,
+
     #[clap(long = "drivers-root-path", value_name = "DRIVERS-ROOT-PATH", help = DRIVERS_ROOT_PATH_HELP)]
     pub drivers_root_path: Option<String>,
 
@@ -359,12 +657,21 @@ pub(crate) struct CliCommandOpts {
     #[clap(long = "env", value_name = "ENV=VAL", help = ENVS_HELP, number_of_values = 1, value_parser = parse_envs)]
     pub envs: Vec<(String, String)>,
 
+    #[clap(long = "host-env", value_name = "NAME", help = HOST_ENV_HELP)]
+    pub host_envs: Vec<String>,
+
     #[clap(long = "env-file", value_name = "ENV_FILE", help = ENV_FILE_HELP)]
     pub env_file: Option<PathBuf>,
 
+    #[clap(long = "profile", value_name = "PROFILE", help = PROFILE_HELP)]
+    pub profile: Option<String>,
+
     #[clap(long = "opt", short = 'O', value_name = "OPT=VAL,", help = OPTS_HELP,  value_parser = parse_opts)]
     pub opts: Option<OptimizeOpts>,
 
+    #[clap(long = "quota", value_name = "QUOTA=VAL,", help = QUOTA_HELP, value_delimiter = ',', value_parser = parse_envs)]
+    pub quotas: Vec<(String, String)>,
+
     #[clap(long = "permission", value_name = "PERMISSION", help = PERMISSION_HELP, value_parser = parse_permission)]
     pub permissions: Vec<Permission>,
 
@@ -389,6 +696,9 @@ pub(crate) struct CliCommandOpts {
     #[clap(long = "max_memory_size", value_name = "MAX_MEMORY_SIZE", help = MAX_MEMORY_SIZE_HELP)]
     pub max_memory_size: Option<u64>,
 
+    #[clap(long = "max-linear-memory-size", value_name = "MAX_LINEAR_MEMORY_SIZE", help = MAX_LINEAR_MEMORY_SIZE_HELP)]
+    pub max_linear_memory_size: Option<usize>,
+
     #[clap(flatten)]
     pub permission_flags: PermissionFlags,
 
@@ -397,6 +707,18 @@ pub(crate) struct CliCommandOpts {
 
     #[clap(long = "nn-graph", value_name = "NN_GRAPH", value_parser = parse_nn_graph, help = NN_GRAPH_HELP)]
     pub nn_graph: Vec<BlsNnGraph>,
+
+    #[clap(long = "trusted-key", value_name = "PUBLIC_KEY", value_parser = parse_trusted_key, help = TRUSTED_KEY_HELP)]
+    pub trusted_keys: Vec<String>,
+
+    #[clap(long = "require-signature", value_name = "REQUIRE_SIGNATURE", help = REQUIRE_SIGNATURE_HELP)]
+    pub require_signature: bool,
+
+    #[clap(long = "in-memory", value_name = "IN_MEMORY", help = IN_MEMORY_HELP)]
+    pub in_memory: bool,
+
+    #[clap(long = "ipfs-gateway", value_name = "URL", env = "BLS_IPFS_GATEWAY", default_value = DEFAULT_GATEWAY, help = IPFS_GATEWAY_HELP)]
+    pub ipfs_gateway: String,
 }
 
 impl CliCommandOpts {
@@ -416,23 +738,63 @@ impl CliCommandOpts {
 
     #[inline(always)]
     pub fn input_ref(&self) -> &str {
-        &self.input
+        self.input.as_deref().unwrap_or_default()
     }
 
+    /// Merge the command line options into the configure loaded from the input.
+    /// The options given in the command line take precedence over the configure:
+    /// values and lists replace the configured ones, flags can only switch a feature on,
+    /// envs and the permission flags are merged one by one.
     pub fn into_config(self, conf: &mut CliConfig) -> Result<()> {
         let envs = self.load_environment_vars()?;
 
-        conf.0.set_debug_info(self.debug_info);
-        conf.0.set_fs_root_path(self.fs_root_path);
-        conf.0.set_runtime_logger(self.runtime_logger);
-        conf.0.limited_memory(self.limited_memory);
-        conf.0.limited_fuel(self.limited_fuel);
-        conf.0.set_run_time(self.run_time);
-        conf.0.set_stdin_args(self.args);
-        conf.0.set_map_dirs(self.dirs);
-        conf.0.set_feature_thread(self.feature_thread);
-        conf.0.limited_memory(self.max_memory_size);
-        conf.0.permissions_config = self.permission_flags.into();
+        if self.debug_info {
+            conf.0.set_debug_info(true);
+        }
+        if self.fs_root_path.is_some() {
+            conf.0.set_fs_root_path(self.fs_root_path);
+        }
+        if self.runtime_logger.is_some() {
+            conf.0.set_runtime_logger(self.runtime_logger);
+        }
+        if self.limited_memory.is_some() {
+            conf.0.limited_memory(self.limited_memory);
+        }
+        if self.limited_fuel.is_some() {
+            conf.0.limited_fuel(self.limited_fuel);
+        }
+        if self.run_time.is_some() {
+            conf.0.set_run_time(self.run_time);
+        }
+        if self.args.len() > 0 {
+            conf.0.set_stdin_args(self.args);
+        }
+        if self.dirs.len() > 0 {
+            conf.0.set_map_dirs(self.dirs);
+        }
+        if self.car_mounts.len() > 0 {
+            conf.0.set_car_mounts(self.car_mounts);
+        }
+        if self.output_dir.is_some() {
+            conf.0.output_dir = self.output_dir;
+            conf.0.output_car = self.output_car;
+        }
+        if self.feature_thread {
+            conf.0.set_feature_thread(true);
+        }
+        // the `--max_memory_size` limits the memory like the `--limited-memory`.
+        if self.max_memory_size.is_some() {
+            conf.0.limited_memory(self.max_memory_size);
+        }
+        if self.max_linear_memory_size.is_some() {
+            conf.0.store_limited.max_memory_size = self.max_linear_memory_size;
+        }
+        // the quotas are set one by one, the others keep the configured.
+        if self.quotas.len() > 0 {
+            conf.0.quotas.config(self.quotas)?;
+        }
+        let permissions_config: PermissionsConfig = self.permission_flags.into();
+        conf.0.permissions_config.merge(permissions_config);
 
         // Handle IO settings
         if let Some(stderr) = self.stdio.stderr {
@@ -448,10 +810,21 @@ impl CliCommandOpts {
             conf.0.set_permisions(self.permissions);
         }
 
-        // Handle environment variables
-        conf.0.set_envs(envs);
+        // Handle environment variables, the command line wins on the same key.
+        let mut conf_envs = conf.0.envs.clone();
+        for env in envs {
+            conf_envs.retain(|(key, _)| key != &env.0);
+            conf_envs.push(env);
+        }
+        conf_envs.sort_by(|(a_key, _), (b_key, _)| a_key.cmp(b_key));
+        conf.0.set_envs(conf_envs);
+        if self.host_envs.len() > 0 {
+            conf.0.host_envs = self.host_envs;
+        }
 
-        conf.0.set_drivers_root_path(self.drivers_root_path);
+        if self.drivers_root_path.is_some() {
+            conf.0.set_drivers_root_path(self.drivers_root_path);
+        }
         let mut modules = self.modules;
         let mut has_entry = false;
         self.entry.map(|e| {
@@ -462,8 +835,10 @@ impl CliCommandOpts {
             modules.push(BlocklessModule {
                 module_type: ModuleType::Entry,
                 name: String::new(),
-                file: self.input,
+                file: self.input.unwrap_or_default(),
                 md5: String::new(),
+                digest: String::new(),
+                permissions: None,
             });
             conf.0.set_modules(modules);
             if !has_entry {
@@ -472,14 +847,46 @@ impl CliCommandOpts {
             conf.0
                 .set_version(blockless::BlocklessConfigVersion::Version1);
         }
-        conf.0.nn = self.nn;
-        conf.0.tcp_listens = self.tcp_listens;
-        conf.0.network_error_code = self.network_error_code;
-        conf.0.unknown_imports_trap = self.unknown_imports_trap;
-        conf.0.nn_graph = self.nn_graph;
+        if self.nn {
+            conf.0.nn = true;
+        }
+        if self.tcp_listens.len() > 0 {
+            conf.0.tcp_listens = self.tcp_listens;
+        }
+        if self.network_error_code {
+            conf.0.network_error_code = true;
+        }
+        if self.unknown_imports_trap {
+            conf.0.unknown_imports_trap = true;
+        }
+        if self.cli_exit_with_code {
+            conf.0.cli_exit_with_code = true;
+        }
+        if self.nn_graph.len() > 0 {
+            conf.0.nn_graph = self.nn_graph;
+        }
+        // only the operator trusts the keys, the modules are checked with the same keys as the car file.
+        conf.0.trusted_keys = self.trusted_keys;
+        if self.require_signature {
+            conf.0.require_signature = true;
+        }
         Ok(())
     }
 
+    /// The options of loading the configure, from the `--env-file` and the `--profile`,
+    /// the CAR file is verified with `--trusted-key` and `--require-signature`.
+    pub(crate) fn load_options(&self) -> Result<LoadOptions> {
+        let mut opts = LoadOptions::new(self.env_file.as_deref(), self.profile.clone())?;
+        let trusted_keys = self
+            .trusted_keys
+            .iter()
+            .map(|key| parse_public_key(key))
+            .collect::<Result<Vec<_>, _>>()?;
+        opts.signature_policy = SignaturePolicy::new(trusted_keys, self.require_signature);
+        opts.in_memory = self.in_memory;
+        Ok(opts)
+    }
+
     /// Load and merge environment variables from both the environment file and explicit --env arguments.
     /// Explicit environment variables take precedence over those from the file.
     /// The environment variables are sorted by key.
@@ -488,15 +895,8 @@ impl CliCommandOpts {
 
         // Load vars from env file if specified
         if let Some(env_file) = &self.env_file {
-            let env_path = Path::new(env_file);
-            if env_path.exists() {
-                // Read variables from the env file
-                let file_vars = dotenvy::from_path_iter(env_path)?
-                    .filter_map(Result::ok)
-                    .collect::<Vec<(String, String)>>();
-                // Add all variables from the file
-                final_envs.extend(file_vars);
-            }
+            // Add all variables from the file, the missing file is an error
+            final_envs.extend(read_env_file(env_file)?);
         }
 
         // Add explicit environment variables, overwriting any duplicates from the file
@@ -532,16 +932,26 @@ mod test {
     #[test]
     fn test_cli_command() {
         let cli = CliCommandOpts::try_parse_from(["cli", "test", "--", "--test=10"]).unwrap();
-        assert_eq!(cli.input.as_str(), "test");
+        assert_eq!(cli.input_ref(), "test");
         assert_eq!(cli.args.len(), 1);
         assert_eq!(cli.args[0], "--test=10");
     }
 
+    #[test]
+    fn test_cli_command_input_named_as_subcommand() {
+        let cli = CliCommandOpts::try_parse_from(["cli", "--", "validate"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.input_ref(), "validate");
+        let cli = CliCommandOpts::try_parse_from(["cli", "./gc"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.input_ref(), "./gc");
+    }
+
     #[test]
     fn test_cli_command_env() {
         let cli = CliCommandOpts::try_parse_from(["cli", "test", "--env", "a=1", "--env", "b=2"])
             .unwrap();
-        assert_eq!(cli.input.as_str(), "test");
+        assert_eq!(cli.input_ref(), "test");
         assert_eq!(cli.envs.len(), 2);
         assert_eq!(cli.envs[0], ("a".to_string(), "1".to_string()));
         assert_eq!(cli.envs[1], ("b".to_string(), "2".to_string()));
@@ -602,7 +1012,7 @@ SERVICE_URL=${{BASE_URL}}/v1"#
             "http://www.google.com",
         ])
         .unwrap();
-        assert_eq!(cli.input.as_str(), "test");
+        assert_eq!(cli.input_ref(), "test");
         assert_eq!(cli.permissions.len(), 1);
         let perm = Permission {
             schema: "http".to_string(),
@@ -611,6 +1021,64 @@ SERVICE_URL=${{BASE_URL}}/v1"#
         assert_eq!(cli.permissions[0], perm);
     }
 
+    #[test]
+    fn test_cli_command_net_permission() {
+        let cli = CliCommandOpts::try_parse_from([
+            "cli",
+            "test",
+            "--allow-net",
+            "api.example.com:443,127.0.0.1",
+            "--deny-net",
+            "127.0.0.1:22",
+            "--allow-listen",
+            "127.0.0.1:8080",
+            "--allow-connect",
+            "127.0.0.1:5432",
+            "--allow-run",
+            "convert",
+            "--deny-env",
+            "TOKEN",
+            "--host-env",
+            "AWS_REGION",
+            "--prompter",
+            "policy:/etc/bls/policy.json",
+            "--audit-log",
+            "audit.log",
+        ])
+        .unwrap();
+        let perms: PermissionsConfig = cli.permission_flags.into();
+        assert!(matches!(
+            &perms.deny_env,
+            Some(PermissionGrant::List(l)) if l == &vec!["TOKEN"]
+        ));
+        assert!(matches!(
+            &perms.allow_net,
+            Some(PermissionGrant::List(l)) if l == &vec!["api.example.com:443", "127.0.0.1"]
+        ));
+        assert!(matches!(
+            &perms.deny_net,
+            Some(PermissionGrant::List(l)) if l == &vec!["127.0.0.1:22"]
+        ));
+        assert!(matches!(
+            &perms.allow_listen,
+            Some(PermissionGrant::List(l)) if l == &vec!["127.0.0.1:8080"]
+        ));
+        assert!(matches!(
+            &perms.allow_connect,
+            Some(PermissionGrant::List(l)) if l == &vec!["127.0.0.1:5432"]
+        ));
+        assert!(matches!(
+            &perms.allow_run,
+            Some(PermissionGrant::List(l)) if l == &vec!["convert"]
+        ));
+        assert_eq!(cli.host_envs, vec!["AWS_REGION"]);
+        assert_eq!(
+            perms.prompter,
+            Some(PrompterKind::Policy("/etc/bls/policy.json".into()))
+        );
+        assert_eq!(perms.audit_log, Some(PathBuf::from("audit.log")));
+    }
+
     #[test]
     fn test_cli_command_input() {
         let command_line = r#"blockless_cli test.wasm"#;
@@ -619,7 +1087,7 @@ SERVICE_URL=${{BASE_URL}}/v1"#
             .map(str::to_string)
             .collect::<Vec<String>>();
         let cli_opts = CliCommandOpts::try_parse_from(command_line).unwrap();
-        let pat = cli_opts.input.as_str();
+        let pat = cli_opts.input_ref();
         assert_eq!(pat, "test.wasm");
     }
 
@@ -635,6 +1103,23 @@ SERVICE_URL=${{BASE_URL}}/v1"#
         assert_eq!(pat, "runtime.log");
     }
 
+    #[test]
+    fn test_cli_command_memory() {
+        let cli_opts = CliCommandOpts::try_parse_from([
+            "blockless_cli",
+            "test.wasm",
+            "--max_memory_size",
+            "20",
+            "--max-linear-memory-size",
+            "1024",
+        ])
+        .unwrap();
+        let mut cli_conf = CliConfig(BlocklessConfig::new("test.wasm"));
+        cli_opts.into_config(&mut cli_conf).unwrap();
+        assert_eq!(cli_conf.0.get_limited_memory(), Some(20));
+        assert_eq!(cli_conf.0.store_limited.max_memory_size, Some(1024));
+    }
+
     #[test]
     fn test_cli_command_fs_root_path() {
         let command_line = r#"blockless_cli test.wasm --fs-root-path /"#;
@@ -698,4 +1183,279 @@ SERVICE_URL=${{BASE_URL}}/v1"#
             BlocklessConfigVersion::Version1
         ));
     }
+
+    #[test]
+    fn test_cli_command_validate() {
+        let cli = CliCommandOpts::try_parse_from(["cli", "validate", "app.json"]).unwrap();
+        assert!(
+            matches!(cli.command, Some(CliSubCommand::Validate { input, .. }) if input == "app.json")
+        );
+
+        // the arguments after the input are the app arguments.
+        let cli = CliCommandOpts::try_parse_from(["cli", "app.wasm", "validate"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.input_ref(), "app.wasm");
+        assert_eq!(cli.args, vec!["validate".to_string()]);
+    }
+
+    #[test]
+    fn test_cli_command_config_show() {
+        let cli = CliCommandOpts::try_parse_from([
+            "cli",
+            "config",
+            "show",
+            "app.json",
+            "--profile",
+            "prod",
+            "--format",
+            "toml",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(CliSubCommand::Config {
+                command: ConfigSubCommand::Show {
+                    profile: Some(profile),
+                    format: Some(ConfigFormat::Toml),
+                    ..
+                }
+            }) if profile == "prod"
+        ));
+
+        let cli = CliCommandOpts::try_parse_from(["cli", "--profile", "prod", "app.json"]).unwrap();
+        assert_eq!(cli.profile.as_deref(), Some("prod"));
+        assert_eq!(cli.load_options().unwrap().profile.as_deref(), Some("prod"));
+    }
+
+    #[test]
+    fn test_cli_command_signature() {
+        let key = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+        let cli = CliCommandOpts::try_parse_from([
+            "cli",
+            "app.car",
+            "--trusted-key",
+            key,
+            "--require-signature",
+        ])
+        .unwrap();
+        assert_eq!(cli.trusted_keys, vec![key.to_string()]);
+        assert!(cli.require_signature);
+        assert!(cli.load_options().is_ok());
+        let mut cli_conf = CliConfig(BlocklessConfig::new("app.wasm"));
+        cli.into_config(&mut cli_conf).unwrap();
+        assert_eq!(cli_conf.0.trusted_keys, vec![key.to_string()]);
+        assert!(cli_conf.0.require_signature);
+
+        assert!(CliCommandOpts::try_parse_from(["cli", "app.car", "--trusted-key", "00"]).is_err());
+
+        let cli = CliCommandOpts::try_parse_from(["cli", "app.car", "--in-memory"]).unwrap();
+        assert!(cli.load_options().unwrap().in_memory);
+        assert_eq!(cli.ipfs_gateway, DEFAULT_GATEWAY);
+
+        let cli = CliCommandOpts::try_parse_from([
+            "cli",
+            "ipfs://bafy",
+            "--ipfs-gateway",
+            "http://127.0.0.1:8080",
+        ])
+        .unwrap();
+        assert_eq!(cli.ipfs_gateway, "http://127.0.0.1:8080");
+
+        let cli = CliCommandOpts::try_parse_from(["cli", "sign", "app.wasm", "--key", "bls.key"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(CliSubCommand::Sign { output: None, .. })
+        ));
+        let cli = CliCommandOpts::try_parse_from(["cli", "keygen", "-o", "dev.key"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(CliSubCommand::Keygen {
+                output: Some(_),
+                force: false
+            })
+        ));
+        let cli = CliCommandOpts::try_parse_from(["cli", "keygen", "--force"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(CliSubCommand::Keygen {
+                output: None,
+                force: true
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cli_command_pack() {
+        let cli = CliCommandOpts::try_parse_from([
+            "cli", "pack", "app.json", "--asset", "assets", "--asset", "data.txt", "-o", "app.car",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(CliSubCommand::Pack {
+                config,
+                assets,
+                output,
+                ..
+            }) => {
+                assert_eq!(config, PathBuf::from("app.json"));
+                assert_eq!(
+                    assets,
+                    vec![PathBuf::from("assets"), PathBuf::from("data.txt")]
+                );
+                assert_eq!(output, Some(PathBuf::from("app.car")));
+            }
+            _ => unreachable!("should be the pack command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_command_convert() {
+        let cli =
+            CliCommandOpts::try_parse_from(["cli", "convert", "app.json", "--to", "yml"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(CliSubCommand::Convert {
+                to: Some(ConfigFormat::Yaml),
+                output: None,
+                ..
+            })
+        ));
+        assert!(
+            CliCommandOpts::try_parse_from(["cli", "convert", "app.json", "--to", "xml"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_command_mount_car() {
+        let cli = CliCommandOpts::try_parse_from([
+            "cli",
+            "app.wasm",
+            "--mount-car",
+            "dataset.car::/data",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.car_mounts,
+            vec![("dataset.car".to_string(), "/data".to_string())]
+        );
+        let mut cli_conf = CliConfig(BlocklessConfig::new("app.wasm"));
+        cli.into_config(&mut cli_conf).unwrap();
+        assert_eq!(
+            cli_conf.0.car_mounts,
+            vec![("dataset.car".to_string(), "/data".to_string())]
+        );
+        assert!(
+            CliCommandOpts::try_parse_from(["cli", "app.wasm", "--mount-car", "dataset.car"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_command_output() {
+        let cli = CliCommandOpts::try_parse_from([
+            "cli",
+            "app.wasm",
+            "--output-dir",
+            "/data/out",
+            "--output-car",
+            "result.car",
+        ])
+        .unwrap();
+        let mut cli_conf = CliConfig(BlocklessConfig::new("app.wasm"));
+        cli.into_config(&mut cli_conf).unwrap();
+        assert_eq!(cli_conf.0.output_dir.as_deref(), Some("/data/out"));
+        assert_eq!(cli_conf.0.output_car.as_deref(), Some("result.car"));
+        // the car file is of the output directory.
+        assert!(
+            CliCommandOpts::try_parse_from(["cli", "app.wasm", "--output-car", "result.car"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cli_command_gc() {
+        let cli = CliCommandOpts::try_parse_from([
+            "cli",
+            "gc",
+            "--max-age",
+            "12h",
+            "--max-size",
+            "10G",
+            "--dry-run",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(CliSubCommand::Gc {
+                root,
+                max_age,
+                max_size,
+                dry_run,
+            }) => {
+                assert_eq!(root, None);
+                assert_eq!(max_age, Some(Duration::from_secs(12 * 60 * 60)));
+                assert_eq!(max_size, Some(10 << 30));
+                assert!(dry_run);
+            }
+            _ => unreachable!("should be the gc command"),
+        }
+        assert!(CliCommandOpts::try_parse_from(["cli", "gc", "--max-age", "1y"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_command_precedence() {
+        let mut cli_conf = CliConfig(
+            BlocklessConfig::from_json(
+                r#"{
+                    "entry": "/a.wasm",
+                    "fs_root_path": "/config",
+                    "limited_fuel": 100,
+                    "debug_info": true,
+                    "feature_thread": true,
+                    "envs": {"A": "config", "B": "config"},
+                    "args": ["config"],
+                    "allow_read": ["/data"],
+                    "deny_write": true,
+                    "quotas": {"max_requests": 10, "max_cgi_processes": 1}
+                }"#,
+            )
+            .unwrap(),
+        );
+        let cli_opts = CliCommandOpts::try_parse_from([
+            "cli",
+            "test.wasm",
+            "--limited-fuel",
+            "200",
+            "--env",
+            "B=cli",
+            "--allow-write",
+            "/tmp",
+            "--quota",
+            "max_requests=20,max_bytes_sent=1024",
+        ])
+        .unwrap();
+        cli_opts.into_config(&mut cli_conf).unwrap();
+        let conf = cli_conf.0;
+        // not given in the command line, keep the configured values.
+        assert_eq!(conf.fs_root_path_ref(), Some("/config"));
+        assert!(conf.get_debug_info());
+        assert!(conf.feature_thread());
+        assert_eq!(conf.stdin_args_ref(), &vec!["config".to_string()]);
+        // given in the command line, override the configured values.
+        assert_eq!(conf.get_limited_fuel(), Some(200));
+        assert_eq!(
+            conf.envs_ref(),
+            &vec![
+                ("A".to_string(), "config".to_string()),
+                ("B".to_string(), "cli".to_string()),
+            ]
+        );
+        let perms = &conf.permissions_config;
+        assert!(matches!(&perms.allow_read, Some(PermissionGrant::List(l)) if l == &vec!["/data"]));
+        assert!(matches!(&perms.allow_write, Some(PermissionGrant::List(l)) if l == &vec!["/tmp"]));
+        assert!(matches!(perms.deny_write, Some(PermissionGrant::All)));
+        assert_eq!(conf.quotas.max_requests, Some(20));
+        assert_eq!(conf.quotas.max_bytes_sent, Some(1024));
+        assert_eq!(conf.quotas.max_cgi_processes, Some(1));
+    }
 }
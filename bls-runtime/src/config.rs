@@ -1,17 +1,22 @@
 use anyhow::{bail, Context, Result};
 use blockless::{
-    self, BlocklessModule, LoggerLevel, ModuleType, OptimizeOpts, Stderr, Stdin, Stdio, Stdout,
+    self, car::car_root_dir, signature::SignaturePolicy, BlocklessConfig, LoggerLevel, PackageFs,
 };
-use blockless::{BlocklessConfig, DriverConfig, MultiAddr, Permission};
-use json::{self, JsonValue};
 use rust_car::reader::{self, CarReader};
 use rust_car::utils::{extract_ipld, ipld_write};
+use serde::Deserialize;
 use std::env::VarError;
 use std::ffi::OsStr;
-use std::fs::{self, File};
+use std::fs;
+use std::io::{Read, Seek};
 use std::path::{Path, PathBuf};
 
+use crate::interpolate::{interpolate_config, Variables};
+use crate::ipfs::verify_car_blocks;
+use crate::layer::{load_layers, select_profile};
 use crate::v86config::V86config;
+use crate::validate::{validate_value, ConfigIssues};
+use crate::workspace::Workspace;
 
 pub(crate) struct CliConfig(pub(crate) BlocklessConfig);
 
@@ -29,6 +34,132 @@ impl Config {
     }
 }
 
+/// The formats of the configure file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// the format by the file extension, `None` if the extension is not a configure format.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        Self::from_name(&ext)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// parse the configure text into the json model.
+    pub fn parse(&self, text: &str) -> Result<serde_json::Value> {
+        let value = match self {
+            ConfigFormat::Json => serde_json::from_str(text)?,
+            ConfigFormat::Toml => toml::from_str(text)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(text)?,
+        };
+        Ok(value)
+    }
+
+    /// render the json model as the configure text.
+    pub fn render(&self, value: &serde_json::Value) -> Result<String> {
+        let text = match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(value)?,
+            ConfigFormat::Toml => toml::to_string_pretty(value)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(value)?,
+        };
+        Ok(text)
+    }
+}
+
+/// convert the configure file into the format, the variables like `$ROOT` are kept.
+pub(crate) fn convert_config_file(path: impl AsRef<Path>, format: ConfigFormat) -> Result<String> {
+    let from = ConfigFormat::from_path(&path).unwrap_or(ConfigFormat::Json);
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.as_ref().display()))?;
+    format.render(&from.parse(&text)?)
+}
+
+/// The options of loading the configure.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct LoadOptions {
+    /// the variables of the `${VAR}` interpolation.
+    pub vars: Variables,
+    /// the profile selected by `--profile`.
+    pub profile: Option<String>,
+    /// the signature policy of the CAR file.
+    pub signature_policy: SignaturePolicy,
+    /// serve the CAR package from the memory instead of extracting it.
+    pub in_memory: bool,
+    /// the configure is from the CAR package, which can't read the host environment
+    /// by the interpolation or the `env_file`.
+    pub package: bool,
+}
+
+impl LoadOptions {
+    /// the variables of the `--env-file` are used by the interpolation.
+    pub fn new(env_file: Option<&Path>, profile: Option<String>) -> Result<Self> {
+        let env_file_vars = match env_file {
+            Some(env_file) => read_env_file(env_file)?,
+            None => Vec::new(),
+        };
+        Ok(Self {
+            vars: Variables::new(env_file_vars),
+            profile,
+            signature_policy: Default::default(),
+            in_memory: false,
+            package: false,
+        })
+    }
+}
+
+/// read the variables of the environment file, the missing file is an error.
+pub(crate) fn read_env_file(env_file: &Path) -> Result<Vec<(String, String)>> {
+    if !env_file.exists() {
+        bail!("the env file is not found: {}", env_file.display());
+    }
+    let iter = dotenvy::from_path_iter(env_file)
+        .with_context(|| format!("failed to read env file: {}", env_file.display()))?;
+    let mut envs = Vec::new();
+    for item in iter {
+        match item {
+            Ok(env) => envs.push(env),
+            // the malformed line is reported with its line number instead of skipped.
+            Err(dotenvy::Error::LineParse(line, _)) => {
+                let content = fs::read_to_string(env_file).unwrap_or_default();
+                match content.find(line.as_str()) {
+                    Some(pos) => bail!(
+                        "{}:{}: malformed line `{line}`",
+                        env_file.display(),
+                        content[..pos].lines().count() + 1
+                    ),
+                    None => bail!("{}: malformed line `{line}`", env_file.display()),
+                }
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read env file: {}", env_file.display()))
+            }
+        }
+    }
+    Ok(envs)
+}
+
+/// The config keys only used by the command line.
+#[derive(Deserialize)]
+struct CliConfigFile {
+    /// the environment file, same as `--env-file`, the `envs` win on the same key.
+    #[serde(default)]
+    env_file: Option<PathBuf>,
+}
+
 struct EnvVar {
     name: String,
     value: String,
@@ -59,186 +190,65 @@ impl CliConfig {
         CliConfig(bconf)
     }
 
-    fn optimize_options(opt_json: &JsonValue) -> Result<OptimizeOpts> {
-        let mut opts: OptimizeOpts = OptimizeOpts::default();
-        let opt_items = opt_json
-            .entries()
-            .map(|item| (item.0.to_string(), json::stringify(item.0)))
-            .collect::<Vec<_>>();
-        opts.config(opt_items)?;
-        Ok(opts)
+    fn from_json_string(json_string: String) -> Result<Self> {
+        Self::from_value(
+            ConfigFormat::Json.parse(&json_string)?,
+            &LoadOptions::default(),
+        )
     }
 
-    fn map_dirs(map_dir: &JsonValue) -> anyhow::Result<Vec<(String, String)>> {
-        let mut ret = Vec::new();
-        if !map_dir.is_null() {
-            if !map_dir.is_array() {
-                bail!("the map dir item should be array.");
-            }
-            for obj in map_dir.members() {
-                let host = obj["host"]
-                    .as_str()
-                    .context("host item is not define.")?
-                    .to_string();
-                let guest = obj["guest"]
-                    .as_str()
-                    .context("guest item is not define.")?
-                    .to_string();
-                ret.push((host, guest));
-            }
+    /// build the configure from the json model parsed from any configure format,
+    /// the selected profile is merged first, then the `${VAR}` in the string values
+    /// are interpolated with the variables.
+    fn from_value(mut value: serde_json::Value, opts: &LoadOptions) -> Result<Self> {
+        select_profile(&mut value, opts.profile.as_deref())?;
+        match opts.package {
+            true => interpolate_config(&mut value, &opts.vars.without_host_env())?,
+            false => interpolate_config(&mut value, &opts.vars)?,
         }
-        Ok(ret)
-    }
-
-    fn permissions(permission_json: &JsonValue) -> Vec<Permission> {
-        match *permission_json {
-            JsonValue::Array(ref perms) => perms
-                .iter()
-                .filter_map(|p| {
-                    let p = p.as_str();
-                    match p {
-                        Some(p) => {
-                            let bs = p.as_bytes();
-                            let addr = MultiAddr::parse(bs);
-                            let addr = if addr.is_ok() {
-                                addr.unwrap()
-                            } else {
-                                return None;
-                            };
-                            let schema = addr.schema();
-                            let schema = if schema.is_ok() {
-                                schema.unwrap()
-                            } else {
-                                return None;
-                            };
-                            Some(Permission {
-                                schema: schema.into(),
-                                url: p.into(),
-                            })
-                        }
-                        None => None,
-                    }
-                })
-                .collect(),
-            _ => Vec::new(),
+        let issues = validate_value(&value);
+        if !issues.is_empty() {
+            return Err(ConfigIssues(issues).into());
         }
-    }
-
-    fn drivers(driver_json: &JsonValue) -> Vec<DriverConfig> {
-        match *driver_json {
-            JsonValue::Array(ref drvs_cfg) => {
-                let cfgs: Vec<DriverConfig> = drvs_cfg
-                    .iter()
-                    .map(|c| {
-                        let schema = c["schema"].as_str().map(String::from);
-                        let path = c["path"].as_str().map(String::from);
-                        (schema, path)
-                    })
-                    .filter(|(s, p)| s.is_some() && p.is_some())
-                    .map(|(s, p)| DriverConfig::new(s.unwrap(), p.unwrap()))
-                    .collect::<Vec<_>>();
-                cfgs
-            }
-            _ => Vec::new(),
-        }
-    }
-
-    fn modules(modules: &JsonValue) -> Vec<BlocklessModule> {
-        match *modules {
-            JsonValue::Array(ref modules_cfg) => modules_cfg
-                .iter()
-                .map(|c| {
-                    let name = c["name"].as_str().map(String::from).unwrap_or_default();
-                    let file = c["file"].as_str().map(String::from).unwrap_or_default();
-                    let md5 = c["md5"].as_str().map(String::from).unwrap_or_default();
-                    let module_type = c["type"]
-                        .as_str()
-                        .map(|s| ModuleType::parse_from_str(s))
-                        .unwrap_or(ModuleType::Module);
-                    BlocklessModule {
-                        module_type,
-                        name,
-                        file,
-                        md5,
-                    }
-                })
-                .collect(),
-            _ => Vec::new(),
+        let cli_file = CliConfigFile::deserialize(&value)?;
+        let mut bc = BlocklessConfig::deserialize(value)?;
+        // the package can't choose the host file written after the run.
+        if opts.package && bc.output_car.is_some() {
+            bail!("the output_car of the package configure is not allowed, use --output-car");
         }
-    }
-
-    fn from_json_string(json_string: String) -> Result<Self> {
-        let json_obj = json::parse(&json_string)?;
-        let fs_root_path: Option<String> = json_obj["fs_root_path"].as_str().map(String::from);
-        let drivers_root_path: Option<String> =
-            json_obj["drivers_root_path"].as_str().map(String::from);
-        let limited_fuel: Option<u64> = json_obj["limited_fuel"].as_u64();
-        let runtime_logger = json_obj["runtime_logger"].as_str().map(String::from);
-        let runtime_logger_level = json_obj["runtime_logger_level"]
-            .as_str()
-            .map(LoggerLevel::from);
-        let limited_memory: Option<u64> = json_obj["limited_memory"].as_u64();
-        let extensions_path: Option<String> =
-            json_obj["extensions_path"].as_str().map(String::from);
-        let stdin: Option<&str> = json_obj["stdin"].as_str();
-        let stdout: Option<&str> = json_obj["stdout"].as_str();
-        let stderr: Option<&str> = json_obj["stderr"].as_str();
-        let debug_info: Option<bool> = json_obj["debug_info"].as_bool();
-        let run_time: Option<u64> = json_obj["run_time"].as_u64();
-
-        let drvs = Self::drivers(&json_obj["drivers"]);
-        let modules = Self::modules(&json_obj["modules"]);
-        let perms: Vec<Permission> = Self::permissions(&json_obj["permissions"]);
-        let entry: &str = json_obj["entry"].as_str().unwrap();
-        let version = json_obj["version"].as_usize();
-        let dirs = Self::map_dirs(&json_obj["map_dirs"])?;
-        let mut bc = BlocklessConfig::new(entry);
-        //if has the optimize item.
-        if json_obj["optimize"].is_object() {
-            bc.opts = Self::optimize_options(&json_obj["optimize"])?;
-        }
-        bc.set_map_dirs(dirs);
-        bc.set_modules(modules);
-        bc.extensions_path(extensions_path);
-        bc.set_fs_root_path(fs_root_path);
-        bc.drivers(drvs);
-        // the set debug mode
-        debug_info.map(|b| bc.set_debug_info(b));
-        runtime_logger_level.map(|l| bc.set_runtime_logger_level(l));
-        bc.set_permisions(perms);
-        bc.set_runtime_logger(runtime_logger);
-        bc.set_drivers_root_path(drivers_root_path);
-        bc.limited_fuel(limited_fuel);
-        bc.limited_memory(limited_memory);
-        bc.set_run_time(run_time);
-        version.map(|v| bc.set_version(v.into()));
-        let stdin = match stdin {
-            Some(s) => {
-                if s == "inherit" {
-                    Stdin::Inherit
-                } else {
-                    Stdin::Fixed(s.to_string())
-                }
+        if let Some(env_file) = cli_file.env_file {
+            if opts.package {
+                bail!("the env_file of the package configure is not allowed");
             }
-            _ => Stdin::Fixed(String::new()),
-        };
-        bc.stdio = Stdio {
-            stdin,
-            stdout: stdio_cfg!(stdout, Stdout, FileName),
-            stderr: stdio_cfg!(stderr, Stderr, FileName),
-        };
+            let mut envs = read_env_file(&env_file)?
+                .into_iter()
+                .filter(|(key, _)| !bc.envs_ref().iter().any(|(k, _)| k == key))
+                .collect::<Vec<_>>();
+            envs.extend(bc.envs_ref().iter().cloned());
+            envs.sort_by(|(a_key, _), (b_key, _)| a_key.cmp(b_key));
+            bc.set_envs(envs);
+        }
         Ok(CliConfig(bc))
     }
 
-    pub fn from_data(data: String, root_suffix: Option<String>) -> Result<Self> {
+    pub fn from_data(
+        data: String,
+        root_suffix: Option<String>,
+        opts: &LoadOptions,
+    ) -> Result<Self> {
         let data = replace_vars(data, root_suffix)?;
-        Self::from_json_string(data)
+        // the configure of the CAR package is untrusted.
+        let opts = LoadOptions {
+            package: true,
+            ..opts.clone()
+        };
+        Self::from_value(ConfigFormat::Json.parse(&data)?, &opts)
     }
 
-    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
-        let values = fs::read_to_string(path)?;
-        let json_string = replace_vars(values, None)?;
-        Self::from_json_string(json_string)
+    /// load the configure file with its `extends` chain, the format is detected
+    /// by the file extension, json is the default.
+    pub fn from_file(path: impl AsRef<Path>, opts: &LoadOptions) -> Result<Self> {
+        Self::from_value(load_layers(path)?, opts)
     }
 }
 
@@ -288,49 +298,88 @@ where
     call(raw_json, root_suffix)
 }
 
-#[allow(dead_code)]
-pub(crate) fn load_cli_config_from_car<T>(car_reader: &mut T) -> Result<CliConfig>
+pub(crate) fn load_cli_config_from_car<T>(
+    car_reader: &mut T,
+    opts: &LoadOptions,
+) -> Result<CliConfig>
 where
     T: CarReader,
 {
-    let rs = load_from_car(car_reader, new_cliconfig);
+    let rs = load_from_car(car_reader, |raw_json, root_suffix| {
+        new_cliconfig(raw_json, root_suffix, opts)
+    });
     rs.map(|r| match r {
         Config::CliConfig(c) => c,
         _ => unreachable!("can be reach!"),
     })
 }
 
-pub(crate) fn load_extract_from_car<F>(f: File, call: F) -> Result<Config>
+pub(crate) fn load_extract_from_car<R, F>(f: R, reuse: bool, call: F) -> Result<Config>
 where
+    R: Read + Seek,
     F: Fn(String, Option<String>) -> Result<Config>,
 {
+    // the extracted package is reused by the root cid, the root cid of the header
+    // is trusted only if all the blocks match their cids.
+    let mut f = f;
+    verify_car_blocks(&mut f)?;
+    f.rewind()?;
     let mut reader = reader::new_v1(f)?;
     let cfg = load_from_car(&mut reader, call)?;
     let header = reader.header();
     let rootfs = cfg
         .root_path()
         .expect("root path must be config in car file");
+    // the package extracted by the previous run of the same cid is reused.
+    let workspace = Workspace::new(rootfs);
     for rcid in header.roots() {
-        let root_path: PathBuf = rootfs.into();
-        let root_path = root_path.join(rcid.to_string());
-        if !root_path.exists() {
-            let msg = format!("create directory error: {}", root_path.to_str().unwrap());
-            fs::create_dir(&root_path).expect(&msg);
-        }
-
-        extract_ipld(&mut reader, rcid, Some(root_path))?;
+        workspace.prepare_package(&rcid.to_string(), reuse, |path| {
+            extract_ipld(&mut reader, rcid, Some(path.to_path_buf()))?;
+            Ok(())
+        })?;
     }
     Ok(cfg)
 }
 
-fn new_cliconfig(raw_json: String, root_suffix: Option<String>) -> Result<Config> {
-    let mut cli_cfg = CliConfig::from_data(raw_json, root_suffix)?;
+fn new_cliconfig(
+    raw_json: String,
+    root_suffix: Option<String>,
+    opts: &LoadOptions,
+) -> Result<Config> {
+    let mut cli_cfg = CliConfig::from_data(raw_json, root_suffix, opts)?;
     cli_cfg.0.set_is_carfile(true);
     Ok(Config::CliConfig(cli_cfg))
 }
 
-pub(crate) fn load_cli_config_extract_from_car(f: File) -> Result<CliConfig> {
-    let rs = load_extract_from_car(f, new_cliconfig);
+/// load the configure from the car file, the package is served from the memory
+/// at `$ROOT` instead of being extracted into `ENV_ROOT_PATH/<cid>`.
+pub(crate) fn load_cli_config_in_memory_from_car<R>(f: R, opts: &LoadOptions) -> Result<CliConfig>
+where
+    R: Read + Seek + Send + 'static,
+{
+    let mut reader = reader::new_v1(f)?;
+    let mut cli_cfg = load_cli_config_from_car(&mut reader, opts)?;
+    let root_cid = match reader.header().roots().first() {
+        Some(cid) => *cid,
+        None => bail!("the car file has no root"),
+    };
+    let root_path = replace_vars("$ROOT".to_string(), Some(root_cid.to_string()))?;
+    // the files are read from the car blocks on demand.
+    let dir = car_root_dir(reader)?;
+    cli_cfg.0.set_package(Some(PackageFs::new(root_path, dir)));
+    Ok(cli_cfg)
+}
+
+/// the signed package is extracted again on each run, the files of the extracted package
+/// on the disk are not covered by the signature.
+pub(crate) fn load_cli_config_extract_from_car<R>(f: R, opts: &LoadOptions) -> Result<CliConfig>
+where
+    R: Read + Seek,
+{
+    let reuse = !opts.signature_policy.is_enforced();
+    let rs = load_extract_from_car(f, reuse, |raw_json, root_suffix| {
+        new_cliconfig(raw_json, root_suffix, opts)
+    });
     rs.map(|r| match r {
         Config::CliConfig(c) => c,
         _ => unreachable!("can be reach!"),
@@ -341,6 +390,7 @@ pub(crate) fn load_cli_config_extract_from_car(f: File) -> Result<CliConfig> {
 mod test {
     #![allow(unused)]
     use std::ffi::OsString;
+    use std::fs::File;
 
     use blockless::BlocklessConfigVersion;
 
@@ -406,7 +456,7 @@ mod test {
         .to_string();
 
         std::env::set_var("ENV_ROOT_PATH", "target");
-        let config = CliConfig::from_data(data, None).unwrap();
+        let config = CliConfig::from_data(data, None, &LoadOptions::default()).unwrap();
         assert!(matches!(
             config.0.version(),
             BlocklessConfigVersion::Version1
@@ -476,4 +526,289 @@ mod test {
         .0;
         assert_eq!(bls_config.fix_stdin_ref(), Some("test"));
     }
+
+    #[test]
+    fn test_optimize_from_json() {
+        let bls_config = CliConfig::from_json_string(
+            r#"{
+                "entry": "lib.wasm",
+                "optimize": {
+                    "opt_level": "ss",
+                    "memory_may_move": false,
+                    "memory_reservation": 4096
+                }
+            }"#
+            .to_string(),
+        )
+        .unwrap()
+        .0;
+        assert_eq!(bls_config.opts.memory_may_move, Some(false));
+        assert_eq!(bls_config.opts.memory_reservation, Some(4096));
+    }
+
+    #[test]
+    fn test_cli_options_from_json() {
+        let mut env_file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut env_file, b"A=file\nB=file\n").unwrap();
+        let data = format!(
+            r#"{{
+                "entry": "lib.wasm",
+                "env_file": "{}",
+                "envs": {{"B": "config"}},
+                "args": ["--port", "8080"],
+                "feature_thread": true,
+                "nn": true,
+                "nn_graph": [{{"format": "openvino", "dir": "/models"}}],
+                "tcp_listens": ["127.0.0.1:8080", "127.0.0.1:8081::4"],
+                "unknown_imports_trap": true,
+                "coredump": "app.coredump",
+                "max_memory_size": 1024,
+                "allow_read": ["/data"],
+                "allow_all": true
+            }}"#,
+            env_file.path().display()
+        );
+        let bls_config = CliConfig::from_json_string(data).unwrap().0;
+        assert_eq!(
+            bls_config.envs_ref(),
+            &vec![
+                ("A".to_string(), "file".to_string()),
+                ("B".to_string(), "config".to_string()),
+            ]
+        );
+        assert_eq!(bls_config.stdin_args_ref().len(), 2);
+        assert!(bls_config.feature_thread());
+        assert!(bls_config.nn);
+        assert_eq!(bls_config.nn_graph.len(), 1);
+        assert_eq!(bls_config.tcp_listens.len(), 2);
+        assert_eq!(bls_config.tcp_listens[1].1, Some(4));
+        assert!(bls_config.unknown_imports_trap);
+        assert_eq!(bls_config.coredump.as_deref(), Some("app.coredump"));
+        assert_eq!(bls_config.store_limited().max_memory_size, Some(1024));
+        assert!(bls_config.permissions_config.allow_read.is_some());
+        assert!(bls_config.permissions_config.allow_all);
+    }
+
+    #[test]
+    fn test_missing_env_file() {
+        let data = r#"{"entry": "lib.wasm", "env_file": "/not/exists/.env"}"#;
+        let err = CliConfig::from_json_string(data.to_string()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the env file is not found: /not/exists/.env"
+        );
+        let err = LoadOptions::new(Some(Path::new("/not/exists/.env")), None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the env file is not found: /not/exists/.env"
+        );
+    }
+
+    #[test]
+    fn test_malformed_env_file() {
+        let mut env_file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut env_file, b"# comment\nA=1\nB C\n").unwrap();
+        let err = read_env_file(env_file.path()).unwrap_err();
+        let expected = format!("{}:3: malformed line", env_file.path().display());
+        assert!(err.to_string().starts_with(&expected), "{err}");
+    }
+
+    #[test]
+    fn test_config_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_file = dir.path().join("app.toml");
+        fs::write(
+            &toml_file,
+            r#"
+# the app entry
+entry = "lib.wasm"
+limited_fuel = 200000000
+permissions = ["http://httpbin.org/anything"]
+
+[envs]
+RUST_LOG = "info"
+
+[[map_dirs]]
+host = "/tmp"
+guest = "/data"
+"#,
+        )
+        .unwrap();
+        let yaml_file = dir.path().join("app.yml");
+        fs::write(
+            &yaml_file,
+            r#"
+# the app entry
+entry: lib.wasm
+limited_fuel: 200000000
+permissions:
+  - http://httpbin.org/anything
+envs:
+  RUST_LOG: info
+map_dirs:
+  - host: /tmp
+    guest: /data
+"#,
+        )
+        .unwrap();
+        for file in [toml_file, yaml_file] {
+            let config = CliConfig::from_file(&file, &LoadOptions::default())
+                .unwrap()
+                .0;
+            assert_eq!(config.entry_ref(), "lib.wasm");
+            assert_eq!(config.get_limited_fuel(), Some(200000000));
+            assert_eq!(config.permisions_ref().len(), 1);
+            assert_eq!(
+                config.envs_ref(),
+                &vec![("RUST_LOG".to_string(), "info".to_string())]
+            );
+            assert_eq!(config.dirs, vec![("/tmp".into(), "/data".into())]);
+        }
+    }
+
+    #[test]
+    fn test_config_format_convert() {
+        let json = ConfigFormat::Json
+            .parse(r#"{"entry": "lib.wasm", "modules": [{"file": "$ROOT/lib.wasm"}]}"#)
+            .unwrap();
+        for format in [ConfigFormat::Toml, ConfigFormat::Yaml, ConfigFormat::Json] {
+            let text = format.render(&json).unwrap();
+            assert_eq!(format.parse(&text).unwrap(), json);
+        }
+    }
+
+    #[test]
+    fn test_from_json_without_entry() {
+        let rs = CliConfig::from_json_string(r#"{"fs_root_path": "/"}"#.to_string());
+        assert!(rs.is_err());
+    }
+
+    #[test]
+    fn test_config_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("base.json"),
+            r#"{
+                "entry": "main.wasm",
+                "limited_fuel": 100,
+                "permissions": ["http://a.com/"],
+                "profiles": {"prod": {"limited_fuel": 300, "permissions+": ["http://${BLS_TEST_LAYER_HOST}/"]}}
+            }"#,
+        )
+        .unwrap();
+        let file = dir.path().join("app.yaml");
+        fs::write(
+            &file,
+            "extends: base.json\nlimited_fuel: 200\nvars:\n  BLS_TEST_LAYER_HOST: prod.com\n",
+        )
+        .unwrap();
+
+        let bls_config = CliConfig::from_file(&file, &LoadOptions::default())
+            .unwrap()
+            .0;
+        assert_eq!(bls_config.get_limited_fuel(), Some(200));
+        assert_eq!(bls_config.permisions_ref().len(), 1);
+
+        let opts = LoadOptions {
+            profile: Some("prod".to_string()),
+            ..Default::default()
+        };
+        let bls_config = CliConfig::from_file(&file, &opts).unwrap().0;
+        assert_eq!(bls_config.get_limited_fuel(), Some(300));
+        let urls: Vec<_> = bls_config.permisions_ref().iter().map(|p| &p.url).collect();
+        assert_eq!(urls, vec!["http://a.com/", "http://prod.com/"]);
+
+        let opts = LoadOptions {
+            profile: Some("dev".to_string()),
+            ..Default::default()
+        };
+        let rs = CliConfig::from_file(&file, &opts);
+        assert!(rs.err().unwrap().downcast::<ConfigIssues>().is_ok());
+    }
+
+    #[test]
+    fn test_package_config_untrusted() {
+        std::env::set_var("BLS_TEST_PACKAGE_SECRET", "secret");
+        let data = r#"{"entry": "main.wasm", "envs": {"X": "${BLS_TEST_PACKAGE_SECRET}"}}"#;
+        let opts = LoadOptions::default();
+        // the package config can't read the host environment.
+        let rs = CliConfig::from_data(data.to_string(), None, &opts);
+        assert!(rs.err().unwrap().downcast::<ConfigIssues>().is_ok());
+        let data = r#"{"entry": "main.wasm", "env_file": "/etc/app.env"}"#;
+        let rs = CliConfig::from_data(data.to_string(), None, &opts);
+        assert!(rs.err().unwrap().to_string().contains("env_file"));
+        let data = r#"{"entry": "main.wasm", "output_dir": "/out", "output_car": "/etc/x"}"#;
+        let rs = CliConfig::from_data(data.to_string(), None, &opts);
+        assert!(rs.err().unwrap().to_string().contains("output_car"));
+    }
+
+    #[test]
+    fn test_config_interpolation() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.toml");
+        fs::write(
+            &file,
+            r#"
+entry = "${APP_DIR}/main.wasm"
+permissions = ["http://${BLS_TEST_INTERPOLATION_HOST}:${PORT:-8080}/"]
+
+[vars]
+APP_DIR = "/srv/${APP}"
+APP = "demo"
+
+[envs]
+API = "$${NOT_A_VAR}"
+"#,
+        )
+        .unwrap();
+        let opts = LoadOptions {
+            vars: Variables::new(vec![(
+                "BLS_TEST_INTERPOLATION_HOST".to_string(),
+                "example.com".to_string(),
+            )]),
+            ..Default::default()
+        };
+        let bls_config = CliConfig::from_file(&file, &opts).unwrap().0;
+        assert_eq!(bls_config.entry_ref(), "/srv/demo/main.wasm");
+        assert_eq!(
+            bls_config.permisions_ref()[0].url,
+            "http://example.com:8080/"
+        );
+        assert_eq!(
+            bls_config.envs_ref(),
+            &[("API".to_string(), "${NOT_A_VAR}".to_string())]
+        );
+
+        let rs = CliConfig::from_file(&file, &LoadOptions::default());
+        let issues = rs.err().unwrap().downcast::<ConfigIssues>().unwrap();
+        assert_eq!(issues.0[0].path, "permissions[0]");
+    }
+
+    #[test]
+    fn test_load_car_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("main.wasm"), b"main").unwrap();
+        fs::create_dir_all(dir.path().join("data/img")).unwrap();
+        fs::write(dir.path().join("data/img/logo.png"), b"logo").unwrap();
+        let config = dir.path().join("app.json");
+        fs::write(
+            &config,
+            format!(r#"{{"entry": "{}/main.wasm"}}"#, dir.path().display()),
+        )
+        .unwrap();
+        let opts = LoadOptions::default();
+        let package =
+            crate::pack::stage_package(&config, &opts, &[dir.path().join("data")]).unwrap();
+        let car = dir.path().join("app.car");
+        package.write_car(File::create(&car).unwrap()).unwrap();
+
+        let cli_cfg = load_cli_config_in_memory_from_car(File::open(&car).unwrap(), &opts).unwrap();
+        let package = cli_cfg.0.package_ref().unwrap();
+        let entry = package.read_file(cli_cfg.0.entry_ref()).unwrap().unwrap();
+        assert_eq!(entry, b"main");
+        let logo = package.read_file(package.root().join("data/img/logo.png"));
+        assert_eq!(logo.unwrap().unwrap(), b"logo");
+        // nothing is extracted to the disk.
+        assert!(!package.root().exists());
+    }
 }
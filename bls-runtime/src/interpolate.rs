@@ -0,0 +1,255 @@
+use std::collections::BTreeMap;
+
+use serde_json::{Map, Value};
+
+use crate::validate::{join_path, ConfigIssue, ConfigIssues};
+
+/// The key of the variables defined in the configure.
+pub(crate) const VARS_KEY: &str = "vars";
+
+/// The variables used by the `${VAR}` interpolation, looked up in order:
+/// the `--env-file` variables, the host environment, the `vars` of the configure.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Variables {
+    env_file: Vec<(String, String)>,
+    config: BTreeMap<String, String>,
+    // the host environment is not looked up for the untrusted configure.
+    no_host_env: bool,
+}
+
+impl Variables {
+    pub fn new(env_file: Vec<(String, String)>) -> Self {
+        Self {
+            env_file,
+            config: BTreeMap::new(),
+            no_host_env: false,
+        }
+    }
+
+    /// the variables of the configure in the CAR package, the package can't read
+    /// the host environment, only the `--env-file` and its own `vars`.
+    pub fn without_host_env(&self) -> Self {
+        Self {
+            no_host_env: true,
+            ..self.clone()
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        if let Some((_, v)) = self.env_file.iter().rev().find(|(k, _)| k == name) {
+            return Some(v.clone());
+        }
+        if !self.no_host_env {
+            if let Ok(v) = std::env::var(name) {
+                return Some(v);
+            }
+        }
+        self.config.get(name).cloned()
+    }
+}
+
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+/// interpolate `${VAR}` and `${VAR:-default}` in the string, `$${` is the escape of `${`.
+pub(crate) fn interpolate_str<F>(s: &str, lookup: F) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if rest.starts_with("$${") {
+            result.push_str("${");
+            rest = &rest[3..];
+        } else if rest.starts_with("${") {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| format!("unterminated variable in `{s}`"))?;
+            let expr = &rest[2..end];
+            let (name, default) = match expr.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (expr, None),
+            };
+            if !is_var_name(name) {
+                return Err(format!("invalid variable name `{name}` in `{s}`"));
+            }
+            match lookup(name).or_else(|| default.map(String::from)) {
+                Some(val) => result.push_str(&val),
+                None => return Err(format!("unresolved variable `{name}`")),
+            }
+            rest = &rest[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn interpolate_value(
+    value: &mut Value,
+    path: &str,
+    vars: &Variables,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    match value {
+        Value::String(s) => match interpolate_str(s, |name| vars.lookup(name)) {
+            Ok(v) => *s = v,
+            Err(msg) => issues.push(ConfigIssue::new(path, msg)),
+        },
+        Value::Array(list) => {
+            for (i, item) in list.iter_mut().enumerate() {
+                interpolate_value(item, &join_path(path, &format!("[{i}]")), vars, issues);
+            }
+        }
+        Value::Object(obj) => {
+            for (key, item) in obj.iter_mut() {
+                interpolate_value(item, &join_path(path, key), vars, issues);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// resolve the `vars` of the configure, a variable can refer to the other variables,
+/// the unresolved variables are reported after no more variable can be resolved.
+fn resolve_config_vars(
+    config_vars: &Map<String, Value>,
+    vars: &mut Variables,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    let mut pending: Vec<(&String, &str)> = Vec::new();
+    for (key, value) in config_vars.iter() {
+        match value.as_str() {
+            Some(s) => pending.push((key, s)),
+            None => issues.push(ConfigIssue::new(
+                join_path(VARS_KEY, key),
+                "the variable should be a string",
+            )),
+        }
+    }
+    loop {
+        let mut errors = Vec::new();
+        let count = pending.len();
+        pending.retain(
+            |(key, s)| match interpolate_str(s, |name| vars.lookup(name)) {
+                Ok(v) => {
+                    vars.config.insert(key.to_string(), v);
+                    false
+                }
+                Err(msg) => {
+                    errors.push(ConfigIssue::new(join_path(VARS_KEY, key), msg));
+                    true
+                }
+            },
+        );
+        if pending.is_empty() || pending.len() == count {
+            issues.extend(errors);
+            break;
+        }
+    }
+}
+
+/// interpolate all the string values of the configure with the variables,
+/// the `vars` key is removed from the configure after it's resolved.
+pub(crate) fn interpolate_config(value: &mut Value, vars: &Variables) -> Result<(), ConfigIssues> {
+    let mut issues = Vec::new();
+    let mut vars = vars.clone();
+    if let Value::Object(obj) = value {
+        match obj.remove(VARS_KEY) {
+            Some(Value::Object(config_vars)) => {
+                resolve_config_vars(&config_vars, &mut vars, &mut issues)
+            }
+            Some(_) => issues.push(ConfigIssue::new(VARS_KEY, "the vars should be an object")),
+            None => {}
+        }
+    }
+    interpolate_value(value, "", &vars, &mut issues);
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigIssues(issues))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some("example.com".into()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate_str() {
+        assert_eq!(
+            interpolate_str("http://${HOST}/api", lookup).unwrap(),
+            "http://example.com/api"
+        );
+        assert_eq!(interpolate_str("${PORT:-8080}", lookup).unwrap(), "8080");
+        assert_eq!(interpolate_str("${EMPTY:-x}", lookup).unwrap(), "");
+        assert_eq!(interpolate_str("$${HOST}", lookup).unwrap(), "${HOST}");
+        assert_eq!(interpolate_str("$ROOT/a$", lookup).unwrap(), "$ROOT/a$");
+        assert!(interpolate_str("${PORT}", lookup).is_err());
+        assert!(interpolate_str("${HOST", lookup).is_err());
+        assert!(interpolate_str("${}", lookup).is_err());
+    }
+
+    #[test]
+    fn test_interpolate_config() {
+        let mut value = json!({
+            "vars": {"APP": "demo", "DIR": "/srv/${APP}"},
+            "entry": "${DIR}/main.wasm",
+            "permissions": ["http://${BLS_TEST_API_HOST:-localhost}/"],
+            "envs": {"MODE": "${BLS_TEST_MODE}"},
+            "limited_fuel": 100,
+        });
+        let vars = Variables::new(vec![("BLS_TEST_MODE".into(), "staging".into())]);
+        interpolate_config(&mut value, &vars).unwrap();
+        assert_eq!(value["entry"], "/srv/demo/main.wasm");
+        assert_eq!(value["permissions"][0], "http://localhost/");
+        assert_eq!(value["envs"]["MODE"], "staging");
+        assert_eq!(value["limited_fuel"], 100);
+    }
+
+    #[test]
+    fn test_interpolate_config_unresolved() {
+        let mut value = json!({
+            "entry": "main.wasm",
+            "modules": [{"file": "${BLS_TEST_NO_SUCH_VAR}/lib.wasm"}],
+        });
+        let issues = interpolate_config(&mut value, &Variables::default()).unwrap_err();
+        assert_eq!(issues.0.len(), 1);
+        assert_eq!(issues.0[0].path, "modules[0].file");
+    }
+
+    #[test]
+    fn test_interpolate_without_host_env() {
+        std::env::set_var("BLS_TEST_HOST_SECRET", "secret");
+        let value = json!({"envs": {"X": "${BLS_TEST_HOST_SECRET}"}});
+        let vars = Variables::new(vec![("BLS_TEST_FILE_VAR".into(), "file".into())]);
+        let mut host = value.clone();
+        interpolate_config(&mut host, &vars).unwrap();
+        assert_eq!(host["envs"]["X"], "secret");
+        // the package can't read the host environment.
+        let mut package = value.clone();
+        let issues = interpolate_config(&mut package, &vars.without_host_env()).unwrap_err();
+        assert_eq!(issues.0[0].path, "envs.X");
+        let mut package = json!({"envs": {"X": "${BLS_TEST_FILE_VAR}"}});
+        interpolate_config(&mut package, &vars.without_host_env()).unwrap();
+        assert_eq!(package["envs"]["X"], "file");
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    fs,
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use cid::Cid;
+use rust_car::reader::{self, CarReader};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// The gateway used if `--ipfs-gateway` is not set.
+pub(crate) const DEFAULT_GATEWAY: &str = "https://ipfs.io";
+
+/// the largest car file fetched from the gateway.
+const MAX_PACKAGE_SIZE: u64 = 256 << 20;
+
+/// the gateway not connected in the time is given up.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// the gateway sending nothing in the time is given up.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// the multihash codes of the block hashes can be verified.
+const IDENTITY: u64 = 0x00;
+const SHA2_256: u64 = 0x12;
+const BLAKE3: u64 = 0x1e;
+
+/// the cid of the input, `ipfs://<cid>` or the bare cid like `bafy...`,
+/// `None` if the input is a local file. The `ipfs://` url must hold a valid cid.
+pub(crate) fn input_cid(input: &str) -> Result<Option<Cid>> {
+    if let Some(cid) = input.strip_prefix("ipfs://") {
+        let cid = cid.trim_end_matches('/');
+        return Cid::try_from(cid)
+            .map(Some)
+            .with_context(|| format!("invalid cid `{cid}`"));
+    }
+    if Path::new(input).exists() {
+        return Ok(None);
+    }
+    Ok(Cid::try_from(input).ok())
+}
+
+/// Fetch the car files by the cid from the ipfs http gateway, the verified car files are
+/// cached by the cid, so the package is fetched only once.
+pub(crate) struct IpfsFetcher {
+    gateway: Url,
+    cache_dir: PathBuf,
+    max_size: u64,
+}
+
+impl IpfsFetcher {
+    pub fn new(gateway: &str, cache_dir: impl Into<PathBuf>) -> Result<Self> {
+        let gateway =
+            Url::parse(gateway).with_context(|| format!("invalid ipfs gateway `{gateway}`"))?;
+        Ok(Self {
+            gateway,
+            cache_dir: cache_dir.into(),
+            max_size: MAX_PACKAGE_SIZE,
+        })
+    }
+
+    /// the largest car file can be fetched, 256MiB by default.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// the car file is cached by the canonical form of the cid, so only the
+    /// verified car files written by the fetcher are found in the cache.
+    fn cache_path(&self, cid: &Cid) -> PathBuf {
+        self.cache_dir.join(format!("{cid}.car"))
+    }
+
+    /// the path of the car file of the cid, fetched from the gateway if it's not cached.
+    pub async fn fetch(&self, cid: &Cid) -> Result<PathBuf> {
+        let path = self.cache_path(cid);
+        if path.exists() {
+            // update the time of the last use.
+            let file = fs::File::options().append(true).open(&path)?;
+            file.set_modified(std::time::SystemTime::now())?;
+            return Ok(path);
+        }
+        let mut url = self.gateway.join(&format!("ipfs/{cid}"))?;
+        url.set_query(Some("format=car"));
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_TIMEOUT)
+            .build()?;
+        let mut resp = client
+            .get(url.clone())
+            .header(reqwest::header::ACCEPT, "application/vnd.ipld.car")
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .with_context(|| format!("failed to fetch {url}"))?;
+        if resp
+            .content_length()
+            .map_or(false, |len| len > self.max_size)
+        {
+            bail!("the car file is larger than {} bytes", self.max_size);
+        }
+        fs::create_dir_all(&self.cache_dir)?;
+        // the car file is moved into the cache when it's written and verified.
+        let tmp_path = path.with_extension(format!("car.{}", std::process::id()));
+        let rs = self.download(resp, cid, &tmp_path).await;
+        if rs.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        rs?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(path)
+    }
+
+    /// the body is streamed into the file in chunks, the gateway can't send more than
+    /// the limit, then the file is verified from the disk.
+    async fn download(&self, mut resp: reqwest::Response, cid: &Cid, path: &Path) -> Result<()> {
+        let mut file = fs::File::create(path)?;
+        let mut size = 0u64;
+        while let Some(chunk) = resp.chunk().await? {
+            size += chunk.len() as u64;
+            if size > self.max_size {
+                bail!("the car file is larger than {} bytes", self.max_size);
+            }
+            file.write_all(&chunk)?;
+        }
+        file.sync_all()?;
+        verify_car(&cid.to_string(), fs::File::open(path)?)
+    }
+}
+
+/// verify the car file is rooted at the cid and all the blocks match their cids.
+pub(crate) fn verify_car<R: Read + Seek>(cid: &str, car: R) -> Result<()> {
+    let mut reader = reader::new_v1(car)?;
+    let roots = reader.header().roots();
+    if !roots.iter().any(|root| root.to_string() == cid) {
+        bail!("the car file is not rooted at {cid}");
+    }
+    verify_blocks(&mut reader)
+}
+
+/// verify all the blocks of the car file match their cids, the roots of the
+/// verified car file can be trusted as the cids of the content.
+pub(crate) fn verify_car_blocks<R: Read + Seek>(car: R) -> Result<()> {
+    let mut reader = reader::new_v1(car)?;
+    verify_blocks(&mut reader)
+}
+
+/// the root blocks must be in the car file as well, the root without its block
+/// can't be run.
+fn verify_blocks(reader: &mut impl CarReader) -> Result<()> {
+    let mut block_cids = Vec::new();
+    for section in reader.sections() {
+        let block_cid = section.cid();
+        block_cids.push(block_cid);
+        let block = reader.read_section_data(&block_cid)?;
+        let hash = block_cid.hash();
+        let actual = match hash.code() {
+            IDENTITY => block.clone(),
+            SHA2_256 => Sha256::digest(&block).to_vec(),
+            BLAKE3 => blake3::hash(&block).as_bytes().to_vec(),
+            code => bail!("the hash 0x{code:x} of the block {block_cid} is not supported"),
+        };
+        if actual != hash.digest() {
+            bail!("the block {block_cid} does not match its cid");
+        }
+    }
+    for root in reader.header().roots().iter() {
+        if !block_cids.contains(root) {
+            bail!("the block of the root {root} is missing");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::LoadOptions;
+    use std::io::Cursor;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    fn pack_car(dir: &Path) -> (String, Vec<u8>) {
+        fs::write(dir.join("main.wasm"), b"main").unwrap();
+        let config = dir.join("app.json");
+        fs::write(
+            &config,
+            format!(r#"{{"entry": "{}/main.wasm"}}"#, dir.display()),
+        )
+        .unwrap();
+        let opts = LoadOptions::default();
+        let assets: &[PathBuf] = &[];
+        let package = crate::pack::stage_package(&config, &opts, assets).unwrap();
+        let mut car = Cursor::new(Vec::new());
+        let cid = package.write_car(&mut car).unwrap();
+        (cid, car.into_inner())
+    }
+
+    #[test]
+    fn test_input_cid() {
+        let v1 = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+        let cid = |input: &str| input_cid(input).unwrap().map(|cid| cid.to_string());
+        assert_eq!(cid(v1).as_deref(), Some(v1));
+        assert_eq!(cid(&format!("ipfs://{v1}/")).as_deref(), Some(v1));
+        let v0 = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+        assert_eq!(cid(v0).as_deref(), Some(v0));
+        assert_eq!(cid("app.car"), None);
+        assert_eq!(cid("bafy.wasm"), None);
+        // the path can't be built from an invalid cid.
+        assert!(input_cid("ipfs://../../etc/passwd").is_err());
+        assert!(input_cid("ipfs://bafy").is_err());
+    }
+
+    #[test]
+    fn test_verify_car() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cid, car) = pack_car(dir.path());
+        assert!(verify_car(&cid, Cursor::new(&car)).is_ok());
+        assert!(verify_car("bafyother", Cursor::new(&car)).is_err());
+        // the block of main.wasm is tampered.
+        let pos = car.windows(4).position(|w| w == b"main").unwrap();
+        let mut tampered = car.clone();
+        tampered[pos..pos + 4].copy_from_slice(b"evil");
+        assert!(verify_car(&cid, Cursor::new(&tampered)).is_err());
+        // the local car file is verified without the expected cid.
+        assert!(verify_car_blocks(Cursor::new(&car)).is_ok());
+        assert!(verify_car_blocks(Cursor::new(&tampered)).is_err());
+    }
+
+    /// the local stand-in of the gateway serves the car file once.
+    async fn serve_car(car: Vec<u8>) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let gateway = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            let head = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/vnd.ipld.car\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                car.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            // the client may close the connection of the oversized body.
+            let _ = stream.write_all(&car).await;
+            request
+        });
+        (gateway, server)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_from_gateway() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cid, car) = pack_car(dir.path());
+        let (gateway, server) = serve_car(car).await;
+        let cid = Cid::try_from(cid.as_str()).unwrap();
+        let cache_dir = dir.path().join("cache");
+        let fetcher = IpfsFetcher::new(&gateway, &cache_dir).unwrap();
+        let path = fetcher.fetch(&cid).await.unwrap();
+        assert_eq!(path, cache_dir.join(format!("{cid}.car")));
+        let request = server.await.unwrap();
+        assert!(request.starts_with(&format!("GET /ipfs/{cid}?format=car ")));
+        // the cached car file is used, the gateway is gone.
+        assert_eq!(fetcher.fetch(&cid).await.unwrap(), path);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_oversized() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cid, car) = pack_car(dir.path());
+        let max_size = car.len() as u64 - 1;
+        let (gateway, _server) = serve_car(car).await;
+        let cid = Cid::try_from(cid.as_str()).unwrap();
+        let cache_dir = dir.path().join("cache");
+        let fetcher = IpfsFetcher::new(&gateway, &cache_dir)
+            .unwrap()
+            .with_max_size(max_size);
+        assert!(fetcher.fetch(&cid).await.is_err());
+        assert!(!fetcher.cache_path(&cid).exists());
+    }
+}
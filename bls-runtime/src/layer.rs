@@ -0,0 +1,254 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+use crate::config::{replace_vars, ConfigFormat};
+use crate::validate::{join_path, ConfigIssue, ConfigIssues};
+
+/// The key of the base configure file.
+pub(crate) const EXTENDS_KEY: &str = "extends";
+
+/// The key of the named profiles.
+pub(crate) const PROFILES_KEY: &str = "profiles";
+
+/// merge the overlay into the base:
+/// 1. the objects are merged key by key.
+/// 2. the scalars and the lists of the overlay replace the base.
+/// 3. the list of `key+` is appended to the list of `key`.
+pub(crate) fn merge_value(base: &mut Value, overlay: Value, path: &str) -> Result<(), ConfigIssue> {
+    let (base, overlay) = match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => (base, overlay),
+        (base, overlay) => {
+            *base = overlay;
+            return Ok(());
+        }
+    };
+    for (key, value) in overlay {
+        if let Some(name) = key.strip_suffix('+') {
+            let path = join_path(path, &key);
+            let items = match value {
+                Value::Array(items) => items,
+                _ => {
+                    return Err(ConfigIssue::new(
+                        path,
+                        "the appended value should be a list",
+                    ))
+                }
+            };
+            match base.get_mut(name) {
+                Some(Value::Array(list)) => list.extend(items),
+                Some(_) => {
+                    return Err(ConfigIssue::new(path, format!("`{name}` is not a list")));
+                }
+                None => {
+                    base.insert(name.to_string(), Value::Array(items));
+                }
+            }
+            continue;
+        }
+        match base.get_mut(&key) {
+            Some(base_value) if base_value.is_object() && value.is_object() => {
+                merge_value(base_value, value, &join_path(path, &key))?
+            }
+            _ => {
+                base.insert(key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn load_layer(path: &Path, chain: &mut Vec<PathBuf>, raw: bool) -> Result<Value> {
+    let real_path =
+        fs::canonicalize(path).with_context(|| format!("failed to read {}", path.display()))?;
+    if chain.contains(&real_path) {
+        chain.push(real_path);
+        let files = chain
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        let issue = ConfigIssue::new(EXTENDS_KEY, format!("cyclic extends: {files}"));
+        return Err(ConfigIssues(vec![issue]).into());
+    }
+    chain.push(real_path);
+    let format = ConfigFormat::from_path(path).unwrap_or(ConfigFormat::Json);
+    let text =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let text = match raw {
+        true => text,
+        false => replace_vars(text, None)?,
+    };
+    let mut value = format
+        .parse(&text)
+        .map_err(|e| ConfigIssues(vec![ConfigIssue::new(".", e.to_string())]))?;
+    let extends = value
+        .as_object_mut()
+        .and_then(|obj| obj.remove(EXTENDS_KEY));
+    let mut base = match extends {
+        Some(Value::String(extends)) => {
+            let base_path = path.parent().unwrap_or(Path::new(".")).join(extends);
+            load_layer(&base_path, chain, raw)?
+        }
+        Some(_) => {
+            let issue = ConfigIssue::new(EXTENDS_KEY, "should be the path of the base configure");
+            return Err(ConfigIssues(vec![issue]).into());
+        }
+        None => Value::Object(Map::new()),
+    };
+    merge_value(&mut base, value, "").map_err(|issue| ConfigIssues(vec![issue]))?;
+    chain.pop();
+    Ok(base)
+}
+
+/// load the configure file and the base files in the `extends` chain,
+/// the path of `extends` is relative to the file extending it.
+pub(crate) fn load_layers(path: impl AsRef<Path>) -> Result<Value> {
+    load_layer(path.as_ref(), &mut Vec::new(), false)
+}
+
+/// load the layers as written, the `$ROOT` and the `$ENV_ROOT_PATH` are not replaced.
+pub(crate) fn load_raw_layers(path: impl AsRef<Path>) -> Result<Value> {
+    load_layer(path.as_ref(), &mut Vec::new(), true)
+}
+
+/// merge the selected profile into the configure, the `profiles` key is removed.
+pub(crate) fn select_profile(value: &mut Value, profile: Option<&str>) -> Result<(), ConfigIssues> {
+    let profiles = match value
+        .as_object_mut()
+        .and_then(|obj| obj.remove(PROFILES_KEY))
+    {
+        Some(Value::Object(profiles)) => profiles,
+        Some(_) => {
+            let issue = ConfigIssue::new(PROFILES_KEY, "the profiles should be an object");
+            return Err(ConfigIssues(vec![issue]));
+        }
+        None => Map::new(),
+    };
+    let name = match profile {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    match profiles.get(name) {
+        Some(overlay) => {
+            // the keys of the profile are merged as the top level keys.
+            merge_value(value, overlay.clone(), "").map_err(|mut issue| {
+                issue.path = join_path(&join_path(PROFILES_KEY, name), &issue.path);
+                ConfigIssues(vec![issue])
+            })
+        }
+        None => {
+            let mut names = profiles.keys().map(String::as_str).collect::<Vec<_>>();
+            names.sort();
+            let issue = ConfigIssue::new(
+                PROFILES_KEY,
+                format!(
+                    "unknown profile `{name}`, the profiles are: {}",
+                    names.join(", ")
+                ),
+            );
+            Err(ConfigIssues(vec![issue]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_value() {
+        let mut base = json!({
+            "entry": "main.wasm",
+            "limited_fuel": 100,
+            "permissions": ["http://a.com/"],
+            "args": ["--verbose"],
+            "envs": {"A": "1", "B": "1"},
+        });
+        let overlay = json!({
+            "limited_fuel": 200,
+            "permissions+": ["http://b.com/"],
+            "args": ["--quiet"],
+            "envs": {"B": "2"},
+            "map_dirs+": [{"host": "/tmp", "guest": "/tmp"}],
+        });
+        merge_value(&mut base, overlay, "").unwrap();
+        assert_eq!(
+            base,
+            json!({
+                "entry": "main.wasm",
+                "limited_fuel": 200,
+                "permissions": ["http://a.com/", "http://b.com/"],
+                "args": ["--quiet"],
+                "envs": {"A": "1", "B": "2"},
+                "map_dirs": [{"host": "/tmp", "guest": "/tmp"}],
+            })
+        );
+
+        let issue = merge_value(&mut base, json!({"entry+": ["x"]}), "").unwrap_err();
+        assert_eq!(issue.path, "entry+");
+    }
+
+    #[test]
+    fn test_select_profile() {
+        let config = json!({
+            "entry": "main.wasm",
+            "limited_fuel": 100,
+            "permissions": ["http://a.com/"],
+            "profiles": {
+                "prod": {"limited_fuel": 200, "permissions+": ["http://b.com/"]},
+            },
+        });
+        let mut value = config.clone();
+        select_profile(&mut value, None).unwrap();
+        assert_eq!(value.get(PROFILES_KEY), None);
+        assert_eq!(value["limited_fuel"], 100);
+
+        let mut value = config.clone();
+        select_profile(&mut value, Some("prod")).unwrap();
+        assert_eq!(value["limited_fuel"], 200);
+        assert_eq!(
+            value["permissions"],
+            json!(["http://a.com/", "http://b.com/"])
+        );
+
+        let mut value = config.clone();
+        let issues = select_profile(&mut value, Some("dev")).unwrap_err();
+        assert_eq!(issues.0[0].path, PROFILES_KEY);
+    }
+
+    #[test]
+    fn test_load_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("base")).unwrap();
+        fs::write(
+            dir.path().join("base/base.toml"),
+            "entry = \"main.wasm\"\nlimited_fuel = 100\npermissions = [\"http://a.com/\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("app.json"),
+            r#"{"extends": "base/base.toml", "limited_fuel": 200, "permissions+": ["http://b.com/"]}"#,
+        )
+        .unwrap();
+        let value = load_layers(dir.path().join("app.json")).unwrap();
+        assert_eq!(value["entry"], "main.wasm");
+        assert_eq!(value["limited_fuel"], 200);
+        assert_eq!(
+            value["permissions"],
+            json!(["http://a.com/", "http://b.com/"])
+        );
+        assert_eq!(value.get(EXTENDS_KEY), None);
+
+        fs::write(dir.path().join("a.json"), r#"{"extends": "b.json"}"#).unwrap();
+        fs::write(dir.path().join("b.json"), r#"{"extends": "a.json"}"#).unwrap();
+        let err = load_layers(dir.path().join("a.json")).unwrap_err();
+        let issues = err.downcast::<ConfigIssues>().unwrap();
+        assert!(issues.0[0].message.starts_with("cyclic extends"));
+    }
+}
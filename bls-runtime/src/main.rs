@@ -3,26 +3,48 @@ mod macros;
 mod cli_clap;
 mod config;
 mod error;
+mod interpolate;
+mod ipfs;
+mod layer;
+mod pack;
 mod plog;
 mod v86;
 mod v86config;
-use blockless::{blockless_run, LoggerLevel, Stdin};
+mod validate;
+mod workspace;
+use blockless::{
+    blockless_run,
+    digest::DigestError,
+    signature::{self, SignatureError},
+    LoggerLevel, Stderr, Stdin, Stdout,
+};
 #[allow(unused_imports)]
 use clap::Parser;
 use clap::{CommandFactory, FromArgMatches};
-use cli_clap::{CliCommandOpts, RuntimeType};
-use config::load_cli_config_extract_from_car;
+use cli_clap::{CliCommandOpts, CliSubCommand, ConfigSubCommand, RuntimeType};
 #[allow(unused_imports)]
 use config::CliConfig;
+use config::{
+    convert_config_file, load_cli_config_extract_from_car, load_cli_config_from_car,
+    load_cli_config_in_memory_from_car, ConfigFormat, LoadOptions,
+};
 use env_logger::Target;
 use error::CliExitCode;
-use log::{error, info, LevelFilter};
+use ipfs::{input_cid, IpfsFetcher};
+use log::{error, info, warn, LevelFilter};
+use pack::stage_package;
 use std::fs;
 use std::path::Path;
 use std::process::exit;
-use std::{io::Read, path::PathBuf, time::Duration};
+use std::{
+    io::{Cursor, Read},
+    path::PathBuf,
+    time::Duration,
+};
 use v86::V86Lib;
 use v86config::load_v86conf_extract_from_car;
+use validate::{validate_files, validate_input, ConfigIssues};
+use workspace::{Retention, Workspace};
 
 const ENV_ROOT_PATH_NAME: &str = "ENV_ROOT_PATH";
 
@@ -73,59 +95,34 @@ fn logger_init(
     Ok(())
 }
 
-fn file_md5(f: impl AsRef<Path>) -> Result<String, CliExitCode> {
-    let mut file = fs::OpenOptions::new().read(true).open(f).map_err(|_e| {
-        CliExitCode::UnknownError(
-            "the module file either does not exist or is inaccessible.".into(),
-        )
-    })?;
-    let mut buf = vec![0u8; 2048];
-    let mut md5_ctx = md5::Context::new();
-    loop {
-        let n = file.read(&mut buf).map_err(|_e| {
-            CliExitCode::UnknownError(
-                "the module file either does not exist or is inaccessible.".into(),
-            )
-        })?;
-        if n == 0 {
-            break;
-        }
-        md5_ctx.consume(&buf[..n])
-    }
-    let digest = md5_ctx.compute();
-    Ok(format!("{digest:x}"))
-}
-
-fn check_module_sum(cfg: &CliConfig) -> Result<(), CliExitCode> {
-    for module in cfg.0.modules_ref() {
-        let m_file = &module.file;
-        let md5sum = file_md5(m_file)?;
-        if md5sum != module.md5 {
-            perror!("the module {m_file} file md5 checksum is not correctly.");
-            return Err(CliExitCode::ConfigureError);
-        }
-    }
-    Ok(())
-}
-
 /// the cli support 3 type file,
 /// 1. the car file format, all files archive into the car file.
 /// 2. the wasm or wasi file format, will run wasm directly.
 /// 3. the the config file, format, all files is define in the config file.
-fn load_cli_config(file_path: &str) -> Result<CliConfig, CliExitCode> {
+fn load_cli_config(file_path: &str, opts: &LoadOptions) -> Result<CliConfig, CliExitCode> {
     let ext = Path::new(file_path).extension();
     let cfg = ext.and_then(|ext| ext.to_str().map(str::to_ascii_lowercase));
     let cli_config = match cfg {
         Some(ext) if ext == "car" => {
-            let file = fs::OpenOptions::new()
-                .read(true)
-                .open(file_path)
-                .map_err(|_e| {
-                    CliExitCode::UnknownError(
-                        "the car file does not exist or is unreadable.".into(),
-                    )
-                })?;
-            Some(load_cli_config_extract_from_car(file))
+            if !Path::new(file_path).is_file() {
+                return Err(CliExitCode::UnknownError(
+                    "the car file does not exist or is unreadable.".into(),
+                ));
+            }
+            // the package is loaded from the checked content, not the file read again.
+            let content = match opts.signature_policy.check_car(file_path) {
+                Ok(content) => content,
+                Err(e) => {
+                    perror!("failed to verify the car file {file_path}: {e}");
+                    return Err(CliExitCode::ConfigureError);
+                }
+            };
+            let file = Cursor::new(content);
+            if opts.in_memory {
+                Some(load_cli_config_in_memory_from_car(file, opts))
+            } else {
+                Some(load_cli_config_extract_from_car(file, opts))
+            }
         }
         Some(ext) if ext == "wasm" || ext == "wasi" || ext == "wat" => {
             Some(Ok(CliConfig::new_with_wasm(file_path)))
@@ -133,8 +130,14 @@ fn load_cli_config(file_path: &str) -> Result<CliConfig, CliExitCode> {
         _ => None,
     };
     cli_config
-        .unwrap_or_else(|| CliConfig::from_file(file_path))
-        .map_err(|e| CliExitCode::UnknownError(e.to_string()))
+        .unwrap_or_else(|| CliConfig::from_file(file_path, opts))
+        .map_err(|e| match e.downcast_ref::<ConfigIssues>() {
+            Some(issues) => {
+                perror!("{}", issues);
+                CliExitCode::ConfigureError
+            }
+            None => CliExitCode::UnknownError(e.to_string()),
+        })
 }
 
 fn v86_runtime(path: &str) -> Result<i32, CliExitCode> {
@@ -157,13 +160,25 @@ fn v86_runtime(path: &str) -> Result<i32, CliExitCode> {
 }
 
 async fn wasm_runtime(mut cfg: CliConfig, cli_command_opts: CliCommandOpts) -> CliExitCode {
+    if let Err(err) = cli_command_opts.into_config(&mut cfg) {
+        perror!("failed to merge the command line options: {}", err);
+        return CliExitCode::ConfigureError;
+    }
+    let issues = validate_files(&cfg.0);
+    if !issues.is_empty() {
+        perror!("{}", ConfigIssues(issues));
+        return CliExitCode::ConfigureError;
+    }
+    if let Err(err) = use_run_dir(&mut cfg) {
+        perror!("failed to create the run directory: {}", err);
+        return CliExitCode::UnknownError(err.to_string());
+    }
     if let Err(err) = logger_init_with_config(&cfg) {
         perror!("failed to init logger: {}", err);
         return err;
     }
 
     let run_time = cfg.0.run_time();
-    cli_command_opts.into_config(&mut cfg).unwrap();
     if cfg.0.is_fixed_stdin() {
         if let Some(stdin_buffer) = non_blocking_read(std::io::stdin()).await {
             cfg.0.stdio.stdin(Stdin::Fixed(stdin_buffer));
@@ -185,14 +200,115 @@ async fn wasm_runtime(mut cfg: CliConfig, cli_command_opts: CliCommandOpts) -> C
         perror!("WASM app crashed, please check the runtime.log file");
     }));
 
-    let exit_status = blockless_run(cfg.0).await.unwrap();
+    let exit_status = match blockless_run(cfg.0).await {
+        Err(e)
+            if e.downcast_ref::<SignatureError>().is_some()
+                || e.downcast_ref::<DigestError>().is_some() =>
+        {
+            perror!("{:#}", e);
+            return CliExitCode::ConfigureError;
+        }
+        rs => rs.unwrap(),
+    };
     info!(
         "The wasm execute finish, the exit code: {}",
         exit_status.code
     );
+    // the result cid is printed to the stderr, the stdout belongs to the app.
+    if let Some(cid) = exit_status.output_cid.as_ref() {
+        info!("The output is packed, the result cid: {cid}");
+        eprintln!("result cid: {cid}");
+    }
+    for denied in exit_status.denied.iter() {
+        warn!(
+            "The access to {} by {} is denied {} times.",
+            denied.resource, denied.api_name, denied.count
+        );
+    }
+    let usage = &exit_status.usage;
+    info!(
+        "The host resource usage, requests: {}, bytes sent: {}, bytes received: {}, cgi processes: {}, peak handles: {:?}.",
+        usage.requests, usage.bytes_sent, usage.bytes_received, usage.cgi_processes, usage.peak_handles
+    );
+    if usage.refused > 0 {
+        warn!(
+            "The quotas refused {} usages of the host resources.",
+            usage.refused
+        );
+    }
+    // the pack error is reported alongside the exit code of the app,
+    // the app exited with 0 still fails by the pack error.
+    if let Some(e) = exit_status.output_error.as_ref() {
+        error!("{}, the exit code: {}", e, exit_status.code);
+        perror!("{}", e);
+        if exit_status.code == 0 {
+            return CliExitCode::UnknownError(e.to_string());
+        }
+    }
     exit_status.code.into()
 }
 
+/// the runtime logger and the stdio files of the car file run are written into the
+/// scratch directory of the run, so the extracted package is kept clean and can be reused.
+fn use_run_dir(cfg: &mut CliConfig) -> anyhow::Result<()> {
+    let root = match std::env::var(ENV_ROOT_PATH_NAME) {
+        Ok(root) if cfg.0.get_is_carfile() => root,
+        _ => return Ok(()),
+    };
+    let run_dir = Workspace::new(root).create_run_dir()?;
+    let run_file = |name: &str| -> String {
+        if Path::new(name).is_absolute() {
+            name.to_string()
+        } else {
+            run_dir.join(name).to_string_lossy().into_owned()
+        }
+    };
+    let logger = cfg.0.runtime_logger.as_deref().map(&run_file);
+    cfg.0.set_runtime_logger(logger);
+    if let Stdout::FileName(name) = cfg.0.stdout_ref() {
+        let stdout = Stdout::FileName(run_file(name));
+        cfg.0.stdio.stdout(stdout);
+    }
+    if let Stderr::FileName(name) = cfg.0.stderr_ref() {
+        let stderr = Stderr::FileName(run_file(name));
+        cfg.0.stdio.stderr(stderr);
+    }
+    Ok(())
+}
+
+/// the input of the cid is fetched from the ipfs gateway into the workspace cache,
+/// the path of the car file is returned, other inputs are returned as they are.
+async fn fetch_input(cli_command_opts: &CliCommandOpts) -> Result<String, CliExitCode> {
+    let input = cli_command_opts.input_ref();
+    let cid = match input_cid(input) {
+        Ok(Some(cid)) => cid,
+        Ok(None) => return Ok(input.to_string()),
+        Err(e) => {
+            perror!("{:#}", e);
+            return Err(CliExitCode::ConfigureError);
+        }
+    };
+    let root = match std::env::var(ENV_ROOT_PATH_NAME) {
+        Ok(root) => root,
+        Err(_) => {
+            perror!("the --fs-root-path is required to run the package {cid}.");
+            return Err(CliExitCode::ConfigureError);
+        }
+    };
+    let cache_dir = Workspace::new(root).cache_dir();
+    let rs = match IpfsFetcher::new(&cli_command_opts.ipfs_gateway, cache_dir) {
+        Ok(fetcher) => fetcher.fetch(&cid).await,
+        Err(e) => Err(e),
+    };
+    match rs {
+        Ok(path) => Ok(path.to_string_lossy().into_owned()),
+        Err(e) => {
+            perror!("failed to fetch the package {cid}: {:#}", e);
+            Err(CliExitCode::UnknownError(e.to_string()))
+        }
+    }
+}
+
 fn set_root_path_env_var(cli_command_opts: &CliCommandOpts) {
     cli_command_opts
         .fs_root_path()
@@ -232,6 +348,13 @@ fn parse_args() -> CliCommandOpts {
             set_perm_grant!("allow-write", o.permission_flags.allow_write);
             set_perm_grant!("deny-read", o.permission_flags.deny_read);
             set_perm_grant!("deny-write", o.permission_flags.deny_write);
+            set_perm_grant!("allow-net", o.permission_flags.allow_net);
+            set_perm_grant!("deny-net", o.permission_flags.deny_net);
+            set_perm_grant!("allow-listen", o.permission_flags.allow_listen);
+            set_perm_grant!("allow-connect", o.permission_flags.allow_connect);
+            set_perm_grant!("allow-run", o.permission_flags.allow_run);
+            set_perm_grant!("allow-env", o.permission_flags.allow_env);
+            set_perm_grant!("deny-env", o.permission_flags.deny_env);
             o
         }
         Err(_) => {
@@ -240,11 +363,319 @@ fn parse_args() -> CliCommandOpts {
     }
 }
 
+/// validate the input and print the issues, the app is not run.
+fn validate_command(input: &str, profile: Option<&str>) -> CliExitCode {
+    match validate_input(input, profile) {
+        Ok(issues) if issues.is_empty() => {
+            println!("{input} is valid.");
+            CliExitCode::Success
+        }
+        Ok(issues) => {
+            perror!("{}", ConfigIssues(issues));
+            CliExitCode::ConfigureError
+        }
+        Err(e) => {
+            perror!("failed to validate {input}: {e}");
+            CliExitCode::ConfigureError
+        }
+    }
+}
+
+/// convert the configure file, the target format is the `--to` format or
+/// the format of the output file.
+fn convert_command(input: &str, to: Option<ConfigFormat>, output: Option<&PathBuf>) -> CliExitCode {
+    let format = to
+        .or_else(|| output.and_then(ConfigFormat::from_path))
+        .unwrap_or(ConfigFormat::Json);
+    let text = match convert_config_file(input, format) {
+        Ok(text) => text,
+        Err(e) => {
+            perror!("failed to convert {input}: {e}");
+            return CliExitCode::ConfigureError;
+        }
+    };
+    match output {
+        Some(output) => {
+            if let Err(e) = fs::write(output, text) {
+                perror!("failed to write {}: {e}", output.display());
+                return CliExitCode::UnknownError(e.to_string());
+            }
+        }
+        None => print!("{text}"),
+    }
+    CliExitCode::Success
+}
+
+/// print the configure merged from the extends chain, the profile and the variables,
+/// the car file is not extracted.
+fn config_show_command(
+    input: &str,
+    profile: Option<&String>,
+    env_file: Option<&PathBuf>,
+    format: ConfigFormat,
+) -> CliExitCode {
+    let opts = match LoadOptions::new(env_file.map(PathBuf::as_path), profile.cloned()) {
+        Ok(opts) => opts,
+        Err(e) => {
+            perror!("{}", e);
+            return CliExitCode::ConfigureError;
+        }
+    };
+    let is_car = Path::new(input)
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("car"));
+    let cfg = if is_car {
+        fs::File::open(input)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(rust_car::reader::new_v1(file)?))
+            .and_then(|mut reader| load_cli_config_from_car(&mut reader, &opts))
+            .map_err(|e| CliExitCode::UnknownError(e.to_string()))
+    } else {
+        load_cli_config(input, &opts)
+    };
+    let cfg = match cfg {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            perror!("failed to load CLI config: {}", e);
+            return e;
+        }
+    };
+    let text = serde_json::to_value(&cfg.0)
+        .map_err(anyhow::Error::from)
+        .and_then(|value| format.render(&value));
+    match text {
+        Ok(text) => println!("{}", text.trim_end()),
+        Err(e) => {
+            perror!("failed to render the configure: {}", e);
+            return CliExitCode::UnknownError(e.to_string());
+        }
+    }
+    CliExitCode::Success
+}
+
+/// generate the key pair, the secret key and the public key are hex encoded.
+fn keygen_command(output: Option<&PathBuf>, force: bool) -> CliExitCode {
+    let secret_file = output.cloned().unwrap_or_else(|| PathBuf::from("bls.key"));
+    let public_file = secret_file.with_extension("pub");
+    if !force {
+        if let Some(file) = [&secret_file, &public_file]
+            .into_iter()
+            .find(|f| f.exists())
+        {
+            perror!(
+                "the key file {} exists, use --force to overwrite it.",
+                file.display()
+            );
+            return CliExitCode::UnknownError("the key file exists".to_string());
+        }
+    }
+    let key = signature::generate_signing_key();
+    let public_key = hex::encode(key.verifying_key().as_bytes());
+    let rs = write_secret_key(&secret_file, &hex::encode(key.to_bytes()))
+        .and_then(|_| fs::write(&public_file, &public_key));
+    if let Err(e) = rs {
+        perror!("failed to write the key files: {e}");
+        return CliExitCode::UnknownError(e.to_string());
+    }
+    println!("{public_key}");
+    CliExitCode::Success
+}
+
+/// the secret key file is only readable and writable by the owner.
+fn write_secret_key(path: &std::path::Path, key: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    #[cfg(unix)]
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let mut opts = fs::OpenOptions::new();
+    opts.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    opts.mode(0o600);
+    let mut file = opts.open(path)?;
+    // the mode is only applied to the new file, the overwritten one is changed here.
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(key.as_bytes())
+}
+
+/// sign the car file into the detached signature file, or the wasm file in place.
+fn sign_command(input: &PathBuf, key: &PathBuf, output: Option<&PathBuf>) -> CliExitCode {
+    let is_car = input
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("car"));
+    let rs = fs::read_to_string(key)
+        .map_err(anyhow::Error::from)
+        .and_then(|key| Ok(signature::parse_signing_key(&key)?))
+        .and_then(|key| {
+            let content = fs::read(input)?;
+            if is_car {
+                let output = output
+                    .cloned()
+                    .unwrap_or_else(|| signature::car_signature_path(input));
+                fs::write(&output, signature::sign(&key, &content))?;
+                Ok(output)
+            } else {
+                let output = output.unwrap_or(input).clone();
+                fs::write(&output, signature::sign_wasm(&key, &content)?)?;
+                Ok(output)
+            }
+        });
+    match rs {
+        Ok(output) => {
+            println!("{} signed into {}.", input.display(), output.display());
+            CliExitCode::Success
+        }
+        Err(e) => {
+            perror!("failed to sign {}: {e}", input.display());
+            CliExitCode::UnknownError(e.to_string())
+        }
+    }
+}
+
+/// pack the configure and the files into the car file, the root cid is printed.
+fn pack_command(
+    config: &PathBuf,
+    assets: &[PathBuf],
+    output: Option<&PathBuf>,
+    profile: Option<&String>,
+    env_file: Option<&PathBuf>,
+) -> CliExitCode {
+    let output = output
+        .cloned()
+        .unwrap_or_else(|| config.with_extension("car"));
+    let package = LoadOptions::new(env_file.map(PathBuf::as_path), profile.cloned())
+        .and_then(|opts| stage_package(config, &opts, assets));
+    let package = match package {
+        Ok(package) => package,
+        Err(e) => {
+            match e.downcast_ref::<ConfigIssues>() {
+                Some(issues) => perror!("{}", issues),
+                None => perror!("failed to pack {}: {}", config.display(), e),
+            }
+            return CliExitCode::ConfigureError;
+        }
+    };
+    let root_cid = fs::File::create(&output)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| package.write_car(file));
+    match root_cid {
+        Ok(root_cid) => {
+            println!("{} packed, the root cid is {root_cid}.", output.display());
+            CliExitCode::Success
+        }
+        Err(e) => {
+            perror!("failed to write {}: {}", output.display(), e);
+            CliExitCode::UnknownError(e.to_string())
+        }
+    }
+}
+
+/// prune the workspace beyond the retention, the pruned entries are printed.
+fn gc_command(
+    root: Option<&PathBuf>,
+    max_age: Option<Duration>,
+    max_size: Option<u64>,
+    dry_run: bool,
+) -> CliExitCode {
+    let root = match root
+        .cloned()
+        .or_else(|| std::env::var_os(ENV_ROOT_PATH_NAME).map(PathBuf::from))
+    {
+        Some(root) => root,
+        None => {
+            perror!("the workspace root is not set, use --root or the {ENV_ROOT_PATH_NAME} environment variable.");
+            return CliExitCode::ConfigureError;
+        }
+    };
+    let retention = Retention {
+        // the age is the default retention if neither is set.
+        max_age: max_age.or_else(|| {
+            max_size
+                .is_none()
+                .then(|| Duration::from_secs(7 * 24 * 60 * 60))
+        }),
+        max_size,
+    };
+    let workspace = Workspace::new(root);
+    let rs = workspace
+        .expired(&retention, std::time::SystemTime::now())
+        .and_then(|expired| {
+            for entry in expired.iter() {
+                println!("{} ({} bytes)", entry.path.display(), entry.size);
+            }
+            if !dry_run {
+                workspace.remove(&expired)?;
+            }
+            Ok(expired.len())
+        });
+    match rs {
+        Ok(n) if dry_run => {
+            println!("{n} entries to prune.");
+            CliExitCode::Success
+        }
+        Ok(n) => {
+            println!("{n} entries pruned.");
+            CliExitCode::Success
+        }
+        Err(e) => {
+            perror!("failed to prune the workspace: {}", e);
+            CliExitCode::UnknownError(e.to_string())
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> CliExitCode {
     let cli_command_opts = parse_args();
+    if let Some(command) = cli_command_opts.command.as_ref() {
+        return match command {
+            CliSubCommand::Validate { input, profile } => {
+                validate_command(input, profile.as_deref())
+            }
+            CliSubCommand::Keygen { output, force } => keygen_command(output.as_ref(), *force),
+            CliSubCommand::Sign { input, key, output } => sign_command(input, key, output.as_ref()),
+            CliSubCommand::Pack {
+                config,
+                assets,
+                output,
+                profile,
+                env_file,
+            } => pack_command(
+                config,
+                assets,
+                output.as_ref(),
+                profile.as_ref(),
+                env_file.as_ref(),
+            ),
+            CliSubCommand::Config {
+                command:
+                    ConfigSubCommand::Show {
+                        input,
+                        profile,
+                        env_file,
+                        format,
+                    },
+            } => config_show_command(
+                input,
+                profile.as_ref(),
+                env_file.as_ref(),
+                format.unwrap_or(ConfigFormat::Json),
+            ),
+            CliSubCommand::Convert { input, to, output } => {
+                convert_command(input, *to, output.as_ref())
+            }
+            CliSubCommand::Gc {
+                root,
+                max_age,
+                max_size,
+                dry_run,
+            } => gc_command(root.as_ref(), *max_age, *max_size, *dry_run),
+        };
+    }
     set_root_path_env_var(&cli_command_opts);
-    let path = cli_command_opts.input_ref();
+    let path = match fetch_input(&cli_command_opts).await {
+        Ok(path) => path,
+        Err(e) => return e,
+    };
 
     match cli_command_opts.runtime_type() {
         RuntimeType::V86 => match v86_runtime(&path) {
@@ -255,17 +686,20 @@ async fn main() -> CliExitCode {
             }
         },
         RuntimeType::Wasm => {
-            let cfg = match load_cli_config(&path) {
+            let opts = match cli_command_opts.load_options() {
+                Ok(opts) => opts,
+                Err(e) => {
+                    perror!("{}", e);
+                    return CliExitCode::ConfigureError;
+                }
+            };
+            let cfg = match load_cli_config(&path, &opts) {
                 Ok(cfg) => cfg,
                 Err(e) => {
                     perror!("failed to load CLI config: {}", e);
                     return e;
                 }
             };
-            if let Err(code) = check_module_sum(&cfg) {
-                perror!("{}", code);
-                return code;
-            }
             return wasm_runtime(cfg, cli_command_opts).await;
         }
     };
@@ -274,7 +708,6 @@ async fn main() -> CliExitCode {
 #[cfg(test)]
 mod test {
     #![allow(unused)]
-    use crate::config::load_cli_config_from_car;
     use blockless::ModuleType;
     use rust_car::{
         codec::Encoder,
@@ -298,9 +731,29 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_keygen_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("dev.key");
+        assert_eq!(keygen_command(Some(&key_file), false), CliExitCode::Success);
+        let key = fs::read_to_string(&key_file).unwrap();
+        assert!(dir.path().join("dev.pub").exists());
+        // the existing key is never overwritten without --force.
+        assert_ne!(keygen_command(Some(&key_file), false), CliExitCode::Success);
+        assert_eq!(fs::read_to_string(&key_file).unwrap(), key);
+        assert_eq!(keygen_command(Some(&key_file), true), CliExitCode::Success);
+        assert_ne!(fs::read_to_string(&key_file).unwrap(), key);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&key_file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
     #[test]
     fn test_load_cli_wasm_config() {
-        let wasm_conf = load_cli_config("test.wasm");
+        let wasm_conf = load_cli_config("test.wasm", &LoadOptions::default());
         let wasm_conf = wasm_conf.unwrap();
         let entry_ref = wasm_conf.0.entry_ref();
         assert_eq!(entry_ref, "test.wasm");
@@ -358,7 +811,7 @@ mod test {
         let input = std::io::Cursor::new(&mut buf);
         let mut car_reader = reader::new_v1(input).unwrap();
         let root_cid = car_reader.header().roots()[0];
-        let cfg = load_cli_config_from_car(&mut car_reader).unwrap();
+        let cfg = load_cli_config_from_car(&mut car_reader, &LoadOptions::default()).unwrap();
         assert_eq!(cfg.0.fs_root_path_ref(), Some("target"));
         assert_eq!(cfg.0.drivers_root_path_ref(), Some("target/drivers"));
     }
@@ -0,0 +1,258 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{Seek, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use blockless::{
+    car::write_files_car,
+    digest::{DigestAlgorithm, ModuleDigest},
+    BlocklessConfig, BlocklessConfigVersion,
+};
+
+use serde_json::Value;
+
+use crate::config::{CliConfig, LoadOptions};
+use crate::layer::{load_raw_layers, select_profile};
+
+/// The files of the package, the key is the path in the package, e.g. `assets/logo.png`.
+#[derive(Default)]
+pub(crate) struct Package {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Package {
+    fn add_file(&mut self, name: String, data: Vec<u8>) -> Result<()> {
+        match self.files.get(&name) {
+            Some(exist) if *exist != data => bail!("duplicate file `{name}` in the package"),
+            _ => {
+                self.files.insert(name, data);
+            }
+        }
+        Ok(())
+    }
+
+    /// add the file or the directory into the package with the name.
+    fn add_path(&mut self, path: &Path, name: String) -> Result<()> {
+        if path.is_dir() {
+            let dir = fs::read_dir(path)
+                .with_context(|| format!("failed to read the directory {}", path.display()))?;
+            for entry in dir {
+                let entry = entry?;
+                let child = entry.file_name().to_string_lossy().to_string();
+                self.add_path(&entry.path(), format!("{name}/{child}"))?;
+            }
+            Ok(())
+        } else {
+            let data =
+                fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+            self.add_file(name, data)
+        }
+    }
+
+    /// add the module file into the package, the path in the package is returned.
+    fn add_module(&mut self, file: &str) -> Result<(String, Vec<u8>)> {
+        let path = Path::new(file);
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.to_string(),
+            None => bail!("invalid module file `{file}`"),
+        };
+        let data = fs::read(path).with_context(|| format!("failed to read the module {file}"))?;
+        self.add_file(name.clone(), data.clone())?;
+        Ok((name, data))
+    }
+
+    /// write the package as the car file, the root is the directory of the package files,
+    /// the root cid is returned.
+    pub fn write_car<T>(&self, output: T) -> Result<String>
+    where
+        T: Write + Seek,
+    {
+        write_files_car(&self.files, output)
+    }
+}
+
+/// the host paths can't be packed, the package is run on the other hosts.
+fn check_host_paths(value: &Value) -> Result<()> {
+    for key in ["env_file", "output_car"] {
+        if value.get(key).is_some() {
+            bail!("the `{key}` is a host path, it can't be packed");
+        }
+    }
+    let map_dirs = value.get("map_dirs").and_then(Value::as_array);
+    for (i, dir) in map_dirs.into_iter().flatten().enumerate() {
+        let host = dir.get("host").and_then(Value::as_str).unwrap_or_default();
+        if host != "$ROOT" && !host.starts_with("$ROOT/") {
+            bail!("the map_dirs[{i}].host `{host}` is a host path, only `$ROOT/...` can be packed");
+        }
+    }
+    Ok(())
+}
+
+/// stage the configure and the files it refers for the package. The configure is packed
+/// as written, the `${VAR}` are resolved when the package runs, and it's rewritten:
+/// 1. the `fs_root_path` is `$ENV_ROOT_PATH`.
+/// 2. the module files are `$ROOT/<file name>` and the sha256 digest is filled.
+/// 3. the assets are added into the package root with their names.
+pub(crate) fn stage_package(
+    config_path: impl AsRef<Path>,
+    opts: &LoadOptions,
+    assets: &[impl AsRef<Path>],
+) -> Result<Package> {
+    let config_path = config_path.as_ref();
+    let mut value = load_raw_layers(config_path)?;
+    select_profile(&mut value, opts.profile.as_deref())?;
+    check_host_paths(&value)?;
+    // the module files are read by the paths resolved on this host.
+    let config: BlocklessConfig = CliConfig::from_file(config_path, opts)?.0;
+    let mut package = Package::default();
+    match config.version() {
+        BlocklessConfigVersion::Version0 => {
+            let (name, _) = package.add_module(config.entry_ref())?;
+            value["entry"] = Value::String(format!("$ROOT/{name}"));
+        }
+        BlocklessConfigVersion::Version1 => {
+            for (i, module) in config.modules.iter().enumerate() {
+                let (name, data) = package.add_module(&module.file)?;
+                let digest = ModuleDigest::compute(DigestAlgorithm::Sha256, &data);
+                value["modules"][i]["file"] = Value::String(format!("$ROOT/{name}"));
+                value["modules"][i]["digest"] = Value::String(digest.to_string());
+            }
+        }
+    }
+    value["fs_root_path"] = Value::String("$ENV_ROOT_PATH".to_string());
+    for asset in assets {
+        let asset = asset.as_ref();
+        let name = match asset.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.to_string(),
+            None => bail!("invalid asset `{}`", asset.display()),
+        };
+        package.add_path(asset, name)?;
+    }
+    let config_json = serde_json::to_string_pretty(&value)?;
+    package.add_file("config.json".to_string(), config_json.into_bytes())?;
+    Ok(package)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_car::{
+        reader::{self, CarReader},
+        utils::ipld_write,
+    };
+
+    #[test]
+    fn test_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("lib.wasm"), b"lib").unwrap();
+        fs::write(dir.path().join("main.wasm"), b"main").unwrap();
+        fs::create_dir_all(dir.path().join("assets/img")).unwrap();
+        fs::write(dir.path().join("assets/img/logo.png"), b"logo").unwrap();
+        let config = dir.path().join("app.json");
+        fs::write(
+            &config,
+            format!(
+                r#"{{
+                    "version": 1,
+                    "entry": "run",
+                    "modules": [
+                        {{"type": "module", "name": "lib", "file": "{0}/lib.wasm"}},
+                        {{"type": "entry", "name": "main", "file": "{0}/main.wasm"}}
+                    ]
+                }}"#,
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+        let package = stage_package(
+            &config,
+            &LoadOptions::default(),
+            &[dir.path().join("assets")],
+        )
+        .unwrap();
+        let names = package.files.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "assets/img/logo.png",
+                "config.json",
+                "lib.wasm",
+                "main.wasm"
+            ]
+        );
+
+        let mut buf = Vec::new();
+        let root_cid = package.write_car(std::io::Cursor::new(&mut buf)).unwrap();
+        let mut reader = reader::new_v1(std::io::Cursor::new(&mut buf)).unwrap();
+        assert_eq!(reader.header().roots()[0].to_string(), root_cid);
+        let cid = reader.search_file_cid("config.json").unwrap();
+        let mut data = Vec::new();
+        ipld_write(&mut reader, cid, &mut data).unwrap();
+        let packed = BlocklessConfig::from_json(std::str::from_utf8(&data).unwrap()).unwrap();
+        assert_eq!(packed.fs_root_path_ref(), Some("$ENV_ROOT_PATH"));
+        let modules = &packed.modules;
+        assert_eq!(modules[0].file, "$ROOT/lib.wasm");
+        let digest = modules[0].digest.parse::<ModuleDigest>().unwrap();
+        assert_eq!(digest.algorithm(), DigestAlgorithm::Sha256);
+        assert!(digest.verify(b"lib").is_ok());
+        assert_eq!(modules[1].file, "$ROOT/main.wasm");
+    }
+
+    #[test]
+    fn test_pack_duplicate_module() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        fs::create_dir(dir.path().join("b")).unwrap();
+        fs::write(dir.path().join("a/lib.wasm"), b"a").unwrap();
+        fs::write(dir.path().join("b/lib.wasm"), b"b").unwrap();
+        let config = dir.path().join("app.json");
+        fs::write(
+            &config,
+            format!(
+                r#"{{
+                    "version": 1,
+                    "entry": "run",
+                    "modules": [
+                        {{"type": "module", "file": "{0}/a/lib.wasm"}},
+                        {{"type": "entry", "file": "{0}/b/lib.wasm"}}
+                    ]
+                }}"#,
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+        let assets: &[&Path] = &[];
+        assert!(stage_package(&config, &LoadOptions::default(), assets).is_err());
+    }
+
+    #[test]
+    fn test_pack_untouched_config() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("main.wasm"), b"main").unwrap();
+        let config = dir.path().join("app.json");
+        let write_config = |extra: &str| {
+            let entry = format!("{}/main.wasm", dir.path().display());
+            let json =
+                format!(r#"{{"entry": "{entry}", "envs": {{"HOME_DIR": "${{HOME}}"}}{extra}}}"#);
+            fs::write(&config, json).unwrap();
+        };
+        let assets: &[&Path] = &[];
+        let opts = LoadOptions::default();
+        write_config(r#", "map_dirs": [{"host": "$ROOT/data", "guest": "/data"}]"#);
+        let package = stage_package(&config, &opts, assets).unwrap();
+        let packed: Value = serde_json::from_slice(&package.files["config.json"]).unwrap();
+        // the variables are resolved when the package runs, not from the host of the pack.
+        assert_eq!(packed["envs"]["HOME_DIR"], "${HOME}");
+        assert_eq!(packed["map_dirs"][0]["host"], "$ROOT/data");
+        assert_eq!(packed["entry"], "$ROOT/main.wasm");
+        // the host paths can't be packed.
+        write_config(r#", "map_dirs": [{"host": "/etc", "guest": "/etc"}]"#);
+        let rs = stage_package(&config, &opts, assets);
+        assert!(rs.err().unwrap().to_string().contains("map_dirs[0].host"));
+        write_config(r#", "env_file": "app.env""#);
+        assert!(stage_package(&config, &opts, assets).is_err());
+    }
+}
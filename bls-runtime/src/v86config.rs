@@ -41,7 +41,7 @@ impl V86config {
 }
 
 pub(crate) fn load_v86conf_extract_from_car(f: File) -> Result<V86config, CliExitCode> {
-    let config = load_extract_from_car(f, |raw_json, root_suffix| {
+    let config = load_extract_from_car(f, true, |raw_json, root_suffix| {
         let mut cfg = V86config::from_data(raw_json.clone(), root_suffix.clone())?;
         cfg.raw_config = replace_vars(raw_json, root_suffix).ok();
         Ok(Config::V86config(cfg))
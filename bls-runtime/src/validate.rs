@@ -0,0 +1,661 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use anyhow::Result;
+
+use blockless::digest::ModuleDigest;
+use blockless::{
+    parse_tcp_listen, BlocklessConfig, BlocklessConfigVersion, BlocklessModule, BlsNnGraph,
+    BlsOptions, DriverConfig, HttpClientConfig, MapDir, ModuleType, OptimizeOpts, Permission,
+    PermissionGrant, PrompterKind, ResourceQuotas, RunRule, Stderr, Stdin, Stdout,
+};
+use rust_car::{
+    reader::{self, CarReader},
+    utils::ipld_write,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
+
+use crate::config::{replace_vars, CliConfig, ConfigFormat};
+use crate::interpolate::{interpolate_config, Variables};
+use crate::layer::{load_layers, select_profile};
+
+const LOGGER_LEVELS: &[&str] = &[
+    "trace", "TRACE", "debug", "DEBUG", "info", "INFO", "warn", "WARN", "error", "ERROR",
+];
+
+const MODULE_KEYS: &[&str] = &["type", "name", "file", "md5", "digest", "permissions"];
+
+const MAP_DIR_KEYS: &[&str] = &["type", "host", "guest"];
+
+const DRIVER_KEYS: &[&str] = &["schema", "path"];
+
+const NN_GRAPH_KEYS: &[&str] = &["format", "dir"];
+
+/// A problem of the configure, the path is the json path of the problem value.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ConfigIssue {
+    pub path: String,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    pub(crate) fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// All the problems found in the configure.
+#[derive(Debug)]
+pub(crate) struct ConfigIssues(pub Vec<ConfigIssue>);
+
+impl fmt::Display for ConfigIssues {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid configure:")?;
+        for issue in self.0.iter() {
+            write!(f, "\n    {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigIssues {}
+
+pub(crate) fn join_path(parent: &str, child: &str) -> String {
+    if parent.is_empty() {
+        child.to_string()
+    } else if child.starts_with('[') {
+        format!("{parent}{child}")
+    } else {
+        format!("{parent}.{child}")
+    }
+}
+
+/// deserialize the value as the type, report the problem with the path in the value.
+fn check_type<T: DeserializeOwned>(value: &Value, path: &str, issues: &mut Vec<ConfigIssue>) {
+    if let Err(e) = serde_path_to_error::deserialize::<_, T>(value) {
+        let inner = e.path().to_string();
+        let path = if inner == "." {
+            path.to_string()
+        } else {
+            join_path(path, &inner)
+        };
+        issues.push(ConfigIssue::new(path, e.into_inner().to_string()));
+    }
+}
+
+/// report the keys of the objects in the list which are not in the known keys.
+fn check_list_keys(value: &Value, keys: &[&str], path: &str, issues: &mut Vec<ConfigIssue>) {
+    if let Some(list) = value.as_array() {
+        for (i, item) in list.iter().enumerate() {
+            if let Some(obj) = item.as_object() {
+                check_keys(obj, keys, &format!("{path}[{i}]"), issues);
+            }
+        }
+    }
+}
+
+fn check_keys(obj: &Map<String, Value>, keys: &[&str], path: &str, issues: &mut Vec<ConfigIssue>) {
+    for key in obj.keys() {
+        if !keys.contains(&key.as_str()) {
+            issues.push(ConfigIssue::new(join_path(path, key), "unknown key"));
+        }
+    }
+}
+
+/// the options like `optimize` and `quotas` are objects of the known options.
+fn check_options<T: BlsOptions + DeserializeOwned>(
+    value: &Value,
+    path: &str,
+    name: &str,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    let obj = match value.as_object() {
+        Some(obj) => obj,
+        None => {
+            issues.push(ConfigIssue::new(
+                path,
+                format!("the {name} should be an object"),
+            ));
+            return;
+        }
+    };
+    for (key, val) in obj.iter() {
+        let path = join_path(path, key);
+        if !T::OPTIONS.iter().any(|o| o.opt_name == key.as_str()) {
+            issues.push(ConfigIssue::new(path, format!("unknown {name} option")));
+            continue;
+        }
+        let mut item = Map::new();
+        item.insert(key.clone(), val.clone());
+        if let Err(e) = serde_json::from_value::<T>(Value::Object(item)) {
+            issues.push(ConfigIssue::new(path, e.to_string()));
+        }
+    }
+}
+
+/// the proxy of the http client is a http or socks url.
+fn check_http_proxy(value: &Value, path: &str, issues: &mut Vec<ConfigIssue>) {
+    if let Some(proxy) = value.get("proxy").and_then(Value::as_str) {
+        let scheme = proxy.split_once("://").map(|(scheme, _)| scheme);
+        if !matches!(scheme, Some("http" | "https" | "socks5" | "socks5h")) {
+            issues.push(ConfigIssue::new(
+                join_path(path, "proxy"),
+                format!("invalid proxy `{proxy}`, must be a http, https, socks5 or socks5h url"),
+            ));
+        }
+    }
+}
+
+fn check_tcp_listens(value: &Value, path: &str, issues: &mut Vec<ConfigIssue>) {
+    if let Some(list) = value.as_array() {
+        for (i, item) in list.iter().enumerate() {
+            if let Some(s) = item.as_str() {
+                if parse_tcp_listen(s).is_none() {
+                    let msg =
+                        format!("invalid tcp listen `{s}`, must be of the form `ADDR[::LISTENFD]`");
+                    issues.push(ConfigIssue::new(format!("{path}[{i}]"), msg));
+                }
+            }
+        }
+    }
+}
+
+fn check_permissions(value: &Value, path: &str, issues: &mut Vec<ConfigIssue>) {
+    if let Some(list) = value.as_array() {
+        for (i, item) in list.iter().enumerate() {
+            let path = format!("{path}[{i}]");
+            match item.as_str() {
+                Some(url) if Permission::parse(url).is_none() || url::Url::parse(url).is_err() => {
+                    issues.push(ConfigIssue::new(
+                        path,
+                        format!("invalid permission url `{url}`"),
+                    ));
+                }
+                Some(_) => {}
+                None => check_type::<Permission>(item, &path, issues),
+            }
+        }
+    } else {
+        check_type::<Vec<Permission>>(value, path, issues);
+    }
+}
+
+fn check_module_digests(value: &Value, path: &str, issues: &mut Vec<ConfigIssue>) {
+    let list = value.as_array().map(Vec::as_slice).unwrap_or_default();
+    // the empty digest skips the check.
+    let digest = |module: &Value, key: &str| {
+        module[key]
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+    for (i, module) in list.iter().enumerate() {
+        if let Some(Err(e)) = digest(module, "digest").map(|d| d.parse::<ModuleDigest>()) {
+            issues.push(ConfigIssue::new(
+                format!("{path}[{i}].digest"),
+                e.to_string(),
+            ));
+        }
+        if let Some(Err(e)) = digest(module, "md5").map(|d| ModuleDigest::from_md5(&d)) {
+            issues.push(ConfigIssue::new(format!("{path}[{i}].md5"), e.to_string()));
+        }
+    }
+}
+
+/// the permissions of the library modules, the entry module uses the permissions of the app.
+fn check_module_permissions(value: &Value, path: &str, issues: &mut Vec<ConfigIssue>) {
+    let list = value.as_array().map(Vec::as_slice).unwrap_or_default();
+    for (i, module) in list.iter().enumerate() {
+        let perms = match module.get("permissions") {
+            Some(perms) => perms,
+            None => continue,
+        };
+        let path = format!("{path}[{i}].permissions");
+        if module["type"] == "entry" {
+            issues.push(ConfigIssue::new(
+                path,
+                "the entry module uses the permissions of the app",
+            ));
+        } else if let Some(urls) = perms.get("permissions") {
+            check_permissions(urls, &format!("{path}.permissions"), issues);
+        }
+    }
+}
+
+/// check the value of the top level key, return false if the key is unknown.
+fn check_top_level(key: &str, value: &Value, issues: &mut Vec<ConfigIssue>) -> bool {
+    macro_rules! check {
+        ($ty: ty) => {
+            check_type::<$ty>(value, key, issues)
+        };
+    }
+    match key {
+        "version" => check!(BlocklessConfigVersion),
+        "entry" | "fs_root_path" | "drivers_root_path" | "extensions_path" | "runtime_logger"
+        | "coredump" | "env_file" | "output_dir" | "output_car" | "audit_log" => {
+            check!(String)
+        }
+        "runtime_logger_level" => match value.as_str() {
+            Some(level) if !LOGGER_LEVELS.contains(&level) => issues.push(ConfigIssue::new(
+                key,
+                format!("unknown logger level `{level}`, expected one of trace, debug, info, warn, error"),
+            )),
+            Some(_) => {}
+            None => check!(String),
+        },
+        "limited_fuel" | "limited_memory" | "run_time" => check!(u64),
+        "max_memory_size" | "max_instances" => check!(usize),
+        "max_table_elements" | "max_tables" => check!(u32),
+        "debug_info" | "feature_thread" | "nn" | "unknown_imports_trap" | "cli_exit_with_code"
+        | "network_error_code" | "allow_all" | "trap_on_grow_failure" | "require_signature" => {
+            check!(bool)
+        }
+        "stdin" => check!(Stdin),
+        "stdout" => check!(Stdout),
+        "stderr" => check!(Stderr),
+        "map_dirs" => {
+            check!(Vec<MapDir>);
+            check_list_keys(value, MAP_DIR_KEYS, key, issues);
+        }
+        "drivers" => {
+            check!(Vec<DriverConfig>);
+            check_list_keys(value, DRIVER_KEYS, key, issues);
+        }
+        "modules" => {
+            check!(Vec<BlocklessModule>);
+            check_list_keys(value, MODULE_KEYS, key, issues);
+            check_module_digests(value, key, issues);
+            check_module_permissions(value, key, issues);
+        }
+        "nn_graph" => {
+            check!(Vec<BlsNnGraph>);
+            check_list_keys(value, NN_GRAPH_KEYS, key, issues);
+        }
+        "permissions" => check_permissions(value, key, issues),
+        // the configure can't choose the keys its own package and modules are checked with.
+        "trusted_keys" => issues.push(ConfigIssue::new(
+            key,
+            "the trusted keys are only given by the --trusted-key",
+        )),
+        "optimize" => check_options::<OptimizeOpts>(value, key, "optimize", issues),
+        "quotas" => check_options::<ResourceQuotas>(value, key, "quota", issues),
+        "http_client" => {
+            check!(HttpClientConfig);
+            check_http_proxy(value, key, issues);
+        }
+        "envs" | "vars" => check!(BTreeMap<String, String>),
+        "args" | "host_envs" => check!(Vec<String>),
+        "tcp_listens" => {
+            check!(Vec<String>);
+            check_tcp_listens(value, key, issues);
+        }
+        "allow_read" | "allow_write" | "deny_read" | "deny_write" | "allow_net" | "deny_net"
+        | "allow_listen" | "allow_connect" | "allow_run" | "allow_env" | "deny_env" => {
+            check!(PermissionGrant)
+        }
+        "run_rules" => check!(BTreeMap<String, RunRule>),
+        "prompter" => check!(PrompterKind),
+        "extends" => issues.push(ConfigIssue::new(
+            key,
+            "the base configure is only supported by the configure file",
+        )),
+        _ => return false,
+    }
+    true
+}
+
+/// The structure checks of the configure: unknown keys, type mismatches,
+/// the required keys and invalid values.
+pub(crate) fn validate_value(value: &Value) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    let obj = match value.as_object() {
+        Some(obj) => obj,
+        None => {
+            issues.push(ConfigIssue::new(".", "the configure should be an object"));
+            return issues;
+        }
+    };
+    if !obj.contains_key("entry") {
+        issues.push(ConfigIssue::new("entry", "missing required key"));
+    }
+    if obj.contains_key("output_car") && !obj.contains_key("output_dir") {
+        issues.push(ConfigIssue::new("output_car", "the output_dir is required"));
+    }
+    for (key, val) in obj.iter() {
+        if !check_top_level(key, val, &mut issues) {
+            issues.push(ConfigIssue::new(key.as_str(), "unknown key"));
+        }
+    }
+    issues
+}
+
+/// The file system checks of the configure, the entry module, the modules,
+/// the drivers and the map_dirs should exist and be readable.
+pub(crate) fn validate_files(config: &BlocklessConfig) -> Vec<ConfigIssue> {
+    let package = config.package_ref();
+    validate_files_with(config, |file| match file {
+        // read the module from stdin.
+        "-" => Ok(()),
+        // the module in the package served from the memory.
+        _ => match package.and_then(|p| p.resolve(file)) {
+            Some(entry) => entry.map(|_| ()),
+            None => fs::File::open(file).map(|_| ()),
+        }
+        .map_err(|e| format!("unreadable file `{file}`: {e}")),
+    })
+}
+
+/// the file system checks with the checker of the module files.
+fn validate_files_with<F>(config: &BlocklessConfig, mut check_module: F) -> Vec<ConfigIssue>
+where
+    F: FnMut(&str) -> Result<(), String>,
+{
+    let mut issues = Vec::new();
+    match config.version() {
+        BlocklessConfigVersion::Version0 => {
+            if let Err(msg) = check_module(config.entry_ref()) {
+                issues.push(ConfigIssue::new("entry", msg));
+            }
+        }
+        BlocklessConfigVersion::Version1 => {
+            let entries = config
+                .modules
+                .iter()
+                .filter(|m| m.module_type == ModuleType::Entry)
+                .count();
+            if entries == 0 {
+                issues.push(ConfigIssue::new("modules", "no module with type `entry`"));
+            } else if entries > 1 {
+                issues.push(ConfigIssue::new(
+                    "modules",
+                    "more than one module with type `entry`",
+                ));
+            }
+            for (i, module) in config.modules.iter().enumerate() {
+                if let Err(msg) = check_module(&module.file) {
+                    issues.push(ConfigIssue::new(format!("modules[{i}].file"), msg));
+                }
+            }
+        }
+    }
+    for (i, driver) in config.drivers_ref().iter().enumerate() {
+        if let Err(e) = fs::File::open(driver.path()) {
+            let msg = format!("unreadable file `{}`: {e}", driver.path());
+            issues.push(ConfigIssue::new(format!("drivers[{i}].path"), msg));
+        }
+    }
+    for (i, (host, _)) in config.dirs.iter().enumerate() {
+        let dir = match config.package_ref().and_then(|p| p.resolve(host)) {
+            Some(entry) => entry.map(|_| ()),
+            None => fs::read_dir(Path::new(host)).map(|_| ()),
+        };
+        if let Err(e) = dir {
+            let msg = format!("unreadable directory `{host}`: {e}");
+            issues.push(ConfigIssue::new(format!("map_dirs[{i}].host"), msg));
+        }
+    }
+    // the car entries of `map_dirs` and the `--mount-car` are indexed apart from the directories.
+    for (i, (car, _)) in config.car_mounts.iter().enumerate() {
+        let file = match config.package_ref().and_then(|p| p.resolve(car)) {
+            Some(entry) => entry.map(|_| ()),
+            None => fs::File::open(car).map(|_| ()),
+        };
+        if let Err(e) = file {
+            let msg = format!("unreadable car file `{car}`: {e}");
+            issues.push(ConfigIssue::new(format!("car_mounts[{i}].host"), msg));
+        }
+    }
+    issues
+}
+
+/// validate the configure merged from the layers, the profile is merged and the variables
+/// are interpolated with the host environment, the file checks only run when the structure is valid.
+fn validate_layered<F>(mut value: Value, profile: Option<&str>, check_module: F) -> Vec<ConfigIssue>
+where
+    F: FnMut(&str) -> Result<(), String>,
+{
+    if let Err(issues) = select_profile(&mut value, profile) {
+        return issues.0;
+    }
+    if let Err(issues) = interpolate_config(&mut value, &Variables::default()) {
+        return issues.0;
+    }
+    let issues = validate_value(&value);
+    if !issues.is_empty() {
+        return issues;
+    }
+    match BlocklessConfig::deserialize(&value) {
+        Ok(config) => validate_files_with(&config, check_module),
+        Err(e) => vec![ConfigIssue::new(".", e.to_string())],
+    }
+}
+
+/// validate the config.json in the car file, the modules should be in the car file.
+fn validate_car(path: &str, profile: Option<&str>) -> Result<Vec<ConfigIssue>> {
+    let file = fs::File::open(path)?;
+    let mut reader = reader::new_v1(file)?;
+    let cid = reader.search_file_cid("config.json")?;
+    let mut data = Vec::new();
+    ipld_write(&mut reader, cid, &mut data)?;
+    let root_suffix = reader.header().roots().first().map(|c| c.to_string());
+    let raw_json = replace_vars(String::from_utf8(data)?, root_suffix)?;
+    let value = match ConfigFormat::Json.parse(&raw_json) {
+        Ok(value) => value,
+        Err(e) => return Ok(vec![ConfigIssue::new(".", e.to_string())]),
+    };
+    Ok(validate_layered(value, profile, |file| {
+        let name = Path::new(file)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(file);
+        reader
+            .search_file_cid(name)
+            .map(|_| ())
+            .map_err(|_| format!("the module `{name}` is not in the car file"))
+    }))
+}
+
+/// validate the input, the input can be a wasm file, a configure file or a car file.
+pub(crate) fn validate_input(path: &str, profile: Option<&str>) -> Result<Vec<ConfigIssue>> {
+    let ext = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str().map(str::to_ascii_lowercase));
+    match ext {
+        Some(ext) if ext == "car" => validate_car(path, profile),
+        Some(ext) if ext == "wasm" || ext == "wasi" || ext == "wat" => {
+            Ok(validate_files(&CliConfig::new_with_wasm(path).0))
+        }
+        _ => {
+            let value = match load_layers(path) {
+                Ok(value) => value,
+                Err(e) => match e.downcast::<ConfigIssues>() {
+                    Ok(issues) => return Ok(issues.0),
+                    Err(e) => return Err(e),
+                },
+            };
+            Ok(validate_layered(value, profile, |file| {
+                fs::File::open(file)
+                    .map(|_| ())
+                    .map_err(|e| format!("unreadable file `{file}`: {e}"))
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blockless::{PackageFs, VirtualDir, VirtualEntry};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn issue_paths(value: Value) -> Vec<String> {
+        validate_value(&value).into_iter().map(|i| i.path).collect()
+    }
+
+    #[test]
+    fn test_validate_value() {
+        let value = json!({
+            "entry": "main.wasm",
+            "version": 1,
+            "limited_fuel": 100,
+            "modules": [{"type": "entry", "file": "main.wasm"}],
+            "map_dirs": [
+                {"host": "/tmp", "guest": "/tmp"},
+                {"type": "car", "host": "set.car", "guest": "/set"},
+            ],
+            "permissions": ["http://httpbin.org/anything"],
+            "optimize": {"opt_level": "s"},
+            "quotas": {"max_requests": 100, "max_cgi_processes": 2},
+            "http_client": {"proxy": "socks5://127.0.0.1:1080", "user_agent": "bls/1.0"},
+            "allow_run": ["convert"],
+            "run_rules": {"convert": {"args": ["--format=*"], "envs": []}},
+            "prompter": "policy:/etc/bls/policy.json",
+        });
+        assert!(validate_value(&value).is_empty());
+    }
+
+    #[test]
+    fn test_validate_value_issues() {
+        let paths = issue_paths(json!({
+            "version": 2,
+            "limited_fuel": "100",
+            "unknown": true,
+            "modules": [
+                {"type": "entry", "file": 1, "permissions": {}},
+                {"file": "a.wasm", "md6": ""},
+                {"file": "b.wasm", "digest": "sha1:00", "md5": ""},
+                {"name": "lib", "file": "lib.wasm", "permissions": {"permissions": ["not a url"]}},
+            ],
+            "map_dirs": [{"host": "/tmp"}],
+            "permissions": ["http://httpbin.org/anything", "not a url"],
+            "optimize": {"opt_level": "x", "no_such_option": 1},
+            "quotas": {"max_requests": "many", "max_handles": 1},
+            "http_client": {"proxy": "ftp://proxy:21"},
+            "tcp_listens": ["127.0.0.1"],
+            "runtime_logger_level": "verbose",
+            "trusted_keys": ["00"],
+            "output_car": "result.car",
+            "run_rules": {"convert": {"argv": []}},
+            "prompter": "ask",
+        }));
+        let expected = [
+            "entry",
+            "http_client.proxy",
+            "limited_fuel",
+            "map_dirs[0]",
+            "modules[0].file",
+            "modules[0].permissions",
+            "modules[1].md6",
+            "modules[2].digest",
+            "modules[3].permissions.permissions[0]",
+            "optimize.no_such_option",
+            "optimize.opt_level",
+            "output_car",
+            "permissions[1]",
+            "prompter",
+            "quotas.max_handles",
+            "quotas.max_requests",
+            "run_rules",
+            "runtime_logger_level",
+            "tcp_listens[0]",
+            "trusted_keys",
+            "unknown",
+            "version",
+        ];
+        for path in expected {
+            assert!(
+                paths.contains(&path.to_string()),
+                "missing {path} in {paths:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let wasm = dir.path().join("main.wasm");
+        fs::write(&wasm, b"").unwrap();
+        let mut config = BlocklessConfig::new(wasm.to_str().unwrap());
+        config.set_map_dirs(vec![("/no/such/dir".into(), "/data".into())]);
+        let issues = validate_files(&config);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "map_dirs[0].host");
+
+        config.set_map_dirs(vec![]);
+        config.set_version(BlocklessConfigVersion::Version1);
+        config.set_modules(vec![BlocklessModule {
+            module_type: ModuleType::Module,
+            name: "lib".into(),
+            file: "/no/such/lib.wasm".into(),
+            md5: String::new(),
+            digest: String::new(),
+            permissions: None,
+        }]);
+        let paths: Vec<_> = validate_files(&config)
+            .into_iter()
+            .map(|i| i.path)
+            .collect();
+        assert_eq!(paths, vec!["modules", "modules[0].file"]);
+
+        config.set_car_mounts(vec![("/no/such/set.car".into(), "/set".into())]);
+        let paths: Vec<_> = validate_files(&config)
+            .into_iter()
+            .map(|i| i.path)
+            .collect();
+        assert_eq!(
+            paths,
+            vec!["modules", "modules[0].file", "car_mounts[0].host"]
+        );
+        config.set_car_mounts(vec![]);
+
+        // the files of the package served from the memory are not on the disk.
+        let mut package = VirtualDir::default();
+        package.insert("lib.wasm", VirtualEntry::File(Arc::new(b"lib".to_vec())));
+        package.insert("data/a.txt", VirtualEntry::File(Arc::new(b"a".to_vec())));
+        config.set_package(Some(PackageFs::new("/no/such", package)));
+        config.set_map_dirs(vec![("/no/such/data".into(), "/data".into())]);
+        let paths: Vec<_> = validate_files(&config)
+            .into_iter()
+            .map(|i| i.path)
+            .collect();
+        assert_eq!(paths, vec!["modules"]);
+    }
+
+    #[test]
+    fn test_validate_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.json");
+        fs::write(&config, r#"{"entry": "main.wasm", "limited_fuel": -1}"#).unwrap();
+        let issues = validate_input(config.to_str().unwrap(), None).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "limited_fuel");
+
+        fs::write(&config, r#"{"entry": "/no/such/main.wasm"}"#).unwrap();
+        let issues = validate_input(config.to_str().unwrap(), None).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "entry");
+
+        fs::write(&config, r#"{"entry": "#).unwrap();
+        let issues = validate_input(config.to_str().unwrap(), None).unwrap();
+        assert_eq!(issues[0].path, ".");
+
+        fs::write(
+            &config,
+            r#"{"entry": "/no/such/main.wasm", "profiles": {"prod": {"limited_fuel": "x"}}}"#,
+        )
+        .unwrap();
+        let issues = validate_input(config.to_str().unwrap(), Some("prod")).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "limited_fuel");
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// The directory of the workspace metadata under the root path.
+pub(crate) const WORKSPACE_DIR: &str = ".bls-workspace";
+
+/// the leftover of the extraction older than the age is interrupted, the younger one
+/// may be still extracted by another run.
+const STALE_EXTRACTION_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The retention of the workspace, the packages and the runs beyond the retention are pruned.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct Retention {
+    /// the packages unused and the runs older than the age are pruned.
+    pub max_age: Option<Duration>,
+    /// the oldest packages and runs are pruned until the total size is under the size.
+    pub max_size: Option<u64>,
+}
+
+/// The entry pruned or kept by the garbage collection.
+#[derive(Debug)]
+pub(crate) struct WorkspaceEntry {
+    pub path: PathBuf,
+    /// the extracted package has the marker of the last use.
+    marker: Option<PathBuf>,
+    pub last_used: SystemTime,
+    pub size: u64,
+}
+
+/// The workspace under `ENV_ROOT_PATH`:
+/// 1. the packages extracted into `<root>/<root cid>`, reused by the runs of the same cid,
+///    the caller must verify the blocks of the car file match the cid before the extraction.
+/// 2. the scratch directories of the runs in `<root>/.bls-workspace/runs`.
+/// 3. the car files fetched from the ipfs gateway in `<root>/.bls-workspace/cache`.
+pub(crate) struct Workspace {
+    root: PathBuf,
+}
+
+impl Workspace {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn meta_dir(&self, name: &str) -> PathBuf {
+        self.root.join(WORKSPACE_DIR).join(name)
+    }
+
+    /// the package is extracted by the callback into the temporary directory and moved to
+    /// `<root>/<cid>` when the extraction finished, the extracted package is reused if `reuse`.
+    /// The cid must be verified, the package of the cid is reused without verification,
+    /// so the signed package is extracted again instead of reusing the files on the disk.
+    pub fn prepare_package<F>(&self, cid: &str, reuse: bool, extract: F) -> Result<PathBuf>
+    where
+        F: FnOnce(&Path) -> Result<()>,
+    {
+        let package_dir = self.root.join(cid);
+        let marker = self.meta_dir("packages").join(cid);
+        if reuse && marker.exists() && package_dir.is_dir() {
+            // update the time of the last use.
+            fs::write(&marker, b"")?;
+            return Ok(package_dir);
+        }
+        let tmp_dir = self
+            .meta_dir("tmp")
+            .join(format!("{cid}-{}", std::process::id()));
+        if tmp_dir.exists() {
+            fs::remove_dir_all(&tmp_dir)?;
+        }
+        fs::create_dir_all(&tmp_dir)
+            .with_context(|| format!("failed to create {}", tmp_dir.display()))?;
+        if let Err(e) = extract(&tmp_dir) {
+            let _ = fs::remove_dir_all(&tmp_dir);
+            return Err(e);
+        }
+        // the package extracted without the marker may be partial, the replaced package
+        // is moved away first, the runs still using it keep their open files.
+        if package_dir.exists() {
+            let old_dir = self
+                .meta_dir("tmp")
+                .join(format!("{cid}-{}-old", std::process::id()));
+            fs::rename(&package_dir, &old_dir)?;
+            fs::remove_dir_all(&old_dir)?;
+        }
+        fs::rename(&tmp_dir, &package_dir)
+            .with_context(|| format!("failed to create {}", package_dir.display()))?;
+        fs::create_dir_all(marker.parent().unwrap())?;
+        fs::write(&marker, b"")?;
+        Ok(package_dir)
+    }
+
+    /// create the scratch directory of the run.
+    pub fn create_run_dir(&self) -> Result<PathBuf> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let name = format!("{}-{}", now.as_millis(), std::process::id());
+        let run_dir = self.meta_dir("runs").join(name);
+        fs::create_dir_all(&run_dir)
+            .with_context(|| format!("failed to create {}", run_dir.display()))?;
+        Ok(run_dir)
+    }
+
+    /// the directory of the car files fetched by the cid.
+    pub fn cache_dir(&self) -> PathBuf {
+        self.meta_dir("cache")
+    }
+
+    /// the extracted packages, the runs and the cached car files, the oldest first.
+    pub fn entries(&self) -> Result<Vec<WorkspaceEntry>> {
+        let mut entries = Vec::new();
+        for marker in read_dir_paths(&self.meta_dir("packages"))? {
+            let name = marker.file_name().unwrap_or_default();
+            let path = self.root.join(name);
+            entries.push(WorkspaceEntry {
+                size: dir_size(&path),
+                last_used: fs::metadata(&marker)?.modified()?,
+                marker: Some(marker),
+                path,
+            });
+        }
+        let runs = read_dir_paths(&self.meta_dir("runs"))?;
+        for path in runs.into_iter().chain(read_dir_paths(&self.cache_dir())?) {
+            entries.push(WorkspaceEntry {
+                size: dir_size(&path),
+                last_used: fs::metadata(&path)?.modified()?,
+                marker: None,
+                path,
+            });
+        }
+        entries.sort_by_key(|e| e.last_used);
+        Ok(entries)
+    }
+
+    /// the entries beyond the retention at the time.
+    pub fn expired(&self, retention: &Retention, now: SystemTime) -> Result<Vec<WorkspaceEntry>> {
+        let mut entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|e| e.size).sum();
+        let mut expired = Vec::new();
+        while let Some(entry) = entries.first() {
+            let age = now.duration_since(entry.last_used).unwrap_or_default();
+            let too_old = retention.max_age.map_or(false, |max| age > max);
+            let too_large = retention.max_size.map_or(false, |max| total > max);
+            if !too_old && !too_large {
+                break;
+            }
+            let entry = entries.remove(0);
+            total -= entry.size;
+            expired.push(entry);
+        }
+        Ok(expired)
+    }
+
+    /// remove the entries and the leftover of the interrupted extractions.
+    pub fn remove(&self, entries: &[WorkspaceEntry]) -> Result<()> {
+        self.remove_at(entries, SystemTime::now())
+    }
+
+    fn remove_at(&self, entries: &[WorkspaceEntry], now: SystemTime) -> Result<()> {
+        for entry in entries {
+            let rs = if entry.path.is_dir() {
+                fs::remove_dir_all(&entry.path)
+            } else if entry.path.exists() {
+                fs::remove_file(&entry.path)
+            } else {
+                Ok(())
+            };
+            rs.with_context(|| format!("failed to remove {}", entry.path.display()))?;
+            if let Some(marker) = entry.marker.as_ref() {
+                fs::remove_file(marker)?;
+            }
+        }
+        // the extractions in progress are kept.
+        for path in read_dir_paths(&self.meta_dir("tmp"))? {
+            let modified = fs::metadata(&path)?.modified()?;
+            let age = now.duration_since(modified).unwrap_or_default();
+            if age > STALE_EXTRACTION_AGE {
+                fs::remove_dir_all(&path)
+                    .with_context(|| format!("failed to remove {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_dir_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        paths.push(entry?.path());
+    }
+    Ok(paths)
+}
+
+/// the total size of the files in the directory.
+fn dir_size(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::read_dir(path)
+            .map(|dir| {
+                dir.filter_map(Result::ok)
+                    .map(|e| dir_size(&e.path()))
+                    .sum()
+            })
+            .unwrap_or(0),
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
+}
+
+/// parse the age like `30m`, `12h` or `7d`, the unit is second without the suffix.
+pub(crate) fn parse_age(age: &str) -> Result<Duration> {
+    let (num, unit) = split_unit(age);
+    let secs = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("invalid age `{age}`, the units are s, m, h and d"),
+    };
+    let num: u64 = num
+        .parse()
+        .with_context(|| format!("invalid age `{age}`"))?;
+    match num.checked_mul(secs) {
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => bail!("the age `{age}` is too large"),
+    }
+}
+
+/// parse the size like `512M` or `10G`, the unit is byte without the suffix.
+pub(crate) fn parse_size(size: &str) -> Result<u64> {
+    let (num, unit) = split_unit(size);
+    let bytes = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => bail!("invalid size `{size}`, the units are K, M, G and T"),
+    };
+    let num: u64 = num
+        .parse()
+        .with_context(|| format!("invalid size `{size}`"))?;
+    match num.checked_mul(bytes) {
+        Some(bytes) => Ok(bytes),
+        None => bail!("the size `{size}` is too large"),
+    }
+}
+
+fn split_unit(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    let pos = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(pos)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prepare_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path());
+        let package = workspace
+            .prepare_package("bafy", true, |path| {
+                fs::write(path.join("config.json"), b"{}")?;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(package, dir.path().join("bafy"));
+        assert!(package.join("config.json").exists());
+        // the extracted package is reused.
+        let reused = workspace
+            .prepare_package("bafy", true, |_| bail!("should not extract again"))
+            .unwrap();
+        assert_eq!(reused, package);
+        // the package not reused is extracted again over the tampered files.
+        fs::write(package.join("config.json"), b"tampered").unwrap();
+        let extracted = workspace
+            .prepare_package("bafy", false, |path| {
+                fs::write(path.join("config.json"), b"{}")?;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(fs::read(extracted.join("config.json")).unwrap(), b"{}");
+        // the failed extraction leaves nothing.
+        assert!(workspace
+            .prepare_package("bafz", true, |_| bail!("broken car"))
+            .is_err());
+        assert!(!dir.path().join("bafz").exists());
+    }
+
+    #[test]
+    fn test_workspace_gc() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path());
+        workspace
+            .prepare_package("bafy", true, |path| {
+                Ok(fs::write(path.join("a.wasm"), [0u8; 100])?)
+            })
+            .unwrap();
+        let run_dir = workspace.create_run_dir().unwrap();
+        fs::write(run_dir.join("runtime.log"), [0u8; 10]).unwrap();
+        assert_eq!(workspace.entries().unwrap().len(), 2);
+
+        let now = SystemTime::now();
+        let retention = Retention {
+            max_age: Some(Duration::from_secs(3600)),
+            max_size: None,
+        };
+        assert!(workspace.expired(&retention, now).unwrap().is_empty());
+        let later = now + Duration::from_secs(7200);
+        assert_eq!(workspace.expired(&retention, later).unwrap().len(), 2);
+
+        let retention = Retention {
+            max_age: None,
+            max_size: Some(50),
+        };
+        let expired = workspace.expired(&retention, now).unwrap();
+        assert!(expired.iter().any(|e| e.path == dir.path().join("bafy")));
+        workspace.remove(&expired).unwrap();
+        assert!(!dir.path().join("bafy").exists());
+        assert!(workspace.entries().unwrap().iter().all(|e| e.size <= 50));
+    }
+
+    #[test]
+    fn test_remove_stale_extraction() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::new(dir.path());
+        let extracting = workspace.meta_dir("tmp").join("bafy-1");
+        fs::create_dir_all(&extracting).unwrap();
+        // the extraction in progress is kept.
+        workspace.remove(&[]).unwrap();
+        assert!(extracting.exists());
+        let later = SystemTime::now() + STALE_EXTRACTION_AGE * 2;
+        workspace.remove_at(&[], later).unwrap();
+        assert!(!extracting.exists());
+    }
+
+    #[test]
+    fn test_parse_retention() {
+        assert_eq!(parse_age("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_age("7d").unwrap(), Duration::from_secs(7 * 86400));
+        assert!(parse_age("7w").is_err());
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10G").unwrap(), 10 << 30);
+        assert!(parse_size("1X").is_err());
+        // the overflow is an error.
+        assert!(parse_age("18446744073709551615d").is_err());
+        assert!(parse_size("18446744073709551615T").is_err());
+    }
+}
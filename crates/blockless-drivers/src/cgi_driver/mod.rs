@@ -3,6 +3,7 @@ mod process;
 
 use std::{collections::HashMap, sync::Once};
 
+pub use process::CgiCommand;
 use process::CgiProcess;
 
 use crate::CgiErrorKind;
@@ -60,7 +61,7 @@ pub async fn cgi_directory_list_read(handle: u32, buf: &mut [u8]) -> Result<u32,
     Ok(copyn as u32)
 }
 
-pub async fn command_and_exec(root_path: &str, cmd: &str) -> Result<u32, CgiErrorKind> {
+pub async fn command_and_exec(root_path: &str, cmd: CgiCommand) -> Result<u32, CgiErrorKind> {
     let handle = increase_handle();
     let mut cgi = CgiProcess::new(root_path.into(), cmd)?;
 
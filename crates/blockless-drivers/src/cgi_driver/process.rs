@@ -44,31 +44,23 @@ fn get_db(path: impl AsRef<Path>) -> MutexGuard<'static, Option<DB>> {
     }
 }
 
-pub struct CgiProcess {
-    root_path: String,
-    child: Option<Child>,
-    command: String,
-    args: Vec<String>,
-    envs: Vec<(String, String)>,
+/// the cgi command of the guest, `{"command": "<alias>", "args": [...], "envs": [...]}`.
+pub struct CgiCommand {
+    pub alias: String,
+    pub args: Vec<String>,
+    pub envs: Vec<(String, String)>,
 }
 
-impl CgiProcess {
-    /// create a CgiProcess with arguments and envriment variables .
-    pub fn new(root_path: String, cmd_with_params: &str) -> Result<Self, CgiErrorKind> {
+impl CgiCommand {
+    pub fn parse(cmd_with_params: &str) -> Result<Self, CgiErrorKind> {
         let obj = match json::parse(cmd_with_params) {
             Ok(o) => o,
             Err(_) => return Err(CgiErrorKind::InvalidParameter),
         };
-        let command = match obj["command"].as_str() {
+        let alias = match obj["command"].as_str() {
             Some(s) => String::from(s),
             None => return Err(CgiErrorKind::InvalidParameter),
         };
-
-        let command = match get_command_with_alias(&root_path, &command) {
-            Some(c) => c.file_name,
-            None => return Err(CgiErrorKind::InvalidExtension),
-        };
-
         let args = match obj["args"] {
             json::JsonValue::Array(ref args) => args
                 .iter()
@@ -90,6 +82,26 @@ impl CgiProcess {
                 .collect(),
             _ => Vec::new(),
         };
+        Ok(Self { alias, args, envs })
+    }
+}
+
+pub struct CgiProcess {
+    root_path: String,
+    child: Option<Child>,
+    command: String,
+    args: Vec<String>,
+    envs: Vec<(String, String)>,
+}
+
+impl CgiProcess {
+    /// create a CgiProcess with arguments and envriment variables .
+    pub fn new(root_path: String, cmd: CgiCommand) -> Result<Self, CgiErrorKind> {
+        let command = match get_command_with_alias(&root_path, &cmd.alias) {
+            Some(c) => c.file_name,
+            None => return Err(CgiErrorKind::InvalidExtension),
+        };
+        let CgiCommand { args, envs, .. } = cmd;
         Ok(Self {
             child: None,
             root_path,
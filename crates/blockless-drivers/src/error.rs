@@ -168,6 +168,8 @@ pub enum CgiErrorKind {
     RuntimeError,
     InvalidParameter,
     InvalidExtension,
+    PermissionDeny,
+    TooManySessions,
 }
 
 impl std::error::Error for CgiErrorKind {}
@@ -179,6 +181,8 @@ impl std::fmt::Display for CgiErrorKind {
             &Self::InvalidHandle => write!(f, "Invalid Error"),
             &Self::InvalidParameter => write!(f, "Invalid parameter"),
             &Self::InvalidExtension => write!(f, "Invalid extension"),
+            &Self::PermissionDeny => write!(f, "Permision deny."),
+            &Self::TooManySessions => write!(f, "Too many sessions"),
         }
     }
 }
@@ -189,6 +193,8 @@ pub enum BlocklessSocketErrorKind {
     ParameterError,
     ConnectionReset,
     AddressInUse,
+    PermissionDeny,
+    TooManySessions,
 }
 
 impl std::error::Error for BlocklessSocketErrorKind {}
@@ -200,6 +206,8 @@ impl std::fmt::Display for BlocklessSocketErrorKind {
             Self::ConnectionReset => write!(f, "Connection Reset Error"),
             Self::AddressInUse => write!(f, "Address In Use"),
             Self::ParameterError => write!(f, "Parameter Error"),
+            Self::PermissionDeny => write!(f, "Permision deny."),
+            Self::TooManySessions => write!(f, "Too many sessions"),
         }
     }
 }
@@ -8,16 +8,43 @@ use cdylib_driver::get_http_driver;
 pub(crate) use cdylib_driver::init_http_driver;
 
 use crate::HttpErrorKind;
+#[cfg(not(feature = "builtin_http"))]
+use log::error;
+use std::sync::Arc;
+use wasi_common::{HttpClientConfig, Quota};
+
+/// the check of the redirect url by the permissions of the guest, the redirects
+/// followed by the driver are denied unless the url is allowed.
+pub type RedirectCheck = Arc<dyn Fn(&str) -> Result<(), HttpErrorKind> + Send + Sync>;
 
+/// the cdylib driver has its own http client, the request is sent once.
 #[cfg(not(feature = "builtin_http"))]
-pub async fn http_req(url: &str, opts: &str) -> Result<(u32, i32), HttpErrorKind> {
+pub async fn http_req(
+    url: &str,
+    opts: &str,
+    _client_config: &HttpClientConfig,
+    quota: &Quota,
+    _check: &RedirectCheck,
+) -> Result<(u32, i32), HttpErrorKind> {
+    quota
+        .request((url.len() + opts.len()) as u64)
+        .map_err(|e| {
+            error!("http: {}", e);
+            HttpErrorKind::TooManySessions
+        })?;
     let driver = get_http_driver().ok_or(HttpErrorKind::InvalidDriver)?;
     driver.http_req(url, opts)
 }
 
 #[cfg(feature = "builtin_http")]
-pub async fn http_req(url: &str, opts: &str) -> Result<(u32, i32), HttpErrorKind> {
-    reqwest_driver::http_req(url, opts).await
+pub async fn http_req(
+    url: &str,
+    opts: &str,
+    client_config: &HttpClientConfig,
+    quota: &Quota,
+    check: &RedirectCheck,
+) -> Result<(u32, i32), HttpErrorKind> {
+    reqwest_driver::http_req(url, opts, client_config, quota, check).await
 }
 
 #[cfg(not(feature = "builtin_http"))]
@@ -27,6 +54,18 @@ pub async fn http_close(fd: u32) -> Result<(), HttpErrorKind> {
     Ok(())
 }
 
+/// the cdylib driver has its own http client.
+#[cfg(not(feature = "builtin_http"))]
+pub fn init_http_client(_client_config: &HttpClientConfig) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// build the shared http client when the app starts, the bad settings fail early.
+#[cfg(feature = "builtin_http")]
+pub fn init_http_client(client_config: &HttpClientConfig) -> anyhow::Result<()> {
+    reqwest_driver::init_http_client(client_config)
+}
+
 #[cfg(feature = "builtin_http")]
 pub async fn http_close(fd: u32) -> Result<(), HttpErrorKind> {
     reqwest_driver::http_close(fd)
@@ -57,3 +96,101 @@ pub async fn http_read_body(fd: u32, buf: &mut [u8]) -> Result<u32, HttpErrorKin
     let driver = get_http_driver().ok_or(HttpErrorKind::InvalidDriver)?;
     driver.http_read_body(fd, buf)
 }
+
+#[cfg(feature = "builtin_http")]
+pub async fn http_open(
+    url: &str,
+    opts: &str,
+    client_config: &HttpClientConfig,
+    quota: Quota,
+    check: RedirectCheck,
+) -> Result<u32, HttpErrorKind> {
+    reqwest_driver::http_open(url, opts, client_config, quota, check).await
+}
+
+/// the cdylib driver has no streamed request body.
+#[cfg(not(feature = "builtin_http"))]
+pub async fn http_open(
+    _url: &str,
+    _opts: &str,
+    _client_config: &HttpClientConfig,
+    _quota: Quota,
+    _check: RedirectCheck,
+) -> Result<u32, HttpErrorKind> {
+    Err(HttpErrorKind::InvalidDriver)
+}
+
+#[cfg(feature = "builtin_http")]
+pub async fn http_write_body(fd: u32, buf: &[u8]) -> Result<u32, HttpErrorKind> {
+    reqwest_driver::http_write_body(fd, buf).await
+}
+
+#[cfg(not(feature = "builtin_http"))]
+pub async fn http_write_body(_fd: u32, _buf: &[u8]) -> Result<u32, HttpErrorKind> {
+    Err(HttpErrorKind::InvalidDriver)
+}
+
+#[cfg(feature = "builtin_http")]
+pub async fn http_send(fd: u32) -> Result<i32, HttpErrorKind> {
+    reqwest_driver::http_send(fd).await
+}
+
+#[cfg(not(feature = "builtin_http"))]
+pub async fn http_send(_fd: u32) -> Result<i32, HttpErrorKind> {
+    Err(HttpErrorKind::InvalidDriver)
+}
+
+// copy the whole json into the buffer, the json can't be truncated.
+// the empty buffer probes the size, the length of the json is returned without copying.
+#[cfg(feature = "builtin_http")]
+fn copy_json(json: String, buf: &mut [u8]) -> Result<u32, HttpErrorKind> {
+    let src = json.as_bytes();
+    if buf.is_empty() {
+        return Ok(src.len() as u32);
+    }
+    if src.len() > buf.len() {
+        return Err(HttpErrorKind::BufferTooSmall);
+    }
+    buf[..src.len()].copy_from_slice(src);
+    Ok(src.len() as u32)
+}
+
+#[cfg(feature = "builtin_http")]
+pub async fn http_read_headers(fd: u32, buf: &mut [u8]) -> Result<u32, HttpErrorKind> {
+    copy_json(reqwest_driver::http_read_headers(fd)?, buf)
+}
+
+/// the cdylib driver can read the headers by name only.
+#[cfg(not(feature = "builtin_http"))]
+pub async fn http_read_headers(_fd: u32, _buf: &mut [u8]) -> Result<u32, HttpErrorKind> {
+    Err(HttpErrorKind::InvalidDriver)
+}
+
+#[cfg(feature = "builtin_http")]
+pub async fn http_read_meta(fd: u32, buf: &mut [u8]) -> Result<u32, HttpErrorKind> {
+    copy_json(reqwest_driver::http_read_meta(fd)?, buf)
+}
+
+#[cfg(not(feature = "builtin_http"))]
+pub async fn http_read_meta(_fd: u32, _buf: &mut [u8]) -> Result<u32, HttpErrorKind> {
+    Err(HttpErrorKind::InvalidDriver)
+}
+
+#[cfg(all(test, feature = "builtin_http"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_copy_json() {
+        let json = r#"{"status":200}"#;
+        assert_eq!(copy_json(json.into(), &mut []), Ok(json.len() as u32));
+        let mut buf = [0u8; 4];
+        assert_eq!(
+            copy_json(json.into(), &mut buf),
+            Err(HttpErrorKind::BufferTooSmall)
+        );
+        let mut buf = [0u8; 32];
+        assert_eq!(copy_json(json.into(), &mut buf), Ok(json.len() as u32));
+        assert_eq!(&buf[..json.len()], json.as_bytes());
+    }
+}
@@ -1,10 +1,27 @@
-use std::{collections::HashMap, pin::Pin, sync::Once, time::Duration};
+use std::{
+    collections::HashMap,
+    fs,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::Duration,
+};
 
+use anyhow::{bail, Context};
 use bytes::{Buf, Bytes};
 use futures_util::StreamExt;
+use lazy_static::lazy_static;
 use log::{debug, error};
+use reqwest::header::{
+    HeaderMap, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, LOCATION,
+    PROXY_AUTHORIZATION,
+};
 use reqwest::Response;
+use wasi_common::{HttpClientConfig, Quota, QuotaExceeded};
 
+use super::RedirectCheck;
 use crate::HttpErrorKind;
 use futures_core;
 use futures_core::Stream;
@@ -16,127 +33,616 @@ struct StreamState {
     buffer: Option<Bytes>,
 }
 
-enum HttpCtx {
-    Response(Response),
-    StreamState(StreamState),
+/// the opened request, the body chunks are sent to the spawned request.
+struct PendingRequest {
+    sender: tokio::sync::mpsc::Sender<Bytes>,
+    response: tokio::task::JoinHandle<Result<Response, HttpErrorKind>>,
 }
 
-/// get the http context
-fn get_ctx() -> Option<&'static mut HashMap<u32, HttpCtx>> {
-    static mut CTX: Option<HashMap<u32, HttpCtx>> = None;
-    static CTX_ONCE: Once = Once::new();
-    CTX_ONCE.call_once(|| unsafe {
-        CTX = Some(HashMap::new());
-    });
-    unsafe { CTX.as_mut() }
+/// the head of the response kept when the response arrives,
+/// so it's read after the body read starts.
+struct ResponseMeta {
+    status: reqwest::StatusCode,
+    version: reqwest::Version,
+    url: reqwest::Url,
+    headers: HeaderMap,
 }
 
-fn increase_fd() -> Option<u32> {
-    static mut MAX_HANDLE: u32 = 0;
-    unsafe {
-        MAX_HANDLE += 1;
-        Some(MAX_HANDLE)
+impl ResponseMeta {
+    fn new(resp: &Response) -> Self {
+        Self {
+            status: resp.status(),
+            version: resp.version(),
+            url: resp.url().clone(),
+            headers: resp.headers().clone(),
+        }
     }
 }
 
-/// request the url and the return the fd handle.
-pub(crate) async fn http_req(url: &str, opts: &str) -> Result<(u32, i32), HttpErrorKind> {
-    let json = match json::parse(opts) {
-        Ok(o) => o,
-        Err(_) => return Err(HttpErrorKind::RequestError),
-    };
-    let method = match json["method"].as_str() {
-        Some(s) => String::from(s),
-        None => return Err(HttpErrorKind::RequestError),
-    };
+enum HttpCtx {
+    Request(PendingRequest),
+    Response(ResponseMeta, Response),
+    StreamState(ResponseMeta, StreamState),
+}
 
-    let mut body = None;
-    if let Some(b) = json["body"].as_str() {
-        body = Some(b.to_string());
-    }
+lazy_static! {
+    static ref CTX: Mutex<HashMap<u32, HttpCtx>> = Mutex::new(HashMap::new());
+}
+
+/// get the http context, the lock is never held across the awaits.
+fn get_ctx() -> MutexGuard<'static, HashMap<u32, HttpCtx>> {
+    CTX.lock().unwrap()
+}
 
-    let connect_timeout = json["connectTimeout"]
-        .as_u64()
-        .map(|s| Duration::from_secs(s));
-    let read_timeout = json["readTimeout"].as_u64().map(|s| Duration::from_secs(s));
+fn increase_fd() -> u32 {
+    static MAX_HANDLE: AtomicU32 = AtomicU32::new(0);
+    MAX_HANDLE.fetch_add(1, Ordering::Relaxed) + 1
+}
 
-    // build the headers from the options json
-    let mut headers = reqwest::header::HeaderMap::new();
-    let header_value = &json["headers"];
+/// the redirect policy of the request, `redirect` in the options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Redirect {
+    /// follow the redirects, at most `maxRedirects` times.
+    Follow(usize),
+    /// return the redirect response to the guest.
+    Manual,
+    /// the redirect is a request error.
+    Error,
+}
 
-    // Check if header_value is a valid string
-    let header_obj = match json::parse(header_value.as_str().unwrap_or_default()) {
-        Ok(o) => o,
-        Err(_) => return Err(HttpErrorKind::HeadersValidationError),
-    };
+/// the request options parsed from the options json.
+#[derive(Debug)]
+struct RequestOptions {
+    method: reqwest::Method,
+    headers: reqwest::header::HeaderMap,
+    body: Option<String>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    redirect: Redirect,
+    // the retries of the connect errors, the timeouts and the server errors.
+    retries: u32,
+    retry_delay: Duration,
+}
 
-    if header_obj.is_object() {
-        for (key, value) in header_obj.entries() {
-            // Handle possible errors from from_bytes
-            let header_name = match reqwest::header::HeaderName::from_bytes(key.as_bytes()) {
-                Ok(name) => name,
-                Err(_) => return Err(HttpErrorKind::HeadersValidationError),
-            };
+impl RequestOptions {
+    fn parse(opts: &str) -> Result<Self, HttpErrorKind> {
+        let json = match json::parse(opts) {
+            Ok(o) => o,
+            Err(_) => return Err(HttpErrorKind::RequestError),
+        };
+        let method = match json["method"].as_str() {
+            Some(s) => s.to_uppercase(),
+            None => return Err(HttpErrorKind::RequestError),
+        };
+        let method = match method.as_str() {
+            "GET" => reqwest::Method::GET,
+            "POST" => reqwest::Method::POST,
+            "PUT" => reqwest::Method::PUT,
+            "PATCH" => reqwest::Method::PATCH,
+            "DELETE" => reqwest::Method::DELETE,
+            "HEAD" => reqwest::Method::HEAD,
+            "OPTIONS" => reqwest::Method::OPTIONS,
+            _ => return Err(HttpErrorKind::InvalidMethod),
+        };
 
-            // Handle possible errors from from_str
-            let header_value =
-                match reqwest::header::HeaderValue::from_str(value.as_str().unwrap_or_default()) {
+        let body = json["body"].as_str().map(String::from);
+
+        let connect_timeout = json["connectTimeout"]
+            .as_u64()
+            .map(|s| Duration::from_secs(s));
+        let read_timeout = json["readTimeout"].as_u64().map(|s| Duration::from_secs(s));
+
+        // build the headers from the options json
+        let mut headers = reqwest::header::HeaderMap::new();
+        let header_value = &json["headers"];
+
+        // Check if header_value is a valid string
+        let header_obj = match json::parse(header_value.as_str().unwrap_or_default()) {
+            Ok(o) => o,
+            Err(_) => return Err(HttpErrorKind::HeadersValidationError),
+        };
+
+        if header_obj.is_object() {
+            for (key, value) in header_obj.entries() {
+                // Handle possible errors from from_bytes
+                let header_name = match reqwest::header::HeaderName::from_bytes(key.as_bytes()) {
+                    Ok(name) => name,
+                    Err(_) => return Err(HttpErrorKind::HeadersValidationError),
+                };
+
+                // Handle possible errors from from_str
+                let header_value = match reqwest::header::HeaderValue::from_str(
+                    value.as_str().unwrap_or_default(),
+                ) {
                     Ok(value) => value,
                     Err(_) => return Err(HttpErrorKind::HeadersValidationError),
                 };
 
-            headers.insert(header_name, header_value);
+                headers.insert(header_name, header_value);
+            }
+        }
+
+        let max_redirects = json["maxRedirects"]
+            .as_usize()
+            .unwrap_or(MAX_REDIRECTS)
+            .min(MAX_REDIRECTS);
+        let redirect = match json["redirect"].as_str() {
+            None | Some("follow") => Redirect::Follow(max_redirects),
+            Some("manual") => Redirect::Manual,
+            Some("error") => Redirect::Error,
+            Some(_) => return Err(HttpErrorKind::RequestError),
+        };
+        let retries = json["retries"].as_u32().unwrap_or(0).min(MAX_RETRIES);
+        let retry_delay =
+            Duration::from_millis(json["retryDelay"].as_u64().unwrap_or(0)).min(MAX_RETRY_DELAY);
+        Ok(Self {
+            method,
+            headers,
+            body,
+            connect_timeout,
+            read_timeout,
+            redirect,
+            retries,
+            retry_delay,
+        })
+    }
+
+    /// only the idempotent requests are retried, a retried `POST` could be applied twice.
+    fn is_idempotent(&self) -> bool {
+        matches!(
+            self.method,
+            reqwest::Method::GET
+                | reqwest::Method::HEAD
+                | reqwest::Method::PUT
+                | reqwest::Method::DELETE
+                | reqwest::Method::OPTIONS
+        )
+    }
+
+    /// send the request, the `connectTimeout` of the request bounds the wait for the
+    /// response head, the `readTimeout` is the timeout of the whole request.
+    async fn send(&self, builder: reqwest::RequestBuilder) -> Result<Response, SendError> {
+        let builder = match self.read_timeout {
+            Some(read_timeout) => builder.timeout(read_timeout),
+            None => builder,
+        };
+        let send = builder.send();
+        match self.connect_timeout {
+            Some(connect_timeout) => tokio::time::timeout(connect_timeout, send)
+                .await
+                .map_err(|_| SendError::Timeout)?
+                .map_err(SendError::Request),
+            None => send.await.map_err(SendError::Request),
+        }
+    }
+
+    /// send the request of the hop with the retries, every attempt is counted
+    /// as a request of the quota.
+    async fn send_with_retries(
+        &self,
+        client: &reqwest::Client,
+        hop: &Hop,
+        quota: &Quota,
+        request_bytes: u64,
+    ) -> Result<Response, HttpErrorKind> {
+        let retries = if self.is_idempotent() {
+            self.retries
+        } else {
+            0
+        };
+        let mut attempt = 0;
+        loop {
+            quota.request(request_bytes).map_err(quota_exceeded)?;
+            let rs = self.send(hop.request(client)).await;
+            let retry = match rs {
+                Ok(ref resp) => resp.status().is_server_error(),
+                Err(ref e) => e.is_retryable(),
+            };
+            if !retry || attempt >= retries {
+                return rs.map_err(send_error);
+            }
+            attempt += 1;
+            debug!("retry the request {} the {} time", hop.url, attempt);
+            tokio::time::sleep(self.retry_delay).await;
+        }
+    }
+
+    /// the next hop of the redirect response, `None` returns the response to the guest.
+    fn next_hop(
+        &self,
+        hop: &Hop,
+        status: reqwest::StatusCode,
+        headers: &HeaderMap,
+        redirects: usize,
+    ) -> Result<Option<Hop>, HttpErrorKind> {
+        use reqwest::StatusCode;
+        if !matches!(
+            status,
+            StatusCode::MOVED_PERMANENTLY
+                | StatusCode::FOUND
+                | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT
+                | StatusCode::PERMANENT_REDIRECT
+        ) {
+            return Ok(None);
+        }
+        let location = match headers.get(LOCATION).and_then(|l| l.to_str().ok()) {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        match self.redirect {
+            Redirect::Manual => return Ok(None),
+            Redirect::Error => {
+                error!("the redirect to {} is not allowed", location);
+                return Err(HttpErrorKind::RequestError);
+            }
+            Redirect::Follow(max) if redirects >= max => {
+                error!("too many redirects, the last is {}", location);
+                return Err(HttpErrorKind::RequestError);
+            }
+            Redirect::Follow(_) => {}
+        }
+        let url = hop
+            .url
+            .join(location)
+            .map_err(|_| HttpErrorKind::InvalidUrl)?;
+        // same as the browsers, 303 and the POST of 301 and 302 are redirected as GET.
+        let to_get = match status {
+            StatusCode::SEE_OTHER => hop.method != reqwest::Method::HEAD,
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => {
+                hop.method == reqwest::Method::POST
+            }
+            _ => false,
+        };
+        // the streamed body is gone, the redirect keeping the body is returned.
+        if hop.streamed && !to_get {
+            return Ok(None);
+        }
+        let mut headers = hop.headers.clone();
+        let (method, body) = if to_get {
+            headers.remove(CONTENT_TYPE);
+            headers.remove(CONTENT_LENGTH);
+            headers.remove(CONTENT_ENCODING);
+            (reqwest::Method::GET, None)
+        } else {
+            (hop.method.clone(), hop.body.clone())
+        };
+        // the credentials are not sent to the other origins.
+        if url.origin() != hop.url.origin() {
+            headers.remove(AUTHORIZATION);
+            headers.remove(COOKIE);
+            headers.remove(PROXY_AUTHORIZATION);
+        }
+        Ok(Some(Hop {
+            method,
+            url,
+            headers,
+            body,
+            streamed: false,
+        }))
+    }
+}
+
+/// a request of the redirects, the client never follows the redirects,
+/// so the redirect policy of the request is applied by the driver.
+struct Hop {
+    method: reqwest::Method,
+    url: reqwest::Url,
+    headers: HeaderMap,
+    body: Option<String>,
+    // the body is streamed by `http_write_body` and can't be sent again.
+    streamed: bool,
+}
+
+impl Hop {
+    fn new(url: &str, opts: &RequestOptions, streamed: bool) -> Result<Self, HttpErrorKind> {
+        let url = reqwest::Url::parse(url).map_err(|_| HttpErrorKind::InvalidUrl)?;
+        Ok(Self {
+            method: opts.method.clone(),
+            url,
+            headers: opts.headers.clone(),
+            body: opts.body.clone(),
+            streamed,
+        })
+    }
+
+    fn request(&self, client: &reqwest::Client) -> reqwest::RequestBuilder {
+        let builder = client
+            .request(self.method.clone(), self.url.clone())
+            .headers(self.headers.clone());
+        match self.body {
+            Some(ref body) => builder.body(body.clone()),
+            None => builder,
+        }
+    }
+}
+
+/// the send error of the request.
+enum SendError {
+    Request(reqwest::Error),
+    // the response head is not arrived in the `connectTimeout`.
+    Timeout,
+}
+
+impl SendError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            SendError::Request(e) => e.is_connect() || e.is_timeout(),
+            SendError::Timeout => true,
+        }
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Request(e) => write!(f, "{}", e),
+            SendError::Timeout => write!(f, "the response is timeout"),
         }
     }
+}
+
+/// build the client of the settings, the client never follows the redirects,
+/// the redirects and the timeouts of the request are applied per request.
+fn build_client(config: &HttpClientConfig) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::ClientBuilder::new();
+    if let Some(proxy) = config.proxy.as_ref() {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    for path in config.root_cas.iter() {
+        let pem = fs::read(path).with_context(|| format!("failed to read the root ca {path}"))?;
+        for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    match (config.client_cert.as_ref(), config.client_key.as_ref()) {
+        (Some(cert), Some(key)) => {
+            let mut pem = fs::read(cert)
+                .with_context(|| format!("failed to read the client certificate {cert}"))?;
+            pem.push(b'\n');
+            pem.extend(
+                fs::read(key).with_context(|| format!("failed to read the client key {key}"))?,
+            );
+            builder = builder.identity(reqwest::Identity::from_pem(&pem)?);
+        }
+        (None, None) => {}
+        _ => bail!("the client certificate and key should be configured together"),
+    }
+    if let Some(connect_timeout) = config.connect_timeout {
+        builder = builder.connect_timeout(Duration::from_secs(connect_timeout));
+    }
+    if let Some(read_timeout) = config.read_timeout {
+        builder = builder.timeout(Duration::from_secs(read_timeout));
+    }
+    if let Some(user_agent) = config.user_agent.as_ref() {
+        builder = builder.user_agent(user_agent);
+    }
+    if let Some(max_idle) = config.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+    if let Some(idle_timeout) = config.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(Duration::from_secs(idle_timeout));
+    }
+    Ok(builder
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?)
+}
+
+lazy_static! {
+    // the clients of the host settings, the options of the requests never add a client.
+    static ref CLIENTS: Mutex<HashMap<HttpClientConfig, reqwest::Client>> =
+        Mutex::new(HashMap::new());
+}
 
-    let mut client_builder = reqwest::ClientBuilder::new();
-    if connect_timeout.is_some() {
-        client_builder = client_builder.connect_timeout(connect_timeout.unwrap());
+fn shared_client(config: &HttpClientConfig) -> anyhow::Result<reqwest::Client> {
+    let mut clients = CLIENTS.lock().unwrap();
+    if let Some(client) = clients.get(config) {
+        return Ok(client.clone());
     }
-    if read_timeout.is_some() {
-        client_builder = client_builder.timeout(read_timeout.unwrap());
+    let client = build_client(config)?;
+    clients.insert(config.clone(), client.clone());
+    Ok(client)
+}
+
+/// build the client of the settings when the app starts, so the bad proxy
+/// or certificates fail before the app runs.
+pub(crate) fn init_http_client(config: &HttpClientConfig) -> anyhow::Result<()> {
+    shared_client(config).map(|_| ())
+}
+
+fn client(config: &HttpClientConfig) -> Result<reqwest::Client, HttpErrorKind> {
+    shared_client(config).map_err(|e| {
+        error!("build the http client error, {:#}", e);
+        HttpErrorKind::RuntimeError
+    })
+}
+
+// the max redirects followed by default, same as reqwest, the guest can't follow more.
+const MAX_REDIRECTS: usize = 10;
+
+// the max retries of a request, the guest can't retry more.
+const MAX_RETRIES: u32 = 5;
+
+// the max delay between the retries.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+// the size of the channel of the request body chunks.
+const BODY_CHANNEL_SIZE: usize = 16;
+
+fn quota_exceeded(e: QuotaExceeded) -> HttpErrorKind {
+    error!("http: {}", e);
+    HttpErrorKind::TooManySessions
+}
+
+fn send_error(e: impl std::fmt::Display) -> HttpErrorKind {
+    error!("request send error, {}", e);
+    HttpErrorKind::RuntimeError
+}
+
+fn insert_response(resp: Response) -> (u32, i32) {
+    let status = resp.status().as_u16() as i32;
+    let fd = increase_fd();
+    get_ctx().insert(fd, HttpCtx::Response(ResponseMeta::new(&resp), resp));
+    (fd, status)
+}
+
+/// request the url and the return the fd handle, every attempt is counted
+/// as a request of the quota, and every redirect is checked by the permissions.
+pub(crate) async fn http_req(
+    url: &str,
+    opts: &str,
+    client_config: &HttpClientConfig,
+    quota: &Quota,
+    check: &RedirectCheck,
+) -> Result<(u32, i32), HttpErrorKind> {
+    let request_bytes = (url.len() + opts.len()) as u64;
+    let opts = RequestOptions::parse(opts)?;
+    let client = client(client_config)?;
+    let mut hop = Hop::new(url, &opts, false)?;
+    let mut resp = opts
+        .send_with_retries(&client, &hop, quota, request_bytes)
+        .await?;
+    let mut redirects = 0;
+    while let Some(next) = opts.next_hop(&hop, resp.status(), resp.headers(), redirects)? {
+        check(next.url.as_str())?;
+        resp = opts
+            .send_with_retries(&client, &next, quota, request_bytes)
+            .await?;
+        hop = next;
+        redirects += 1;
     }
-    let client = client_builder.build().unwrap();
-    let req_method = method.to_lowercase();
-    let req_builder = match req_method.as_str() {
-        "get" => client.get(url),
-        "post" => client.post(url),
-        _ => return Err(HttpErrorKind::RequestError),
+    Ok(insert_response(resp))
+}
+
+/// open the request with the body written in chunks by [`http_write_body`],
+/// the request is sent at once and the body is streamed, so it's never retried.
+/// The redirects are checked by the permissions and counted as the requests of the quota.
+pub(crate) async fn http_open(
+    url: &str,
+    opts: &str,
+    client_config: &HttpClientConfig,
+    quota: Quota,
+    check: RedirectCheck,
+) -> Result<u32, HttpErrorKind> {
+    let request_bytes = (url.len() + opts.len()) as u64;
+    let opts = RequestOptions::parse(opts)?;
+    let client = client(client_config)?;
+    let mut hop = Hop::new(url, &opts, true)?;
+    let (sender, receiver) = tokio::sync::mpsc::channel::<Bytes>(BODY_CHANNEL_SIZE);
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver
+            .recv()
+            .await
+            .map(|chunk| (Ok::<_, std::convert::Infallible>(chunk), receiver))
+    });
+    let request = hop.request(&client).body(reqwest::Body::wrap_stream(body));
+    let response = tokio::spawn(async move {
+        let mut resp = opts.send(request).await.map_err(send_error)?;
+        let mut redirects = 0;
+        while let Some(next) = opts.next_hop(&hop, resp.status(), resp.headers(), redirects)? {
+            check(next.url.as_str())?;
+            quota.request(request_bytes).map_err(quota_exceeded)?;
+            resp = opts.send(next.request(&client)).await.map_err(send_error)?;
+            hop = next;
+            redirects += 1;
+        }
+        Ok::<_, HttpErrorKind>(resp)
+    });
+    let fd = increase_fd();
+    get_ctx().insert(fd, HttpCtx::Request(PendingRequest { sender, response }));
+    Ok(fd)
+}
+
+/// write the chunk into the body of the opened request.
+pub(crate) async fn http_write_body(fd: u32, buf: &[u8]) -> Result<u32, HttpErrorKind> {
+    let sender = match get_ctx().get(&fd) {
+        Some(HttpCtx::Request(req)) => req.sender.clone(),
+        Some(_) => return Err(HttpErrorKind::RuntimeError),
+        None => return Err(HttpErrorKind::InvalidHandle),
     };
-    let resp = req_builder
-        .headers(headers)
-        .body(body.unwrap_or_default())
-        .send()
+    // the request is finished by the server when the receiver is dropped.
+    sender
+        .send(Bytes::copy_from_slice(buf))
         .await
-        .map_err(|e| {
-            error!("request send error, {}", e);
-            HttpErrorKind::RuntimeError
-        })?;
+        .map_err(|_| HttpErrorKind::RequestError)?;
+    Ok(buf.len() as u32)
+}
+
+/// finish the body of the opened request and wait for the response,
+/// the handle is the response handle after it.
+pub(crate) async fn http_send(fd: u32) -> Result<i32, HttpErrorKind> {
+    let req = get_ctx().remove(&fd);
+    let PendingRequest { sender, response } = match req {
+        Some(HttpCtx::Request(req)) => req,
+        Some(other) => {
+            get_ctx().insert(fd, other);
+            return Err(HttpErrorKind::RuntimeError);
+        }
+        None => return Err(HttpErrorKind::InvalidHandle),
+    };
+    drop(sender);
+    let resp = response.await.map_err(|e| {
+        error!("request task error, {}", e);
+        HttpErrorKind::RuntimeError
+    })??;
     let status = resp.status().as_u16() as i32;
-    let fd = increase_fd().unwrap();
-    let ctx = get_ctx().unwrap();
-    ctx.insert(fd, HttpCtx::Response(resp));
-    Ok((fd, status))
+    get_ctx().insert(fd, HttpCtx::Response(ResponseMeta::new(&resp), resp));
+    Ok(status)
 }
 
 /// read from handle
 pub(crate) fn http_read_head(fd: u32, head: &str) -> Result<String, HttpErrorKind> {
-    let ctx = get_ctx().unwrap();
-    let respone = match ctx.get_mut(&fd) {
-        Some(HttpCtx::Response(ref h)) => h,
-        Some(HttpCtx::StreamState(_)) => return Err(HttpErrorKind::RuntimeError),
-        None => return Err(HttpErrorKind::InvalidHandle),
-    };
-    let headers = respone.headers();
-    match headers.get(head) {
+    with_meta(fd, |meta| match meta.headers.get(head) {
         Some(h) => match h.to_str() {
             Ok(s) => Ok(s.into()),
             Err(_) => Err(HttpErrorKind::InvalidEncoding),
         },
         None => Err(HttpErrorKind::HeaderNotFound),
+    })?
+}
+
+/// the response headers as a json list of the `[name, value]` pairs,
+/// the repeated headers like `Set-Cookie` are kept in order.
+fn headers_json(headers: &reqwest::header::HeaderMap) -> String {
+    let mut list = json::JsonValue::new_array();
+    for (name, value) in headers.iter() {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        let _ = list.push(json::array![name.as_str(), value.as_ref()]);
+    }
+    list.dump()
+}
+
+/// the status, the reason phrase, the http version and the final url after the redirects.
+fn meta_json(status: reqwest::StatusCode, version: reqwest::Version, url: &reqwest::Url) -> String {
+    json::object! {
+        status: status.as_u16(),
+        reason: status.canonical_reason().unwrap_or_default(),
+        version: format!("{:?}", version),
+        url: url.as_str(),
+    }
+    .dump()
+}
+
+/// read the head of the response under the lock, before or after the body read.
+fn with_meta<T>(fd: u32, f: impl FnOnce(&ResponseMeta) -> T) -> Result<T, HttpErrorKind> {
+    let ctx = get_ctx();
+    match ctx.get(&fd) {
+        Some(HttpCtx::Response(meta, _)) | Some(HttpCtx::StreamState(meta, _)) => Ok(f(meta)),
+        Some(HttpCtx::Request(_)) => Err(HttpErrorKind::RuntimeError),
+        None => Err(HttpErrorKind::InvalidHandle),
     }
 }
 
+/// read all the headers of the response as json.
+pub(crate) fn http_read_headers(fd: u32) -> Result<String, HttpErrorKind> {
+    with_meta(fd, |meta| headers_json(&meta.headers))
+}
+
+/// read the metadata of the response as json.
+pub(crate) fn http_read_meta(fd: u32) -> Result<String, HttpErrorKind> {
+    with_meta(fd, |meta| meta_json(meta.status, meta.version, &meta.url))
+}
+
 async fn stream_read(state: &mut StreamState, dest: &mut [u8]) -> usize {
     let read_call = |buffer: &mut Bytes, dest: &mut [u8]| -> usize {
         let remaining = buffer.remaining();
@@ -192,31 +698,37 @@ async fn stream_read(state: &mut StreamState, dest: &mut [u8]) -> usize {
 }
 
 pub async fn http_read_body(fd: u32, buf: &mut [u8]) -> Result<u32, HttpErrorKind> {
-    let ctx = get_ctx().unwrap();
-    match ctx.remove(&fd) {
-        Some(HttpCtx::Response(resp)) => {
+    // the state is taken out of the map while reading, so the lock isn't held.
+    let state = get_ctx().remove(&fd);
+    let (meta, mut stream_state) = match state {
+        Some(HttpCtx::Response(meta, resp)) => {
             let stream = Box::pin(resp.bytes_stream());
-            let mut stream_state = StreamState {
+            let stream_state = StreamState {
                 stream,
                 buffer: None,
             };
-            let readn = stream_read(&mut stream_state, buf).await;
-            ctx.insert(fd, HttpCtx::StreamState(stream_state));
-            Ok(readn as u32)
+            (meta, stream_state)
         }
-        Some(HttpCtx::StreamState(mut stream_state)) => {
-            let readn = stream_read(&mut stream_state, buf).await;
-            ctx.insert(fd, HttpCtx::StreamState(stream_state));
-            Ok(readn as u32)
+        Some(HttpCtx::StreamState(meta, stream_state)) => (meta, stream_state),
+        Some(req @ HttpCtx::Request(_)) => {
+            get_ctx().insert(fd, req);
+            return Err(HttpErrorKind::RuntimeError);
         }
         None => return Err(HttpErrorKind::InvalidHandle),
-    }
+    };
+    let readn = stream_read(&mut stream_state, buf).await;
+    get_ctx().insert(fd, HttpCtx::StreamState(meta, stream_state));
+    Ok(readn as u32)
 }
 
 /// close the handle, destroy the memory.
 pub(crate) fn http_close(fd: u32) -> Result<(), HttpErrorKind> {
-    let ctx = get_ctx().unwrap();
-    match ctx.remove(&fd) {
+    let req = get_ctx().remove(&fd);
+    match req {
+        Some(HttpCtx::Request(req)) => {
+            req.response.abort();
+            Ok(())
+        }
         Some(_) => Ok(()),
         None => Err(HttpErrorKind::InvalidHandle),
     }
@@ -348,6 +860,192 @@ mod test {
         assert_eq!(result.err().unwrap(), HttpErrorKind::HeadersValidationError);
     }
 
+    #[test]
+    fn test_request_options() {
+        for method in ["get", "POST", "put", "patch", "delete", "head", "options"] {
+            let opts = format!(r#"{{"method":"{method}","headers":"{{}}"}}"#);
+            let opts = RequestOptions::parse(&opts).unwrap();
+            assert_eq!(opts.method.as_str(), method.to_uppercase());
+            assert_eq!(opts.redirect, Redirect::Follow(MAX_REDIRECTS));
+            assert_eq!(opts.retries, 0);
+        }
+        let opts = RequestOptions::parse(
+            r#"{"method":"put","headers":"{}","redirect":"follow","maxRedirects":2,"retries":3,"retryDelay":500}"#,
+        )
+        .unwrap();
+        assert_eq!(opts.redirect, Redirect::Follow(2));
+        assert_eq!(opts.retries, 3);
+        assert_eq!(opts.retry_delay, Duration::from_millis(500));
+        assert!(opts.is_idempotent());
+        let opts = RequestOptions::parse(
+            r#"{"method":"post","headers":"{}","maxRedirects":100,"retries":100,"retryDelay":3600000}"#,
+        )
+        .unwrap();
+        assert_eq!(opts.retries, MAX_RETRIES);
+        assert_eq!(opts.redirect, Redirect::Follow(MAX_REDIRECTS));
+        assert_eq!(opts.retry_delay, MAX_RETRY_DELAY);
+        assert!(!opts.is_idempotent());
+        let opts = RequestOptions::parse(r#"{"method":"get","headers":"{}","redirect":"manual"}"#)
+            .unwrap();
+        assert_eq!(opts.redirect, Redirect::Manual);
+
+        let rs = RequestOptions::parse(r#"{"method":"trace","headers":"{}"}"#);
+        assert_eq!(rs.err(), Some(HttpErrorKind::InvalidMethod));
+        let rs = RequestOptions::parse(r#"{"method":"get","headers":"{}","redirect":"never"}"#);
+        assert_eq!(rs.err(), Some(HttpErrorKind::RequestError));
+    }
+
+    #[test]
+    fn test_shared_client() {
+        let config = HttpClientConfig {
+            proxy: Some("socks5://127.0.0.1:1080".into()),
+            user_agent: Some("bls/1.0".into()),
+            connect_timeout: Some(5),
+            pool_max_idle_per_host: Some(4),
+            ..Default::default()
+        };
+        assert!(init_http_client(&config).is_ok());
+        assert!(CLIENTS.lock().unwrap().contains_key(&config));
+        // the requests of the config share the client built when the app starts.
+        let clients = CLIENTS.lock().unwrap().len();
+        assert!(client(&config).is_ok());
+        assert_eq!(CLIENTS.lock().unwrap().len(), clients);
+
+        let missing_ca = HttpClientConfig {
+            root_cas: vec!["/no/such/ca.pem".into()],
+            ..Default::default()
+        };
+        assert!(init_http_client(&missing_ca).is_err());
+        assert_eq!(client(&missing_ca).err(), Some(HttpErrorKind::RuntimeError));
+        let cert_only = HttpClientConfig {
+            client_cert: Some("client.pem".into()),
+            ..Default::default()
+        };
+        let e = build_client(&cert_only).unwrap_err();
+        assert!(e.to_string().contains("configured together"));
+    }
+
+    #[test]
+    fn test_next_hop() {
+        use reqwest::StatusCode;
+        let opts = RequestOptions::parse(
+            r#"{"method":"post","headers":"{\"Authorization\":\"a\",\"Content-Type\":\"text/plain\"}","body":"hi","maxRedirects":1}"#,
+        )
+        .unwrap();
+        let hop = Hop::new("https://example.com/a", &opts, false).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(LOCATION, HeaderValue::from_static("/b"));
+
+        // 307 keeps the method and the body.
+        let next = opts
+            .next_hop(&hop, StatusCode::TEMPORARY_REDIRECT, &headers, 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.method, reqwest::Method::POST);
+        assert_eq!(next.url.as_str(), "https://example.com/b");
+        assert_eq!(next.body.as_deref(), Some("hi"));
+        assert!(next.headers.contains_key(AUTHORIZATION));
+
+        // the POST of 302 is redirected as GET without the body.
+        let next = opts
+            .next_hop(&hop, StatusCode::FOUND, &headers, 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.method, reqwest::Method::GET);
+        assert_eq!(next.body, None);
+        assert!(!next.headers.contains_key(CONTENT_TYPE));
+
+        // the credentials are dropped for the other origins.
+        headers.insert(LOCATION, HeaderValue::from_static("https://other.com/"));
+        let next = opts
+            .next_hop(&hop, StatusCode::MOVED_PERMANENTLY, &headers, 0)
+            .unwrap()
+            .unwrap();
+        assert!(!next.headers.contains_key(AUTHORIZATION));
+
+        // the streamed body can't be sent again.
+        let streamed = Hop::new("https://example.com/a", &opts, true).unwrap();
+        let rs = opts.next_hop(&streamed, StatusCode::PERMANENT_REDIRECT, &headers, 0);
+        assert!(rs.unwrap().is_none());
+
+        let rs = opts.next_hop(&hop, StatusCode::FOUND, &headers, 1);
+        assert_eq!(rs.err(), Some(HttpErrorKind::RequestError));
+        assert!(opts
+            .next_hop(&hop, StatusCode::OK, &headers, 0)
+            .unwrap()
+            .is_none());
+
+        let manual =
+            RequestOptions::parse(r#"{"method":"get","headers":"{}","redirect":"manual"}"#)
+                .unwrap();
+        assert!(manual
+            .next_hop(&hop, StatusCode::FOUND, &headers, 0)
+            .unwrap()
+            .is_none());
+        let error =
+            RequestOptions::parse(r#"{"method":"get","headers":"{}","redirect":"error"}"#).unwrap();
+        let rs = error.next_hop(&hop, StatusCode::FOUND, &headers, 0);
+        assert_eq!(rs.err(), Some(HttpErrorKind::RequestError));
+    }
+
+    #[test]
+    fn test_response_metadata() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("text/html"));
+        headers.append("set-cookie", HeaderValue::from_static("a=1"));
+        headers.append("set-cookie", HeaderValue::from_static("b=2"));
+        let list = json::parse(&headers_json(&headers)).unwrap();
+        assert_eq!(list.len(), 3);
+        assert_eq!(list[0][0], "content-type");
+        assert_eq!(list[0][1], "text/html");
+        assert_eq!(list[1][0], "set-cookie");
+        assert_eq!(list[1][1], "a=1");
+        assert_eq!(list[2][1], "b=2");
+
+        let url = reqwest::Url::parse("https://example.com/final").unwrap();
+        let meta = meta_json(
+            reqwest::StatusCode::NOT_FOUND,
+            reqwest::Version::HTTP_11,
+            &url,
+        );
+        let meta = json::parse(&meta).unwrap();
+        assert_eq!(meta["status"], 404);
+        assert_eq!(meta["reason"], "Not Found");
+        assert_eq!(meta["version"], "HTTP/1.1");
+        assert_eq!(meta["url"], "https://example.com/final");
+    }
+
+    #[test]
+    fn test_response_meta_after_body() {
+        let rt = get_runtime();
+        rt.block_on(async move {
+            let mut headers = HeaderMap::new();
+            headers.insert("content-type", HeaderValue::from_static("text/plain"));
+            let meta = ResponseMeta {
+                status: reqwest::StatusCode::OK,
+                version: reqwest::Version::HTTP_11,
+                url: reqwest::Url::parse("https://example.com/").unwrap(),
+                headers,
+            };
+            let state = StreamState {
+                stream: Box::pin(TestStream(vec![Bytes::from_static(b"hello")])),
+                buffer: None,
+            };
+            let fd = increase_fd();
+            get_ctx().insert(fd, HttpCtx::StreamState(meta, state));
+            let mut buf = [0u8; 16];
+            assert_eq!(http_read_body(fd, &mut buf).await, Ok(5));
+            // the head is kept after the body read starts.
+            assert_eq!(http_read_head(fd, "content-type").unwrap(), "text/plain");
+            let meta = json::parse(&http_read_meta(fd).unwrap()).unwrap();
+            assert_eq!(meta["status"], 200);
+            let headers = json::parse(&http_read_headers(fd).unwrap()).unwrap();
+            assert_eq!(headers[0][1], "text/plain");
+            assert!(http_close(fd).is_ok());
+            assert_eq!(http_read_meta(fd).err(), Some(HttpErrorKind::InvalidHandle));
+        });
+    }
+
     #[test]
     fn test_stream_read_full() {
         let rt = get_runtime();
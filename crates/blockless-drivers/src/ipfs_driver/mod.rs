@@ -57,6 +57,11 @@ pub fn increase_fd() -> Option<u32> {
     }
 }
 
+/// the url of the ipfs api, the guest needs the network permission of it.
+pub fn api_url() -> String {
+    format!("http://{HOST}:{PORT}")
+}
+
 pub async fn command(cmd: &str) -> Result<(u16, u32), IpfsErrorKind> {
     let rs = inner_command(cmd).await?;
     let fd = increase_fd().unwrap();
@@ -61,6 +61,12 @@ pub async fn close(handle: u32) -> Result<(), S3ErrorKind> {
     Ok(())
 }
 
+/// the endpoint of the bucket in the config, the guest needs the network permission of it.
+pub fn endpoint(cfg: &str) -> Option<String> {
+    let json = json::parse(cfg).ok()?;
+    json["endpoint"].as_str().map(String::from)
+}
+
 pub async fn bucket_command(cmd: u16, params: &str) -> Result<u32, S3ErrorKind> {
     let content = match cmd {
         1 => {
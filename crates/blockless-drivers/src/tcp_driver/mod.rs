@@ -9,6 +9,19 @@ use wasi_common::WasiFile;
 
 pub struct TcpDriver {}
 
+/// the address of the tcp socket, e.g. `127.0.0.1:80` of `/tcp/127.0.0.1:80`.
+pub(crate) fn socket_addr(socket: &str) -> Result<String, ErrorKind> {
+    let ma = multiaddr::parse(socket.as_bytes()).map_err(|e| {
+        error!("error open:{:?}", e);
+        ErrorKind::DriverBadOpen
+    })?;
+    if ma.paths_ref().is_empty() {
+        error!("error open error path : {}", socket);
+        Err(ErrorKind::DriverBadOpen)?
+    }
+    Ok(ma.paths_ref()[1].value_to_str().to_string())
+}
+
 impl Driver for TcpDriver {
     fn name(&self) -> &str {
         "tcp"
@@ -23,16 +36,8 @@ impl Driver for TcpDriver {
         //this open options.
         let _opts: String = opts.into();
         Box::pin(async move {
-            let ma = multiaddr::parse(socket.as_bytes()).map_err(|e| {
-                error!("error open:{:?}", e);
-                ErrorKind::DriverBadOpen
-            })?;
-            if ma.paths_ref().is_empty() {
-                error!("error open error path : {}", socket);
-                Err(ErrorKind::DriverBadOpen)?
-            }
-            let socket = ma.paths_ref()[1].value_to_str();
-            let stream = match TcpStream::connect(socket).await {
+            let socket = socket_addr(&socket)?;
+            let stream = match TcpStream::connect(&socket).await {
                 Ok(s) => s,
                 Err(e) => {
                     error!("error connect in driver {}: {}", socket, e);
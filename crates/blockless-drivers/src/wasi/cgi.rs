@@ -1,11 +1,11 @@
 #![allow(non_upper_case_globals)]
 use log::error;
-use wasi_common::WasiCtx;
+use wasi_common::{HostModule, QuotaExceeded, WasiCtx};
 use wiggle::{GuestMemory, GuestPtr};
 
 use crate::cgi_driver::{
     self, cgi_directory_list_exec, cgi_directory_list_read, child_stderr_read, child_stdin_write,
-    child_stdout_read, command_and_exec,
+    child_stdout_read, command_and_exec, CgiCommand,
 };
 use crate::CgiErrorKind;
 
@@ -34,10 +34,17 @@ impl From<CgiErrorKind> for types::CgiError {
             CgiErrorKind::InvalidParameter => CgiError::InvalidParameter,
             CgiErrorKind::RuntimeError => CgiError::RuntimeError,
             CgiErrorKind::InvalidExtension => CgiError::InvalidExtension,
+            CgiErrorKind::PermissionDeny => CgiError::PermissionDeny,
+            CgiErrorKind::TooManySessions => CgiError::TooManySessions,
         }
     }
 }
 
+fn quota_exceeded(e: QuotaExceeded) -> CgiErrorKind {
+    error!("cgi: {}", e);
+    CgiErrorKind::TooManySessions
+}
+
 impl wiggle::GuestErrorType for types::CgiError {
     fn success() -> Self {
         Self::Success
@@ -59,16 +66,37 @@ impl blockless_cgi::BlocklessCgi for WasiCtx {
                 CgiErrorKind::InvalidParameter
             })?
             .unwrap();
+        let cmd = CgiCommand::parse(cmd)?;
+        if !self.run_permission(&cmd.alias, &cmd.args, &cmd.envs, "cgi_open") {
+            error!("permission deny: run the extension {}", cmd.alias);
+            return Err(CgiErrorKind::PermissionDeny);
+        }
+        let quota = self.quota();
+        quota.cgi_process().map_err(quota_exceeded)?;
         let root_path = self.config_drivers_root_path_ref().unwrap();
-        command_and_exec(&root_path, cmd).await.map(|r| r.into())
+        command_and_exec(&root_path, cmd)
+            .await
+            .map(|r| r.into())
+            .map_err(|e| {
+                quota.cgi_process_failed();
+                e
+            })
     }
 
     async fn cgi_list_exec(
         &mut self,
         _memory: &mut GuestMemory<'_>,
     ) -> Result<types::CgiHandle, CgiErrorKind> {
+        let quota = self.quota();
+        quota.open_handle(HostModule::Cgi).map_err(quota_exceeded)?;
         let root_path = self.config_drivers_root_path_ref().unwrap();
-        cgi_directory_list_exec(&root_path).await.map(|r| r.into())
+        cgi_directory_list_exec(&root_path)
+            .await
+            .map(|r| r.into())
+            .map_err(|e| {
+                quota.close_handle(HostModule::Cgi);
+                e
+            })
     }
 
     /// read the cgi list
@@ -146,6 +174,8 @@ impl blockless_cgi::BlocklessCgi for WasiCtx {
         _memory: &mut GuestMemory<'_>,
         handle: types::CgiHandle,
     ) -> Result<(), CgiErrorKind> {
-        cgi_driver::close(handle.into())
+        cgi_driver::close(handle.into())?;
+        self.quota().close_handle(HostModule::Cgi);
+        Ok(())
     }
 }
@@ -1,7 +1,11 @@
 #![allow(non_upper_case_globals)]
-use crate::{http_driver, HttpErrorKind};
+use crate::{
+    http_driver::{self, RedirectCheck},
+    HttpErrorKind,
+};
 use log::error;
-use wasi_common::WasiCtx;
+use std::sync::Arc;
+use wasi_common::{HostModule, QuotaExceeded, WasiCtx};
 use wiggle::{GuestMemory, GuestPtr};
 
 wiggle::from_witx!({
@@ -86,6 +90,22 @@ impl From<u32> for HttpErrorKind {
     }
 }
 
+fn quota_exceeded(e: QuotaExceeded) -> HttpErrorKind {
+    error!("http: {}", e);
+    HttpErrorKind::TooManySessions
+}
+
+/// the redirects followed by the driver are checked like the requested url.
+fn redirect_check(ctx: &WasiCtx, api_name: &'static str) -> RedirectCheck {
+    let ctx = ctx.clone();
+    Arc::new(move |url: &str| {
+        ctx.check_net_url(url, api_name).map_err(|e| {
+            error!("permission deny: {}", e);
+            HttpErrorKind::PermissionDeny
+        })
+    })
+}
+
 impl wiggle::GuestErrorType for types::HttpError {
     fn success() -> Self {
         Self::Success
@@ -107,8 +127,8 @@ impl blockless_http::BlocklessHttp for WasiCtx {
                 HttpErrorKind::Utf8Error
             })?
             .unwrap();
-        if !self.resource_permission(url) {
-            error!("Permission Deny");
+        if let Err(e) = self.check_net_url(url, "http_req") {
+            error!("permission deny: {}", e);
             return Err(HttpErrorKind::PermissionDeny);
         }
         let opts: &str = memory
@@ -118,7 +138,19 @@ impl blockless_http::BlocklessHttp for WasiCtx {
                 HttpErrorKind::Utf8Error
             })?
             .unwrap();
-        let (fd, code) = http_driver::http_req(url, opts).await?;
+        // the driver counts the request of every attempt.
+        let quota = self.quota();
+        quota
+            .open_handle(HostModule::Http)
+            .map_err(quota_exceeded)?;
+        let check = redirect_check(self, "http_req");
+        let (fd, code) =
+            http_driver::http_req(url, opts, &self.http_client_config(), &quota, &check)
+                .await
+                .map_err(|e| {
+                    quota.close_handle(HostModule::Http);
+                    e
+                })?;
         Ok((types::HttpHandle::from(fd), types::CodeType::from(code)))
     }
 
@@ -127,7 +159,9 @@ impl blockless_http::BlocklessHttp for WasiCtx {
         _memory: &mut GuestMemory<'_>,
         handle: types::HttpHandle,
     ) -> Result<(), HttpErrorKind> {
-        http_driver::http_close(handle.into()).await
+        http_driver::http_close(handle.into()).await?;
+        self.quota().close_handle(HostModule::Http);
+        Ok(())
     }
 
     async fn http_read_header(
@@ -161,9 +195,12 @@ impl blockless_http::BlocklessHttp for WasiCtx {
         buf: GuestPtr<u8>,
         buf_len: u32,
     ) -> Result<u32, HttpErrorKind> {
+        let quota = self.quota();
+        let buf_len = quota.receive_limit(buf_len).map_err(quota_exceeded)?;
         let mut dest_buf = vec![0; buf_len as _];
         let buf = buf;
         let rs = http_driver::http_read_body(handle.into(), &mut dest_buf[..]).await?;
+        quota.received(rs as _);
         if rs > 0 {
             memory
                 .copy_from_slice(&dest_buf[0..rs as _], buf.as_array(rs))
@@ -171,4 +208,111 @@ impl blockless_http::BlocklessHttp for WasiCtx {
         }
         Ok(rs)
     }
+
+    async fn http_open(
+        &mut self,
+        memory: &mut GuestMemory<'_>,
+        url: GuestPtr<str>,
+        opts: GuestPtr<str>,
+    ) -> Result<types::RequestHandle, HttpErrorKind> {
+        let url: &str = memory
+            .as_str(url)
+            .map_err(|e| {
+                error!("guest url error: {}", e);
+                HttpErrorKind::Utf8Error
+            })?
+            .unwrap();
+        if let Err(e) = self.check_net_url(url, "http_open") {
+            error!("permission deny: {}", e);
+            return Err(HttpErrorKind::PermissionDeny);
+        }
+        let opts: &str = memory
+            .as_str(opts)
+            .map_err(|e| {
+                error!("guest options error: {}", e);
+                HttpErrorKind::Utf8Error
+            })?
+            .unwrap();
+        let quota = self.quota();
+        quota
+            .request((url.len() + opts.len()) as u64)
+            .and_then(|_| quota.open_handle(HostModule::Http))
+            .map_err(quota_exceeded)?;
+        let check = redirect_check(self, "http_open");
+        let fd =
+            http_driver::http_open(url, opts, &self.http_client_config(), quota.clone(), check)
+                .await
+                .map_err(|e| {
+                    quota.close_handle(HostModule::Http);
+                    e
+                })?;
+        Ok(types::RequestHandle::from(fd))
+    }
+
+    async fn http_write_body(
+        &mut self,
+        memory: &mut GuestMemory<'_>,
+        handle: types::RequestHandle,
+        buf: GuestPtr<u8>,
+        buf_len: u32,
+    ) -> Result<u32, HttpErrorKind> {
+        let buf = memory
+            .as_slice(buf.as_array(buf_len))
+            .map_err(|e| {
+                error!("guest body error: {}", e);
+                HttpErrorKind::MemoryAccessError
+            })?
+            .unwrap();
+        self.quota()
+            .send(buf.len() as u64)
+            .map_err(quota_exceeded)?;
+        http_driver::http_write_body(handle.into(), &buf).await
+    }
+
+    async fn http_send(
+        &mut self,
+        _memory: &mut GuestMemory<'_>,
+        handle: types::RequestHandle,
+    ) -> Result<types::CodeType, HttpErrorKind> {
+        let code = http_driver::http_send(handle.into()).await?;
+        Ok(types::CodeType::from(code))
+    }
+
+    async fn http_read_headers(
+        &mut self,
+        memory: &mut GuestMemory<'_>,
+        handle: types::HttpHandle,
+        buf: GuestPtr<u8>,
+        buf_len: u32,
+    ) -> Result<u32, HttpErrorKind> {
+        let mut dest_buf = vec![0; buf_len as _];
+        let rs = http_driver::http_read_headers(handle.into(), &mut dest_buf[..]).await?;
+        // the size probe with the empty buffer copies nothing.
+        if buf_len == 0 {
+            return Ok(rs);
+        }
+        memory
+            .copy_from_slice(&dest_buf[0..rs as _], buf.as_array(rs))
+            .map_err(|_| HttpErrorKind::MemoryAccessError)?;
+        Ok(rs)
+    }
+
+    async fn http_read_meta(
+        &mut self,
+        memory: &mut GuestMemory<'_>,
+        handle: types::HttpHandle,
+        buf: GuestPtr<u8>,
+        buf_len: u32,
+    ) -> Result<u32, HttpErrorKind> {
+        let mut dest_buf = vec![0; buf_len as _];
+        let rs = http_driver::http_read_meta(handle.into(), &mut dest_buf[..]).await?;
+        // the size probe with the empty buffer copies nothing.
+        if buf_len == 0 {
+            return Ok(rs);
+        }
+        memory
+            .copy_from_slice(&dest_buf[0..rs as _], buf.as_array(rs))
+            .map_err(|_| HttpErrorKind::MemoryAccessError)?;
+        Ok(rs)
+    }
 }
@@ -2,7 +2,7 @@
 use crate::ipfs_driver;
 use crate::IpfsErrorKind;
 use log::error;
-use wasi_common::WasiCtx;
+use wasi_common::{HostModule, QuotaExceeded, WasiCtx};
 use wiggle::GuestMemory;
 use wiggle::GuestPtr;
 
@@ -39,6 +39,11 @@ impl From<IpfsErrorKind> for types::IpfsError {
     }
 }
 
+fn quota_exceeded(e: QuotaExceeded) -> IpfsErrorKind {
+    error!("ipfs: {}", e);
+    IpfsErrorKind::TooManySessions
+}
+
 impl wiggle::GuestErrorType for types::IpfsError {
     fn success() -> Self {
         Self::Success
@@ -59,7 +64,19 @@ impl blockless_ipfs::BlocklessIpfs for WasiCtx {
                 IpfsErrorKind::Utf8Error
             })?
             .unwrap();
-        let (status, fd) = ipfs_driver::command(params).await?;
+        if let Err(e) = self.check_net_url(&ipfs_driver::api_url(), "ipfs_command") {
+            error!("permission deny: {}", e);
+            return Err(IpfsErrorKind::PermissionDeny);
+        }
+        let quota = self.quota();
+        quota
+            .request(params.len() as u64)
+            .and_then(|_| quota.open_handle(HostModule::Ipfs))
+            .map_err(quota_exceeded)?;
+        let (status, fd) = ipfs_driver::command(params).await.map_err(|e| {
+            quota.close_handle(HostModule::Ipfs);
+            e
+        })?;
         Ok((types::IpfsHandle::from(fd), types::StatusCode::from(status)))
     }
 
@@ -70,8 +87,11 @@ impl blockless_ipfs::BlocklessIpfs for WasiCtx {
         buf: GuestPtr<u8>,
         buf_len: u32,
     ) -> Result<u32, IpfsErrorKind> {
+        let quota = self.quota();
+        let buf_len = quota.receive_limit(buf_len).map_err(quota_exceeded)?;
         let mut dest_buf = vec![0; buf_len as _];
         let rs = ipfs_driver::read_body(handle.into(), &mut dest_buf[..]).await?;
+        quota.received(rs as _);
         if rs > 0 {
             memory
                 .copy_from_slice(&dest_buf[0..rs as _], buf.as_array(rs))
@@ -86,6 +106,7 @@ impl blockless_ipfs::BlocklessIpfs for WasiCtx {
         handle: types::IpfsHandle,
     ) -> Result<(), IpfsErrorKind> {
         ipfs_driver::close(handle.into()).await?;
+        self.quota().close_handle(HostModule::Ipfs);
         Ok(())
     }
 
@@ -103,6 +124,9 @@ impl blockless_ipfs::BlocklessIpfs for WasiCtx {
                 IpfsErrorKind::InvalidParameter
             })?
             .unwrap();
+        self.quota()
+            .send(buf.len() as u64)
+            .map_err(quota_exceeded)?;
         let rs = ipfs_driver::write_body(handle.into(), buf).await?;
         Ok(rs)
     }
@@ -1,6 +1,5 @@
 #![allow(non_upper_case_globals)]
 use crate::{memory_driver, BlocklessMemoryErrorKind};
-use std::env;
 use wasi_common::WasiCtx;
 use wiggle::{GuestMemory, GuestPtr};
 
@@ -61,25 +60,13 @@ impl blockless_memory::BlocklessMemory for WasiCtx {
         buf: GuestPtr<u8>,
         buf_len: u32,
     ) -> Result<u32, BlocklessMemoryErrorKind> {
-        // get the list of env_vars to load into the wasi assembly
-        // from the BLS_LIST_VARS env var
-        let env_var = match env::var_os("BLS_LIST_VARS") {
-            Some(v) => v.into_string().unwrap(),
-            None => "".to_string(),
-        };
-
-        let mut owned_string: String = "{".to_owned();
-        for s in env_var.split(";") {
-            let env_var = match env::var_os(s) {
-                Some(v) => v.into_string().unwrap(),
-                None => "".to_string(),
-            };
-            owned_string.push_str(&format!("\"{}\": \"{}\",", s, env_var));
+        // the host env vars exposed by the config and allowed by the env permissions.
+        let mut envs = json::JsonValue::new_object();
+        for (name, val) in self.exposed_host_envs() {
+            envs[name] = val.into();
         }
-        owned_string.pop();
-        owned_string.push_str("}");
         let mut dest_buf = vec![0; buf_len as _];
-        let rs = memory_driver::read(&mut dest_buf, owned_string).await?;
+        let rs = memory_driver::read(&mut dest_buf, envs.dump()).await?;
         if rs > 0 {
             memory
                 .copy_from_slice(&dest_buf[0..rs as _], buf.as_array(rs))
@@ -109,6 +109,13 @@ impl blockless_drivers::BlocklessDrivers for WasiCtx {
             Some(d) => d,
             None => return Err(ErrorKind::DriverNotFound),
         };
+        if drv.name() == "tcp" {
+            let addr = crate::tcp_driver::socket_addr(path)?;
+            if !self.connect_permission(&addr, "blockless_open") {
+                log::error!("permission deny: connect {}", addr);
+                return Err(ErrorKind::PermissionDeny);
+            }
+        }
         let mode = FileAccessMode::READ | FileAccessMode::WRITE;
         match drv
             .open(path, opts)
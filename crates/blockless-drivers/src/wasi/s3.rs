@@ -1,7 +1,7 @@
 #![allow(non_upper_case_globals)]
 use crate::{s3_driver, S3ErrorKind};
 use log::error;
-use wasi_common::WasiCtx;
+use wasi_common::{HostModule, QuotaExceeded, WasiCtx};
 use wiggle::{GuestMemory, GuestPtr};
 
 wiggle::from_witx!({
@@ -45,6 +45,19 @@ impl wiggle::GuestErrorType for types::S3Error {
     }
 }
 
+fn quota_exceeded(e: QuotaExceeded) -> S3ErrorKind {
+    error!("s3: {}", e);
+    S3ErrorKind::TooManySessions
+}
+
+fn check_endpoint(ctx: &WasiCtx, cfg: &str, api_name: &str) -> Result<(), S3ErrorKind> {
+    let endpoint = s3_driver::endpoint(cfg).ok_or(S3ErrorKind::InvalidParameter)?;
+    ctx.check_net_url(&endpoint, api_name).map_err(|e| {
+        error!("permission deny: {}", e);
+        S3ErrorKind::PermissionDeny
+    })
+}
+
 #[wiggle::async_trait]
 impl blockless_s3::BlocklessS3 for WasiCtx {
     async fn bucket_command(
@@ -60,7 +73,16 @@ impl blockless_s3::BlocklessS3 for WasiCtx {
                 S3ErrorKind::Utf8Error
             })?
             .unwrap();
-        let rs = s3_driver::bucket_command(cmd, params).await?;
+        check_endpoint(self, params, "bucket_command")?;
+        let quota = self.quota();
+        quota
+            .request(params.len() as u64)
+            .and_then(|_| quota.open_handle(HostModule::S3))
+            .map_err(quota_exceeded)?;
+        let rs = s3_driver::bucket_command(cmd, params).await.map_err(|e| {
+            quota.close_handle(HostModule::S3);
+            e
+        })?;
         Ok(rs.into())
     }
 
@@ -86,6 +108,10 @@ impl blockless_s3::BlocklessS3 for WasiCtx {
                 S3ErrorKind::InvalidParameter
             })?
             .unwrap();
+        check_endpoint(self, cfg, "bucket_put_object")?;
+        self.quota()
+            .request((cfg.len() + params.len()) as u64)
+            .map_err(quota_exceeded)?;
         s3_driver::bucket_put_object(cfg, params).await
     }
 
@@ -96,8 +122,11 @@ impl blockless_s3::BlocklessS3 for WasiCtx {
         buf: GuestPtr<u8>,
         buf_len: u32,
     ) -> Result<u32, S3ErrorKind> {
+        let quota = self.quota();
+        let buf_len = quota.receive_limit(buf_len).map_err(quota_exceeded)?;
         let mut dest_buf = vec![0; buf_len as _];
         let rs = s3_driver::read(handle.into(), &mut dest_buf).await?;
+        quota.received(rs as _);
         if rs > 0 {
             memory
                 .copy_from_slice(&dest_buf[0..rs as _], buf.as_array(rs))
@@ -111,6 +140,8 @@ impl blockless_s3::BlocklessS3 for WasiCtx {
         _memory: &mut GuestMemory<'_>,
         handle: types::S3Handle,
     ) -> Result<(), S3ErrorKind> {
-        s3_driver::close(handle.into()).await
+        s3_driver::close(handle.into()).await?;
+        self.quota().close_handle(HostModule::S3);
+        Ok(())
     }
 }
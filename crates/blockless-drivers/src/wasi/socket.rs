@@ -3,7 +3,7 @@ use std::sync::Arc;
 use wasi_common::{
     file::{FileAccessMode, FileEntry},
     sync::net::Socket,
-    WasiCtx, WasiFile,
+    HostModule, QuotaExceeded, QuotaFile, WasiCtx, WasiFile,
 };
 
 use crate::BlocklessSocketErrorKind;
@@ -41,6 +41,8 @@ impl From<BlocklessSocketErrorKind> for types::SocketError {
             BlocklessSocketErrorKind::ConnectRefused => SocketError::ConnectionRefused,
             BlocklessSocketErrorKind::ConnectionReset => SocketError::ConnectionReset,
             BlocklessSocketErrorKind::ParameterError => SocketError::ParameterError,
+            BlocklessSocketErrorKind::PermissionDeny => SocketError::PermissionDeny,
+            BlocklessSocketErrorKind::TooManySessions => SocketError::TooManySessions,
         }
     }
 }
@@ -73,6 +75,43 @@ async fn tcp_bind(addr: &str) -> Result<Box<dyn WasiFile>, BlocklessSocketErrorK
     Ok(wasi_file)
 }
 
+fn quota_exceeded(e: QuotaExceeded) -> BlocklessSocketErrorKind {
+    error!("socket: {}", e);
+    BlocklessSocketErrorKind::TooManySessions
+}
+
+/// the socket is counted as an open handle until the guest closes it.
+fn quota_socket(
+    ctx: &WasiCtx,
+    file: Box<dyn WasiFile>,
+) -> Result<Box<dyn WasiFile>, BlocklessSocketErrorKind> {
+    QuotaFile::open(file, ctx.quota(), HostModule::Socket)
+        .map(|f| Box::new(f) as Box<dyn WasiFile>)
+        .map_err(quota_exceeded)
+}
+
+/// the guest needs the connect permission of the address to connect.
+fn check_connect(
+    ctx: &WasiCtx,
+    addr: &str,
+    api_name: &str,
+) -> Result<(), BlocklessSocketErrorKind> {
+    if !ctx.connect_permission(addr, api_name) {
+        error!("permission deny: connect {}", addr);
+        return Err(BlocklessSocketErrorKind::PermissionDeny);
+    }
+    Ok(())
+}
+
+/// the guest needs the listen permission of the address to bind.
+fn check_listen(ctx: &WasiCtx, addr: &str, api_name: &str) -> Result<(), BlocklessSocketErrorKind> {
+    if !ctx.listen_permission(addr, api_name) {
+        error!("permission deny: listen on {}", addr);
+        return Err(BlocklessSocketErrorKind::PermissionDeny);
+    }
+    Ok(())
+}
+
 #[wiggle::async_trait]
 impl blockless_socket::BlocklessSocket for WasiCtx {
     async fn create_tcp_bind_socket(
@@ -84,8 +123,13 @@ impl blockless_socket::BlocklessSocket for WasiCtx {
             .as_str(bind)
             .map_err(|_| BlocklessSocketErrorKind::ParameterError)?
             .unwrap();
+        check_listen(self, &addr, "create_tcp_bind_socket")?;
         let mode = FileAccessMode::READ | FileAccessMode::WRITE;
-        match tcp_bind(&addr).await.map(|f| Arc::new(FileEntry::new(f, mode))) {
+        match tcp_bind(&addr)
+            .await
+            .and_then(|f| quota_socket(self, f))
+            .map(|f| Arc::new(FileEntry::new(f, mode)))
+        {
             Ok(f) => {
                 let fd_num = self.table().push(f).unwrap();
                 let fd = types::SocketHandle::from(fd_num);
@@ -104,8 +148,14 @@ impl blockless_socket::BlocklessSocket for WasiCtx {
             .as_str(target)
             .map_err(|_| BlocklessSocketErrorKind::ParameterError)?
             .unwrap();
+        check_connect(self, &addr, "tcp_connect")?;
+        self.quota().request(0).map_err(quota_exceeded)?;
         let mode = FileAccessMode::READ | FileAccessMode::WRITE;
-        match tcp_connect(&addr).await.map(|f| Arc::new(FileEntry::new(f, mode))) {
+        match tcp_connect(&addr)
+            .await
+            .and_then(|f| quota_socket(self, f))
+            .map(|f| Arc::new(FileEntry::new(f, mode)))
+        {
             Ok(f) => {
                 let fd_num = self.table().push(f).unwrap();
                 let fd = types::SocketHandle::from(fd_num);
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditDecision {
+    Allow,
+    Deny,
+}
+
+/// The record of a permission decision, written as a json line into the audit file.
+#[derive(Debug, Serialize)]
+pub struct AuditRecord<'a> {
+    /// the milliseconds since the unix epoch.
+    pub timestamp: u64,
+    pub resource: &'a str,
+    pub api_name: &'a str,
+    pub decision: AuditDecision,
+}
+
+/// The denied accesses of the same resource by the same api.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DeniedAccess {
+    pub resource: String,
+    pub api_name: String,
+    pub count: u64,
+}
+
+#[derive(Default)]
+struct AuditLogInner {
+    writer: Option<Box<dyn Write + Send>>,
+    denied: BTreeMap<(String, String), u64>,
+    // the audit the records are forwarded to.
+    forward: Option<AuditLog>,
+}
+
+/// The audit of the permission decisions, the clones share the same file and summary,
+/// so the child permissions and the threads are audited together.
+#[derive(Clone, Default)]
+pub struct AuditLog(Arc<Mutex<AuditLogInner>>);
+
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLog").finish_non_exhaustive()
+    }
+}
+
+impl AuditLog {
+    /// append the records to the file.
+    pub fn open(&self, path: &Path) -> anyhow::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open the audit log {}", path.display()))?;
+        self.set_writer(Box::new(file));
+        Ok(())
+    }
+
+    pub fn set_writer(&self, writer: Box<dyn Write + Send>) {
+        self.0.lock().unwrap().writer = Some(writer);
+    }
+
+    /// forward the records to the other audit, e.g. the audit of the app.
+    pub fn forward_to(&self, other: &AuditLog) {
+        self.0.lock().unwrap().forward = Some(other.clone());
+    }
+
+    pub fn record(&self, resource: &str, api_name: &str, allowed: bool) {
+        let forward = self.0.lock().unwrap().forward.clone();
+        if let Some(forward) = forward {
+            return forward.record(resource, api_name, allowed);
+        }
+        let decision = if allowed {
+            AuditDecision::Allow
+        } else {
+            AuditDecision::Deny
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let record = AuditRecord {
+            timestamp,
+            resource,
+            api_name,
+            decision,
+        };
+        let mut inner = self.0.lock().unwrap();
+        if !allowed {
+            *inner
+                .denied
+                .entry((resource.to_string(), api_name.to_string()))
+                .or_default() += 1;
+        }
+        if let Some(writer) = inner.writer.as_mut() {
+            // the audit failure should not break the app.
+            let rs = serde_json::to_string(&record)
+                .map_err(std::io::Error::from)
+                .and_then(|line| writeln!(writer, "{line}"))
+                .and_then(|_| writer.flush());
+            if let Err(e) = rs {
+                log::error!("failed to write the audit log: {e}");
+            }
+        }
+    }
+
+    /// record the decision of the check and pass the result through.
+    pub fn audited<T, E>(&self, resource: &str, api_name: &str, rs: Result<T, E>) -> Result<T, E> {
+        self.record(resource, api_name, rs.is_ok());
+        rs
+    }
+
+    /// the summary of the denied accesses ordered by the resource.
+    pub fn denied_summary(&self) -> Vec<DeniedAccess> {
+        let inner = self.0.lock().unwrap();
+        inner
+            .denied
+            .iter()
+            .map(|((resource, api_name), count)| DeniedAccess {
+                resource: resource.clone(),
+                api_name: api_name.clone(),
+                count: *count,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_audit_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let audit = AuditLog::default();
+        audit.open(&path).unwrap();
+        let shared = audit.clone();
+        audit.record("/data/a.txt", "open", true);
+        shared.record("/etc/passwd", "open", false);
+        let rs: Result<(), &str> = shared.audited("/etc/passwd", "open", Err("denied"));
+        assert!(rs.is_err());
+        let module = AuditLog::default();
+        module.forward_to(&audit);
+        module.record("evil.net:443", "http_req", false);
+
+        let lines = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<serde_json::Value> = lines
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0]["resource"], "/data/a.txt");
+        assert_eq!(records[0]["api_name"], "open");
+        assert_eq!(records[0]["decision"], "allow");
+        assert_eq!(records[1]["decision"], "deny");
+        assert!(records[1]["timestamp"].as_u64().unwrap() > 0);
+
+        let denied = audit.denied_summary();
+        assert_eq!(
+            denied,
+            vec![
+                DeniedAccess {
+                    resource: "/etc/passwd".into(),
+                    api_name: "open".into(),
+                    count: 2,
+                },
+                DeniedAccess {
+                    resource: "evil.net:443".into(),
+                    api_name: "http_req".into(),
+                    count: 1,
+                },
+            ]
+        );
+    }
+}
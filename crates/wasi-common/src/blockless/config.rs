@@ -1,8 +1,9 @@
 use crate::Permission;
-use anyhow::{bail, Ok};
+use anyhow::bail;
 use bls_permissions::PermissionsOptions;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     net::SocketAddr,
     path::{Path, PathBuf},
     str::FromStr,
@@ -10,6 +11,9 @@ use std::{
 use wasmtime::OptLevel;
 
 use super::set_is_inherit_stdin;
+use super::BlocklessConfigFile;
+use super::PackageFs;
+use super::QuotaTracker;
 
 const ENTRY: &str = "_start";
 
@@ -35,6 +39,31 @@ impl From<&str> for LoggerLevel {
     }
 }
 
+impl LoggerLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoggerLevel::DEBUG => "debug",
+            LoggerLevel::INFO => "info",
+            LoggerLevel::WARN => "warn",
+            LoggerLevel::TRACE => "trace",
+            LoggerLevel::ERROR => "error",
+        }
+    }
+}
+
+impl Serialize for LoggerLevel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for LoggerLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let level = String::deserialize(deserializer)?;
+        Ok(level.as_str().into())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Stdin {
     Inherit,
@@ -61,7 +90,71 @@ pub enum Stderr {
     FileName(String),
 }
 
-#[derive(Clone)]
+/// the escape of the fixed stdin, `{"fixed": "inherit"}` is the fixed input `inherit`.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StdinValue {
+    Str(String),
+    #[serde(deny_unknown_fields)]
+    Fixed {
+        fixed: String,
+    },
+}
+
+/// `"inherit"` inherits the host stdin, any other string is fed to the module as stdin,
+/// the fixed input `inherit` is written as `{"fixed": "inherit"}`.
+impl Serialize for Stdin {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Stdin::Inherit => serializer.serialize_str("inherit"),
+            Stdin::Fixed(s) if s == "inherit" => {
+                StdinValue::Fixed { fixed: s.clone() }.serialize(serializer)
+            }
+            Stdin::Fixed(s) => serializer.serialize_str(s),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Stdin {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match StdinValue::deserialize(deserializer)? {
+            StdinValue::Str(stdin) if stdin == "inherit" => Ok(Stdin::Inherit),
+            StdinValue::Str(stdin) => Ok(Stdin::Fixed(stdin)),
+            StdinValue::Fixed { fixed } => Ok(Stdin::Fixed(fixed)),
+        }
+    }
+}
+
+/// `"inherit"` and `"null"` are keywords, any other string is a file name in the root path.
+macro_rules! stdio_output_serde {
+    ($stdio:ident) => {
+        impl Serialize for $stdio {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self {
+                    $stdio::Null => serializer.serialize_str("null"),
+                    $stdio::Inherit => serializer.serialize_str("inherit"),
+                    $stdio::FileName(f) => serializer.serialize_str(f),
+                }
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $stdio {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                match s.as_str() {
+                    "null" => Ok($stdio::Null),
+                    "inherit" => Ok($stdio::Inherit),
+                    _ => Ok($stdio::FileName(s)),
+                }
+            }
+        }
+    };
+}
+
+stdio_output_serde!(Stdout);
+stdio_output_serde!(Stderr);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DriverConfig {
     schema: String,
     path: String,
@@ -81,8 +174,9 @@ impl DriverConfig {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub enum ModuleType {
+    #[default]
     Module,
     Entry,
 }
@@ -116,14 +210,62 @@ impl ModuleType {
             _ => Self::Module,
         }
     }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Module => "module",
+            Self::Entry => "entry",
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+impl Serialize for ModuleType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ModuleType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let ty = String::deserialize(deserializer)?;
+        Ok(ModuleType::parse_from_str(&ty))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlocklessModule {
+    /// `entry` for the module exporting the entry function, `module` for libraries.
+    #[serde(rename = "type", default)]
     pub module_type: ModuleType,
+    /// the name the module is linked as.
+    #[serde(default)]
     pub name: String,
+    /// the wasm file path.
+    #[serde(default)]
     pub file: String,
+    /// the md5 checksum of the wasm file, empty skips the check.
+    /// Deprecated, it's only verified when the `digest` is empty.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub md5: String,
+    /// the checksum of the wasm file with the algorithm, e.g. `sha256:<hex>` or `blake3:<hex>`,
+    /// empty skips the check.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub digest: String,
+    /// the permissions of the library module, it shares the permissions of the app if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<ModulePermissions>,
+}
+
+/// The permissions scoped to a library module, the module is granted nothing else.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModulePermissions {
+    /// the resource urls, as the `permissions` of the app.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<Permission>,
+    /// the grants like `allow_read` and `allow_net`, the prompter and the audit log of the
+    /// app are used.
+    #[serde(flatten)]
+    pub permissions_config: PermissionsConfig,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -132,26 +274,93 @@ pub enum BlocklessConfigVersion {
     Version1,
 }
 
-impl From<usize> for BlocklessConfigVersion {
-    fn from(value: usize) -> Self {
+impl TryFrom<usize> for BlocklessConfigVersion {
+    type Error = anyhow::Error;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
         match value {
-            1 => BlocklessConfigVersion::Version1,
-            0 => BlocklessConfigVersion::Version0,
-            _ => unreachable!("unknown configure version: {value}."),
+            1 => Ok(BlocklessConfigVersion::Version1),
+            0 => Ok(BlocklessConfigVersion::Version0),
+            _ => bail!("unknown configure version: {value}, only 0 and 1 accepted."),
+        }
+    }
+}
+
+impl Serialize for BlocklessConfigVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            BlocklessConfigVersion::Version0 => serializer.serialize_u64(0),
+            BlocklessConfigVersion::Version1 => serializer.serialize_u64(1),
         }
     }
 }
 
-#[derive(Default, Clone)]
+impl<'de> Deserialize<'de> for BlocklessConfigVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match u64::deserialize(deserializer)? {
+            0 => Ok(BlocklessConfigVersion::Version0),
+            1 => Ok(BlocklessConfigVersion::Version1),
+            v => Err(de::Error::custom(format!(
+                "unknown configure version: {v}, only 0 and 1 accepted."
+            ))),
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct StoreLimited {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_memory_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_table_elements: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_instances: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tables: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_memories: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub trap_on_grow_failure: Option<bool>,
 }
 
+/// The http client shared by the http requests of the app, the requests with the same
+/// settings reuse the pooled connections.
+#[derive(Default, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpClientConfig {
+    /// the http or socks proxy, e.g. `http://proxy:3128` or `socks5://proxy:1080`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// the PEM files of the root certificates trusted besides the builtin roots.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub root_cas: Vec<String>,
+    /// the PEM files of the client certificate and the private key of the mutual TLS.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<String>,
+    /// the default timeouts in seconds, the options of the request win.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// the max idle connections kept for each host.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_max_idle_per_host: Option<usize>,
+    /// the seconds the idle connections are kept.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_idle_timeout: Option<u64>,
+}
+
+impl HttpClientConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == Default::default()
+    }
+}
+
 pub trait BlsOptions {
     const OPTIONS: &'static [OptionDesc];
 }
@@ -163,6 +372,7 @@ pub struct OptionDesc {
 
 macro_rules! bls_options {
     (
+        $name:literal,
         $(#[$attr:meta])*
         pub struct $opts:ident {
             $(
@@ -186,7 +396,7 @@ macro_rules! bls_options {
                         $(
                         stringify!($opt) => self.$opt = Some(OptionParser::<String>::parse(&item.1)?),
                         )+
-                        _ => bail!("there is no optimize argument: {}", item.0),
+                        _ => bail!("there is no {} argument: {}", $name, item.0),
                     }
                 }
                 Ok(())
@@ -197,6 +407,34 @@ macro_rules! bls_options {
             }
         }
 
+        /// serialized as a map of the configured options, the values are
+        /// the same as the ones accepted by the command line.
+        impl Serialize for $opts {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+                let mut map = serializer.serialize_map(None)?;
+                $(
+                if let Some(ref v) = self.$opt {
+                    map.serialize_entry(stringify!($opt), &OptionFormat::format(v))?;
+                }
+                )+
+                map.end()
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $opts {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let items = std::collections::BTreeMap::<String, OptionValue>::deserialize(deserializer)?;
+                let items = items
+                    .into_iter()
+                    .map(|(k, v)| (k, v.to_string()))
+                    .collect();
+                let mut opts = Self::default();
+                opts.config(items).map_err(de::Error::custom)?;
+                Ok(opts)
+            }
+        }
+
         impl BlsOptions for $opts {
             const OPTIONS: &'static [OptionDesc] = &[
                 $(
@@ -210,6 +448,79 @@ macro_rules! bls_options {
     }
 }
 
+/// The value of an optimize option in the config file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OptionValue {
+    Bool(bool),
+    Number(u64),
+    Text(String),
+}
+
+impl std::fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptionValue::Bool(b) => write!(f, "{b}"),
+            OptionValue::Number(n) => write!(f, "{n}"),
+            OptionValue::Text(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+/// The reverse of [`OptionParser`], format the option value for serialization.
+pub trait OptionFormat {
+    fn format(&self) -> OptionValue;
+}
+
+impl OptionFormat for u32 {
+    fn format(&self) -> OptionValue {
+        OptionValue::Number((*self).into())
+    }
+}
+
+impl OptionFormat for usize {
+    fn format(&self) -> OptionValue {
+        OptionValue::Number(*self as u64)
+    }
+}
+
+impl OptionFormat for u64 {
+    fn format(&self) -> OptionValue {
+        OptionValue::Number(*self)
+    }
+}
+
+impl OptionFormat for bool {
+    fn format(&self) -> OptionValue {
+        OptionValue::Bool(*self)
+    }
+}
+
+impl OptionFormat for OptLevel {
+    fn format(&self) -> OptionValue {
+        #[allow(unreachable_patterns)]
+        let level = match self {
+            OptLevel::None => "n",
+            OptLevel::Speed => "s",
+            OptLevel::SpeedAndSize => "ss",
+            _ => "s",
+        };
+        OptionValue::Text(level.to_string())
+    }
+}
+
+impl OptionFormat for wasmtime::RegallocAlgorithm {
+    fn format(&self) -> OptionValue {
+        #[allow(unreachable_patterns)]
+        let algorithm = match self {
+            wasmtime::RegallocAlgorithm::Backtracking => "backtracking",
+            wasmtime::RegallocAlgorithm::SinglePass => "single-pass",
+            _ => "backtracking",
+        };
+        OptionValue::Text(algorithm.to_string())
+    }
+}
+
 pub trait OptionParser<T>: Sized {
     fn parse(v: &T) -> anyhow::Result<Self>
     where
@@ -292,6 +603,7 @@ impl OptionParser<&str> for PermissionGrant {
 }
 
 bls_options! {
+    "optimize",
     #[derive(PartialEq, Clone)]
     pub struct OptimizeOpts {
         /// Optimization level of generated code (0-2, s; default: 2)
@@ -439,7 +751,31 @@ bls_options! {
     }
 }
 
-#[derive(Clone)]
+bls_options! {
+    "quota",
+    #[derive(PartialEq, Clone)]
+    pub struct ResourceQuotas {
+        /// The maximum concurrent open handles of each host module, the
+        /// http, ipfs, s3, cgi and socket handles are counted separately.
+        pub max_open_handles: Option<u32>,
+
+        /// The maximum requests of the run, including the http, ipfs and s3
+        /// requests and the tcp connections.
+        pub max_requests: Option<u64>,
+
+        /// The maximum bytes sent by the requests of the run.
+        pub max_bytes_sent: Option<u64>,
+
+        /// The maximum bytes received from the responses of the run.
+        pub max_bytes_received: Option<u64>,
+
+        /// The maximum cgi processes spawned in the run.
+        pub max_cgi_processes: Option<u32>,
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Stdio {
     pub stdin: Stdin,
     pub stdout: Stdout,
@@ -478,7 +814,7 @@ impl Default for Stdio {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BlsNnGraph {
     pub format: String,
     pub dir: String,
@@ -496,15 +832,115 @@ impl Default for PermissionGrant {
     }
 }
 
-#[derive(Clone, Debug)]
+/// `true` grants all, a list or a comma separated string grants the listed paths.
+impl Serialize for PermissionGrant {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            PermissionGrant::All => serializer.serialize_bool(true),
+            PermissionGrant::List(list) => list.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PermissionGrant {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Grant {
+            Flag(bool),
+            Text(String),
+            List(Vec<String>),
+        }
+        match Grant::deserialize(deserializer)? {
+            Grant::Flag(true) => Ok(PermissionGrant::All),
+            Grant::Flag(false) => Err(de::Error::custom(
+                "permission grant should be `true`, a list or a comma separated string, remove the key to grant nothing",
+            )),
+            Grant::Text(s) => {
+                <PermissionGrant as OptionParser<&str>>::parse(&s.as_str()).map_err(de::Error::custom)
+            }
+            Grant::List(list) => Ok(PermissionGrant::List(list)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PermissionsConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub allow_read: Option<PermissionGrant>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub allow_write: Option<PermissionGrant>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub deny_read: Option<PermissionGrant>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub deny_write: Option<PermissionGrant>,
+    /// the hosts the guest can connect, e.g. `api.example.com:443` or `127.0.0.1`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_net: Option<PermissionGrant>,
+    /// the hosts the guest can never connect, it wins over the allowed hosts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deny_net: Option<PermissionGrant>,
+    /// the addresses the guest can listen on, e.g. `127.0.0.1:8080` or `0.0.0.0`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_listen: Option<PermissionGrant>,
+    /// the addresses the guest can connect by the sockets, e.g. `db.example.com:5432`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_connect: Option<PermissionGrant>,
+    /// the aliases of the cgi extensions the guest can run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_run: Option<PermissionGrant>,
+    /// the environment variables the guest can read, all the configured ones if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_env: Option<PermissionGrant>,
+    /// the environment variables the guest can never read, it wins over the allowed ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deny_env: Option<PermissionGrant>,
+    /// the arguments and the environment variables allowed for the aliases.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub run_rules: BTreeMap<String, RunRule>,
+    /// the prompter asked for the permissions not granted, e.g. `deny` or `unix:<socket>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompter: Option<PrompterKind>,
+    /// the file the permission decisions are appended to as json lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<PathBuf>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
     pub allow_all: bool,
 }
 
+impl PermissionsConfig {
+    /// merge the other config into this one, the grants set in the other config win.
+    pub fn merge(&mut self, other: PermissionsConfig) {
+        macro_rules! merge_grant {
+            ($($field:ident),+) => {
+                $(
+                if other.$field.is_some() {
+                    self.$field = other.$field;
+                }
+                )+
+            };
+        }
+        merge_grant!(
+            allow_read,
+            allow_write,
+            deny_read,
+            deny_write,
+            allow_net,
+            deny_net,
+            allow_listen,
+            allow_connect,
+            allow_run,
+            allow_env,
+            deny_env,
+            prompter,
+            audit_log
+        );
+        self.run_rules.extend(other.run_rules);
+        self.allow_all |= other.allow_all;
+    }
+}
+
 impl Into<PermissionsOptions> for &PermissionsConfig {
     fn into(self) -> PermissionsOptions {
         let mut options = PermissionsOptions::default();
@@ -526,6 +962,8 @@ impl Into<PermissionsOptions> for &PermissionsConfig {
         set_perm!(&self.allow_write, options.allow_write);
         set_perm!(&self.deny_read, options.deny_read);
         set_perm!(&self.deny_write, options.deny_write);
+        set_perm!(&self.allow_net, options.allow_net);
+        set_perm!(&self.deny_net, options.deny_net);
         options.prompt = true;
         options.allow_all = self.allow_all;
         options
@@ -539,12 +977,81 @@ impl Default for PermissionsConfig {
             allow_write: None,
             deny_read: None,
             deny_write: None,
+            allow_net: None,
+            deny_net: None,
+            allow_listen: None,
+            allow_connect: None,
+            allow_run: None,
+            allow_env: None,
+            deny_env: None,
+            run_rules: BTreeMap::new(),
+            prompter: None,
+            audit_log: None,
             allow_all: false,
         }
     }
 }
 
-#[derive(Clone)]
+/// The restriction of the arguments and the environment variables of a cgi extension.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunRule {
+    /// the allowed arguments, the trailing `*` matches any suffix, e.g. `--format=*`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<String>>,
+    /// the allowed names of the environment variables.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub envs: Option<Vec<String>>,
+}
+
+impl RunRule {
+    pub fn allows(&self, args: &[String], envs: &[(String, String)]) -> bool {
+        let arg_allowed = |arg: &String| match &self.args {
+            Some(patterns) => patterns.iter().any(|p| match p.strip_suffix('*') {
+                Some(prefix) => arg.starts_with(prefix),
+                None => p == arg,
+            }),
+            None => true,
+        };
+        let env_allowed = |(name, _): &(String, String)| match &self.envs {
+            Some(names) => names.contains(name),
+            None => true,
+        };
+        args.iter().all(arg_allowed) && envs.iter().all(env_allowed)
+    }
+}
+
+/// split the address `HOST[:PORT]`, the ipv6 host is in the brackets like `[::1]:80`.
+fn split_host_port(addr: &str) -> (&str, Option<u16>) {
+    let addr = addr.trim();
+    let (host, port) = match addr.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => match port.parse() {
+            Ok(port) => (host, Some(port)),
+            Err(_) => (addr, None),
+        },
+        _ => (addr, None),
+    };
+    (host.trim_start_matches('[').trim_end_matches(']'), port)
+}
+
+/// the address `HOST:PORT` is granted, a granted address without the port grants
+/// all the ports of the host.
+fn addr_granted(grant: &Option<PermissionGrant>, addr: &str) -> bool {
+    let (host, port) = split_host_port(addr);
+    match grant {
+        Some(PermissionGrant::All) => true,
+        Some(PermissionGrant::List(grants)) => grants.iter().any(|grant| {
+            let (granted_host, granted_port) = split_host_port(grant);
+            granted_host.eq_ignore_ascii_case(host)
+                && (granted_port.is_none() || granted_port == port)
+        }),
+        None => false,
+    }
+}
+
+/// The runtime configure, (de)serialized with the schema of [`BlocklessConfigFile`].
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "BlocklessConfigFile", into = "BlocklessConfigFile")]
 pub struct BlocklessConfig {
     pub entry: String,
     pub nn: bool,
@@ -563,8 +1070,12 @@ pub struct BlocklessConfig {
     pub unknown_imports_trap: bool,
     pub store_limited: StoreLimited,
     pub envs: Vec<(String, String)>,
+    // the names of the host environment variables exposed to the guest by `env_var_read`.
+    pub host_envs: Vec<String>,
     pub permisions: Vec<Permission>,
     pub dirs: Vec<(String, String)>,
+    // the CAR files mounted read-only, the CAR file and the guest directory.
+    pub car_mounts: Vec<(String, String)>,
     pub fs_root_path: Option<String>,
     pub modules: Vec<BlocklessModule>,
     pub runtime_logger: Option<String>,
@@ -578,6 +1089,20 @@ pub struct BlocklessConfig {
     pub tcp_listens: Vec<(SocketAddr, Option<u32>)>,
     pub group_permisions: HashMap<String, Vec<Permission>>,
     pub permissions_config: PermissionsConfig,
+    // the hex ed25519 public keys of the trusted publishers.
+    pub trusted_keys: Vec<String>,
+    // refuse the unsigned packages and modules.
+    pub require_signature: bool,
+    // the package served from the memory instead of the host file system.
+    pub package: Option<PackageFs>,
+    // the guest directory packed into the CAR file after the run.
+    pub output_dir: Option<String>,
+    // the host CAR file of the output directory.
+    pub output_car: Option<String>,
+    pub quotas: ResourceQuotas,
+    pub http_client: HttpClientConfig,
+    // the usage of the host resources counted against the quotas, shared by the clones.
+    pub resource_usage: QuotaTracker,
 }
 
 impl BlocklessConfig {
@@ -587,8 +1112,10 @@ impl BlocklessConfig {
             run_time: None,
             coredump: None,
             envs: Vec::new(),
+            host_envs: Vec::new(),
             debug_info: false,
             dirs: Vec::new(),
+            car_mounts: Vec::new(),
             is_carfile: false,
             fs_root_path: None,
             drivers: Vec::new(),
@@ -617,9 +1144,32 @@ impl BlocklessConfig {
             runtime_logger_level: LoggerLevel::WARN,
             version: BlocklessConfigVersion::Version0,
             permissions_config: Default::default(),
+            trusted_keys: Vec::new(),
+            require_signature: false,
+            output_dir: None,
+            output_car: None,
+            package: None,
+            quotas: Default::default(),
+            http_client: Default::default(),
+            resource_usage: Default::default(),
         }
     }
 
+    /// start a fluent builder of the configure.
+    pub fn builder(entry: &str) -> BlocklessConfigBuilder {
+        BlocklessConfigBuilder::new(entry)
+    }
+
+    /// parse the configure from the json config file content.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// dump the effective configure as json.
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
     #[inline(always)]
     pub fn version(&self) -> BlocklessConfigVersion {
         self.version
@@ -650,6 +1200,59 @@ impl BlocklessConfig {
         self.envs = envs;
     }
 
+    /// the guest can read the host environment variable if it's not denied by `deny_env`
+    /// and it's granted by `allow_env` or `allow_all`, nothing is granted by default.
+    pub fn env_permission(&self, name: &str) -> bool {
+        self.env_granted(name, false)
+    }
+
+    /// `default` is the grant without `allow_env` and `allow_all`.
+    fn env_granted(&self, name: &str, default: bool) -> bool {
+        let perms = &self.permissions_config;
+        let listed = |grant: &Option<PermissionGrant>| match grant {
+            Some(PermissionGrant::All) => Some(true),
+            Some(PermissionGrant::List(names)) => Some(names.iter().any(|n| n == name)),
+            None => None,
+        };
+        if listed(&perms.deny_env) == Some(true) {
+            return false;
+        }
+        perms.allow_all || listed(&perms.allow_env).unwrap_or(default)
+    }
+
+    /// the environment variables configured by the operator, all of them are passed to
+    /// the guest unless they are denied by `deny_env` or not listed in `allow_env`.
+    pub fn guest_envs(&self) -> Vec<(String, String)> {
+        self.envs
+            .iter()
+            .filter(|(name, _)| self.env_granted(name, true))
+            .cloned()
+            .collect()
+    }
+
+    /// the host environment variables exposed to the guest allowed by the env permissions,
+    /// the names are configured by `host_envs` or the legacy `BLS_LIST_VARS` like `A;B`.
+    pub fn exposed_host_envs(&self) -> Vec<(String, String)> {
+        let names = if self.host_envs.is_empty() {
+            std::env::var("BLS_LIST_VARS")
+                .unwrap_or_default()
+                .split(';')
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect()
+        } else {
+            self.host_envs.clone()
+        };
+        names
+            .into_iter()
+            .filter(|name| self.env_permission(name))
+            .map(|name| {
+                let val = std::env::var(&name).unwrap_or_default();
+                (name, val)
+            })
+            .collect()
+    }
+
     #[inline(always)]
     pub fn set_stdin_args(&mut self, args: Vec<String>) {
         self.stdin_args = args;
@@ -660,6 +1263,11 @@ impl BlocklessConfig {
         self.dirs = dirs;
     }
 
+    #[inline(always)]
+    pub fn set_car_mounts(&mut self, mounts: Vec<(String, String)>) {
+        self.car_mounts = mounts;
+    }
+
     #[inline(always)]
     pub fn set_entry(&mut self, entry: String) {
         self.entry = entry;
@@ -743,7 +1351,7 @@ impl BlocklessConfig {
         let mut g_perms: HashMap<String, Vec<_>> = HashMap::new();
         perms.iter().for_each(|p| {
             g_perms
-                .entry(p.schema.clone())
+                .entry(p.schema.to_ascii_lowercase())
                 .or_insert_with(Vec::new)
                 .push(p.clone());
         });
@@ -776,6 +1384,16 @@ impl BlocklessConfig {
         self.is_carfile
     }
 
+    #[inline(always)]
+    pub fn set_package(&mut self, package: Option<PackageFs>) {
+        self.package = package;
+    }
+
+    #[inline(always)]
+    pub fn package_ref(&self) -> Option<&PackageFs> {
+        self.package.as_ref()
+    }
+
     #[inline(always)]
     pub fn add_driver(&mut self, d_conf: DriverConfig) {
         self.drivers.push(d_conf)
@@ -825,6 +1443,35 @@ impl BlocklessConfig {
         self.modules = modules;
     }
 
+    /// the configure of the library module with its own permissions, `None` if the module
+    /// shares the permissions of the app. The module with its own permissions is isolated,
+    /// the root file system, the host directories, the CAR mounts, the envs, the stdio and
+    /// the listeners belong to the entry module.
+    pub fn module_config(&self, module: &BlocklessModule) -> Option<BlocklessConfig> {
+        let perms = module.permissions.as_ref()?;
+        let mut config = self.clone();
+        config.set_permisions(perms.permissions.clone());
+        config.permissions_config = PermissionsConfig {
+            prompter: None,
+            audit_log: None,
+            ..perms.permissions_config.clone()
+        };
+        config.fs_root_path = None;
+        config.package = None;
+        config.dirs.clear();
+        config.car_mounts.clear();
+        config.envs.clear();
+        config.host_envs.clear();
+        config.stdin_args.clear();
+        config.stdio = Stdio {
+            stdin: Stdin::Fixed(String::new()),
+            stdout: Stdout::Null,
+            stderr: Stderr::Null,
+        };
+        config.tcp_listens.clear();
+        Some(config)
+    }
+
     #[inline(always)]
     pub fn fixed_stdin(&mut self, stdin: String) {
         self.stdio.stdin = Stdin::Fixed(stdin);
@@ -896,8 +1543,48 @@ impl BlocklessConfig {
         self.store_limited.max_memories.map(|m| m as u64)
     }
 
+    /// the url is permitted by the permissions of its schema.
     pub fn resource_permission(&self, url: &str) -> bool {
-        self.permisions.iter().any(|p| p.is_permision(url))
+        let schema = url.split_once("://").map_or("", |(s, _)| s);
+        self.group_permisions
+            .get(&schema.to_ascii_lowercase())
+            .map_or(false, |perms| perms.iter().any(|p| p.is_permision(url)))
+    }
+
+    /// the guest can run the cgi extension of the alias if it's granted by `allow_run`
+    /// and the arguments and the environment variables match the run rule of the alias.
+    pub fn run_permission(&self, alias: &str, args: &[String], envs: &[(String, String)]) -> bool {
+        let perms = &self.permissions_config;
+        let granted = match &perms.allow_run {
+            _ if perms.allow_all => true,
+            Some(PermissionGrant::All) => true,
+            Some(PermissionGrant::List(aliases)) => aliases.iter().any(|a| a == alias),
+            None => false,
+        };
+        granted
+            && perms
+                .run_rules
+                .get(alias)
+                .map_or(true, |rule| rule.allows(args, envs))
+    }
+
+    /// the guest can listen on the address `HOST:PORT` if it's granted by `allow_listen`,
+    /// a granted address without the port grants all the ports of the host.
+    pub fn listen_permission(&self, addr: &str) -> bool {
+        let perms = &self.permissions_config;
+        perms.allow_all || addr_granted(&perms.allow_listen, addr)
+    }
+
+    /// the guest can connect the address `HOST:PORT` by the sockets if it's granted by
+    /// `allow_connect` or `allow_net` and not denied by `deny_net`.
+    pub fn connect_permission(&self, addr: &str) -> bool {
+        let perms = &self.permissions_config;
+        if addr_granted(&perms.deny_net, addr) {
+            return false;
+        }
+        perms.allow_all
+            || addr_granted(&perms.allow_connect, addr)
+            || addr_granted(&perms.allow_net, addr)
     }
 
     #[inline(always)]
@@ -906,6 +1593,230 @@ impl BlocklessConfig {
     }
 }
 
+/// The fluent builder of [`BlocklessConfig`].
+///
+/// ```ignore
+/// let config = BlocklessConfig::builder("main.wasm")
+///     .fs_root_path("/tmp/app")
+///     .limited_fuel(200_000_000)
+///     .permission(Permission::parse("http://httpbin.org/anything").unwrap())
+///     .build();
+/// ```
+pub struct BlocklessConfigBuilder {
+    config: BlocklessConfig,
+    permisions: Vec<Permission>,
+}
+
+impl BlocklessConfigBuilder {
+    pub fn new(entry: &str) -> Self {
+        Self {
+            config: BlocklessConfig::new(entry),
+            permisions: Vec::new(),
+        }
+    }
+
+    pub fn version(mut self, version: BlocklessConfigVersion) -> Self {
+        self.config.set_version(version);
+        self
+    }
+
+    pub fn fs_root_path(mut self, path: impl Into<String>) -> Self {
+        self.config.set_fs_root_path(Some(path.into()));
+        self
+    }
+
+    pub fn drivers_root_path(mut self, path: impl Into<String>) -> Self {
+        self.config.set_drivers_root_path(Some(path.into()));
+        self
+    }
+
+    pub fn extensions_path(mut self, path: impl Into<String>) -> Self {
+        self.config.extensions_path(Some(path.into()));
+        self
+    }
+
+    pub fn runtime_logger(mut self, file: impl Into<String>) -> Self {
+        self.config.set_runtime_logger(Some(file.into()));
+        self
+    }
+
+    pub fn runtime_logger_level(mut self, level: LoggerLevel) -> Self {
+        self.config.set_runtime_logger_level(level);
+        self
+    }
+
+    pub fn debug_info(mut self, debug_info: bool) -> Self {
+        self.config.set_debug_info(debug_info);
+        self
+    }
+
+    pub fn limited_fuel(mut self, fuel: u64) -> Self {
+        self.config.limited_fuel(Some(fuel));
+        self
+    }
+
+    pub fn limited_time(mut self, time: u64) -> Self {
+        self.config.limited_time(Some(time));
+        self
+    }
+
+    pub fn limited_memory(mut self, pages: u64) -> Self {
+        self.config.limited_memory(Some(pages));
+        self
+    }
+
+    pub fn run_time(mut self, run_time: u64) -> Self {
+        self.config.set_run_time(Some(run_time));
+        self
+    }
+
+    pub fn store_limited(mut self, store_limited: StoreLimited) -> Self {
+        self.config.store_limited = store_limited;
+        self
+    }
+
+    pub fn stdin(mut self, stdin: Stdin) -> Self {
+        self.config.stdio.stdin(stdin);
+        self
+    }
+
+    pub fn stdout(mut self, stdout: Stdout) -> Self {
+        self.config.stdio.stdout(stdout);
+        self
+    }
+
+    pub fn stderr(mut self, stderr: Stderr) -> Self {
+        self.config.stdio.stderr(stderr);
+        self
+    }
+
+    pub fn driver(mut self, driver: DriverConfig) -> Self {
+        self.config.add_driver(driver);
+        self
+    }
+
+    pub fn module(mut self, module: BlocklessModule) -> Self {
+        self.config.add_module(module);
+        self
+    }
+
+    pub fn permission(mut self, permission: Permission) -> Self {
+        self.permisions.push(permission);
+        self
+    }
+
+    pub fn permissions_config(mut self, permissions_config: PermissionsConfig) -> Self {
+        self.config.permissions_config = permissions_config;
+        self
+    }
+
+    pub fn map_dir(mut self, host: impl Into<String>, guest: impl Into<String>) -> Self {
+        self.config.dirs.push((host.into(), guest.into()));
+        self
+    }
+
+    /// mount the UnixFS tree in the CAR file as the read-only guest directory.
+    pub fn mount_car(mut self, car: impl Into<String>, guest: impl Into<String>) -> Self {
+        self.config.car_mounts.push((car.into(), guest.into()));
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.envs.push((key.into(), value.into()));
+        self
+    }
+
+    /// expose the host environment variable to the guest by `env_var_read`.
+    pub fn host_env(mut self, name: impl Into<String>) -> Self {
+        self.config.host_envs.push(name.into());
+        self
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.config.stdin_args.push(arg.into());
+        self
+    }
+
+    pub fn optimize(mut self, opts: OptimizeOpts) -> Self {
+        self.config.opts = opts;
+        self
+    }
+
+    pub fn quotas(mut self, quotas: ResourceQuotas) -> Self {
+        self.config.quotas = quotas;
+        self
+    }
+
+    pub fn http_client(mut self, http_client: HttpClientConfig) -> Self {
+        self.config.http_client = http_client;
+        self
+    }
+
+    pub fn feature_thread(mut self, feature_thread: bool) -> Self {
+        self.config.set_feature_thread(feature_thread);
+        self
+    }
+
+    pub fn nn(mut self, nn: bool) -> Self {
+        self.config.nn = nn;
+        self
+    }
+
+    pub fn nn_graph(mut self, graph: BlsNnGraph) -> Self {
+        self.config.nn_graph.push(graph);
+        self
+    }
+
+    pub fn tcp_listen(mut self, addr: SocketAddr, fd: Option<u32>) -> Self {
+        self.config.tcp_listens.push((addr, fd));
+        self
+    }
+
+    pub fn coredump(mut self, path: impl Into<String>) -> Self {
+        self.config.coredump = Some(path.into());
+        self
+    }
+
+    pub fn unknown_imports_trap(mut self, trap: bool) -> Self {
+        self.config.unknown_imports_trap = trap;
+        self
+    }
+
+    pub fn cli_exit_with_code(mut self, exit_with_code: bool) -> Self {
+        self.config.cli_exit_with_code = exit_with_code;
+        self
+    }
+
+    pub fn network_error_code(mut self, network_error_code: bool) -> Self {
+        self.config.network_error_code = network_error_code;
+        self
+    }
+
+    /// trust the hex ed25519 public key of the publisher.
+    pub fn trusted_key(mut self, key: impl Into<String>) -> Self {
+        self.config.trusted_keys.push(key.into());
+        self
+    }
+
+    pub fn require_signature(mut self, require: bool) -> Self {
+        self.config.require_signature = require;
+        self
+    }
+
+    /// pack the guest directory into the CAR file after the run.
+    pub fn output(mut self, guest_dir: impl Into<String>, car: impl Into<String>) -> Self {
+        self.config.output_dir = Some(guest_dir.into());
+        self.config.output_car = Some(car.into());
+        self
+    }
+
+    pub fn build(self) -> BlocklessConfig {
+        let mut config = self.config;
+        config.set_permisions(self.permisions);
+        config
+    }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -947,13 +1858,13 @@ mod test {
 
     #[test]
     fn test_version_convert() {
-        let _version0: BlocklessConfigVersion = 0.into();
-        let matched = matches!(BlocklessConfigVersion::Version0, _version0);
-        assert!(matched);
+        let version0 = BlocklessConfigVersion::try_from(0).unwrap();
+        assert!(matches!(version0, BlocklessConfigVersion::Version0));
 
-        let _version1: BlocklessConfigVersion = 1.into();
-        let matched = matches!(BlocklessConfigVersion::Version1, _version1);
-        assert!(matched);
+        let version1 = BlocklessConfigVersion::try_from(1).unwrap();
+        assert!(matches!(version1, BlocklessConfigVersion::Version1));
+
+        assert!(BlocklessConfigVersion::try_from(2).is_err());
     }
 
     #[test]
@@ -973,4 +1884,231 @@ mod test {
         let ty = "trace".into();
         assert!(matches!(ty, LoggerLevel::TRACE));
     }
+
+    #[test]
+    fn test_builder() {
+        let config = BlocklessConfig::builder("release")
+            .version(BlocklessConfigVersion::Version1)
+            .fs_root_path("/tmp")
+            .limited_fuel(200000000)
+            .limited_memory(30)
+            .map_dir("/tmp/data", "/data")
+            .permission(Permission::parse("http://httpbin.org/anything").unwrap())
+            .permission(Permission::parse("http://httpbin.org/get").unwrap())
+            .build();
+        assert!(matches!(config.version(), BlocklessConfigVersion::Version1));
+        assert_eq!(config.fs_root_path_ref(), Some("/tmp"));
+        assert_eq!(config.get_limited_fuel(), Some(200000000));
+        assert_eq!(config.get_limited_memory(), Some(30));
+        assert_eq!(config.dirs, vec![("/tmp/data".into(), "/data".into())]);
+        assert_eq!(config.group_permisions.get("http").map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut opts = OptimizeOpts::default();
+        opts.opt_level = Some(OptLevel::SpeedAndSize);
+        opts.memory_may_move = Some(false);
+        opts.memory_reservation = Some(0x1000);
+        let config = BlocklessConfig::builder("release")
+            .version(BlocklessConfigVersion::Version1)
+            .fs_root_path("/tmp")
+            .runtime_logger("runtime.log")
+            .runtime_logger_level(LoggerLevel::DEBUG)
+            .debug_info(true)
+            .run_time(1000)
+            .stdin(Stdin::Fixed("hello".into()))
+            .stdout(Stdout::Null)
+            .stderr(Stderr::FileName("err.log".into()))
+            .map_dir("/tmp/data", "/data")
+            .mount_car("/tmp/set.car", "/set")
+            .output("/data/out", "/tmp/result.car")
+            .driver(DriverConfig::new("tcp".into(), "libtcp.so".into()))
+            .module(BlocklessModule {
+                module_type: ModuleType::Entry,
+                name: "release".into(),
+                file: "release.wasm".into(),
+                md5: String::new(),
+                digest: "sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+                    .into(),
+                permissions: None,
+            })
+            .permission(Permission::parse("http://httpbin.org/anything").unwrap())
+            .optimize(opts.clone())
+            .build();
+        let json = config.to_json().unwrap();
+        let parsed = BlocklessConfig::from_json(&json).unwrap();
+        assert_eq!(parsed.to_json().unwrap(), json);
+        assert_eq!(parsed.opts, opts);
+        assert_eq!(parsed.fix_stdin_ref(), Some("hello"));
+        assert!(matches!(parsed.stdout_ref(), Stdout::Null));
+        assert!(matches!(parsed.stderr_ref(), Stderr::FileName(f) if f == "err.log"));
+        assert_eq!(parsed.modules[0].module_type, ModuleType::Entry);
+        assert_eq!(parsed.drivers_ref()[0].schema(), "tcp");
+        assert_eq!(parsed.permisions_ref(), config.permisions_ref());
+        assert_eq!(parsed.dirs, config.dirs);
+        assert_eq!(parsed.car_mounts, config.car_mounts);
+        assert_eq!(parsed.output_dir.as_deref(), Some("/data/out"));
+        assert_eq!(ModuleType::default(), ModuleType::Module);
+    }
+
+    #[test]
+    fn test_stdin_serde() {
+        let fixed = Stdin::Fixed("inherit".into());
+        let json = serde_json::to_string(&fixed).unwrap();
+        assert_eq!(json, r#"{"fixed":"inherit"}"#);
+        let parsed: Stdin = serde_json::from_str(&json).unwrap();
+        assert!(matches!(parsed, Stdin::Fixed(s) if s == "inherit"));
+        let parsed: Stdin = serde_json::from_str(r#""inherit""#).unwrap();
+        assert!(matches!(parsed, Stdin::Inherit));
+        let parsed: Stdin = serde_json::from_str(r#""hello""#).unwrap();
+        assert!(matches!(parsed, Stdin::Fixed(s) if s == "hello"));
+        assert!(serde_json::from_str::<Stdin>(r#"{"fixed": "a", "b": 1}"#).is_err());
+
+        let config = BlocklessConfig::builder("app").stdin(fixed).build();
+        let parsed = BlocklessConfig::from_json(&config.to_json().unwrap()).unwrap();
+        assert_eq!(parsed.fix_stdin_ref(), Some("inherit"));
+    }
+
+    #[test]
+    fn test_json_errors() {
+        assert!(BlocklessConfig::from_json(r#"{"fs_root_path": "/"}"#).is_err());
+        assert!(BlocklessConfig::from_json(r#"{"entry": "a.wasm", "version": 2}"#).is_err());
+        assert!(BlocklessConfig::from_json(r#"{"entry": "a.wasm", "permissions": [""]}"#).is_err());
+        assert!(BlocklessConfig::from_json(
+            r#"{"entry": "a.wasm", "optimize": {"opt_level": "x"}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_permission_grant_serde() {
+        let grant: PermissionGrant = serde_json::from_str("true").unwrap();
+        assert!(matches!(grant, PermissionGrant::All));
+        let grant: PermissionGrant = serde_json::from_str(r#""/a,/b""#).unwrap();
+        assert!(matches!(grant, PermissionGrant::List(l) if l == vec!["/a", "/b"]));
+        let grant: PermissionGrant = serde_json::from_str(r#"["/a"]"#).unwrap();
+        assert!(matches!(grant, PermissionGrant::List(l) if l == vec!["/a"]));
+        assert!(serde_json::from_str::<PermissionGrant>("false").is_err());
+    }
+
+    #[test]
+    fn test_module_config() {
+        let mut config = BlocklessConfig::new("test");
+        config.set_fs_root_path(Some("/app".into()));
+        config.set_envs(vec![("TOKEN".into(), "secret".into())]);
+        config.dirs.push(("/data".into(), "/data".into()));
+        config
+            .car_mounts
+            .push(("assets.car".into(), "/assets".into()));
+        config.set_permisions(vec![Permission::parse("http://httpbin.org").unwrap()]);
+        config.permissions_config.allow_all = true;
+        let mut module = BlocklessModule {
+            module_type: ModuleType::Module,
+            name: "lib".into(),
+            file: "lib.wasm".into(),
+            md5: String::new(),
+            digest: String::new(),
+            permissions: None,
+        };
+        // the module without permissions shares the permissions of the app.
+        assert!(config.module_config(&module).is_none());
+        module.permissions = Some(ModulePermissions::default());
+        let m_cfg = config.module_config(&module).unwrap();
+        assert_eq!(m_cfg.fs_root_path_ref(), None);
+        assert!(m_cfg.dirs.is_empty());
+        assert!(m_cfg.car_mounts.is_empty());
+        assert!(m_cfg.envs_ref().is_empty());
+        assert!(matches!(m_cfg.stdio.stdout, Stdout::Null));
+        assert!(!m_cfg.permissions_config.allow_all);
+        assert!(!m_cfg.resource_permission("http://httpbin.org/get"));
+        module.permissions = Some(ModulePermissions {
+            permissions: vec![Permission::parse("http://api.example.com").unwrap()],
+            ..Default::default()
+        });
+        let m_cfg = config.module_config(&module).unwrap();
+        assert!(m_cfg.resource_permission("http://api.example.com/v1"));
+        assert!(!m_cfg.resource_permission("http://httpbin.org/get"));
+    }
+
+    #[test]
+    fn test_listen_permission() {
+        let mut config = BlocklessConfig::new("test");
+        assert!(!config.listen_permission("127.0.0.1:8080"));
+        config.permissions_config.allow_listen = Some(PermissionGrant::List(vec![
+            "127.0.0.1:8080".into(),
+            "[::1]".into(),
+        ]));
+        assert!(config.listen_permission("127.0.0.1:8080"));
+        assert!(!config.listen_permission("127.0.0.1:8081"));
+        assert!(!config.listen_permission("0.0.0.0:8080"));
+        assert!(config.listen_permission("[::1]:9000"));
+        config.permissions_config.allow_listen = Some(PermissionGrant::All);
+        assert!(config.listen_permission("0.0.0.0:22"));
+    }
+
+    #[test]
+    fn test_connect_permission() {
+        let mut config = BlocklessConfig::new("test");
+        assert!(!config.connect_permission("127.0.0.1:5432"));
+        // the hosts of `allow_net` are granted to the sockets too.
+        config.permissions_config.allow_net = Some(PermissionGrant::List(vec!["127.0.0.1".into()]));
+        assert!(config.connect_permission("127.0.0.1:5432"));
+        config.permissions_config.allow_net = None;
+        assert!(!config.connect_permission("127.0.0.1:5432"));
+        config.permissions_config.allow_connect = Some(PermissionGrant::List(vec![
+            "127.0.0.1:5432".into(),
+            "db.example.com".into(),
+        ]));
+        assert!(config.connect_permission("127.0.0.1:5432"));
+        assert!(!config.connect_permission("127.0.0.1:6379"));
+        assert!(config.connect_permission("db.example.com:3306"));
+        config.permissions_config.deny_net =
+            Some(PermissionGrant::List(vec!["db.example.com:3306".into()]));
+        assert!(!config.connect_permission("db.example.com:3306"));
+        assert!(config.connect_permission("db.example.com:5432"));
+    }
+
+    #[test]
+    fn test_run_permission() {
+        let mut config = BlocklessConfig::new("test");
+        let args = vec!["--format=json".to_string()];
+        let envs = vec![("LANG".to_string(), "C".to_string())];
+        assert!(!config.run_permission("convert", &args, &envs));
+        config.permissions_config.allow_run = Some(PermissionGrant::List(vec!["convert".into()]));
+        assert!(config.run_permission("convert", &args, &envs));
+        assert!(!config.run_permission("shell", &[], &[]));
+        config.permissions_config.run_rules.insert(
+            "convert".into(),
+            RunRule {
+                args: Some(vec!["--format=*".into()]),
+                envs: Some(vec![]),
+            },
+        );
+        assert!(config.run_permission("convert", &args, &[]));
+        assert!(!config.run_permission("convert", &args, &envs));
+        assert!(!config.run_permission("convert", &["--exec".into()], &[]));
+    }
+
+    #[test]
+    fn test_env_permission() {
+        let mut config = BlocklessConfig::new("test");
+        config.set_envs(vec![
+            ("LANG".into(), "C".into()),
+            ("TOKEN".into(), "secret".into()),
+        ]);
+        // the configured variables are passed, the host variables are denied by default.
+        assert_eq!(config.guest_envs().len(), 2);
+        assert!(!config.env_permission("LANG"));
+        config.permissions_config.allow_all = true;
+        assert!(config.env_permission("LANG"));
+        config.permissions_config.allow_all = false;
+        config.permissions_config.deny_env = Some(PermissionGrant::List(vec!["TOKEN".into()]));
+        assert_eq!(config.guest_envs(), vec![("LANG".into(), "C".into())]);
+        config.permissions_config.allow_env = Some(PermissionGrant::List(vec!["HOME".into()]));
+        assert!(config.guest_envs().is_empty());
+        config.permissions_config.allow_env = Some(PermissionGrant::All);
+        assert!(!config.env_permission("TOKEN"));
+        assert!(config.env_permission("PATH"));
+    }
 }
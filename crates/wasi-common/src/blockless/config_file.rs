@@ -0,0 +1,459 @@
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    BlocklessConfig, BlocklessConfigVersion, BlocklessModule, BlsNnGraph, DriverConfig,
+    HttpClientConfig, LoggerLevel, OptimizeOpts, Permission, PermissionsConfig, ResourceQuotas,
+    Stderr, Stdin, Stdio, Stdout, StoreLimited,
+};
+
+/// The type of the directory mapped into the sandbox.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapDirType {
+    /// the host directory.
+    #[default]
+    Host,
+    /// the UnixFS tree in the CAR file, mounted read-only.
+    Car,
+}
+
+impl MapDirType {
+    fn is_host(&self) -> bool {
+        matches!(self, MapDirType::Host)
+    }
+}
+
+/// The host directory mapped into the sandbox.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapDir {
+    /// the directory in the host, or the CAR file with the type `car`.
+    pub host: String,
+    /// the directory seen by the wasm module.
+    pub guest: String,
+    #[serde(rename = "type", default, skip_serializing_if = "MapDirType::is_host")]
+    pub dir_type: MapDirType,
+}
+
+/// The schema of the json config file.
+///
+/// ```jsonp
+/// {
+///     "version": 1,
+///     "entry": "release",
+///     "fs_root_path": "/tmp/app",
+///     "drivers_root_path": "/tmp/app/drivers",
+///     "runtime_logger": "runtime.log",
+///     "runtime_logger_level": "info",
+///     "limited_fuel": 200000000,
+///     "limited_memory": 30,
+///     "run_time": 1000,
+///     "debug_info": false,
+///     "stdin": "inherit",
+///     "stdout": "inherit",
+///     "stderr": "null",
+///     "map_dirs": [{"host": "/tmp/data", "guest": "/data"}, {"type": "car", "host": "/tmp/set.car", "guest": "/set"}],
+///     "drivers": [{"schema": "tcp", "path": "/tmp/app/drivers/libtcp.so"}],
+///     "modules": [{"type": "entry", "name": "release", "file": "release.wasm", "digest": "sha256:9f86d081..."}],
+///     "permissions": ["http://httpbin.org/anything"],
+///     "optimize": {"opt_level": "s", "memory_may_move": false},
+///     "quotas": {"max_open_handles": 8, "max_requests": 100, "max_cgi_processes": 2},
+///     "http_client": {"proxy": "socks5://127.0.0.1:1080", "root_cas": ["/etc/bls/ca.pem"], "user_agent": "bls/1.0"},
+///     "envs": {"RUST_LOG": "info"},
+///     "host_envs": ["AWS_REGION"],
+///     "args": ["--port", "8080"],
+///     "tcp_listens": ["127.0.0.1:8080", "127.0.0.1:8081::4"],
+///     "allow_read": ["/data"],
+///     "deny_write": true,
+///     "allow_net": ["api.example.com:443"],
+///     "allow_listen": ["127.0.0.1:8080"],
+///     "allow_connect": ["127.0.0.1:5432"],
+///     "allow_run": ["convert"],
+///     "run_rules": {"convert": {"args": ["--format=*"], "envs": ["LANG"]}},
+///     "deny_env": ["AWS_SECRET_ACCESS_KEY"],
+///     "prompter": "deny",
+///     "audit_log": "audit.log",
+///     "max_memory_size": 67108864,
+///     "feature_thread": false,
+///     "nn": true,
+///     "nn_graph": [{"format": "openvino", "dir": "/models"}],
+///     "coredump": "app.coredump",
+///     "unknown_imports_trap": false,
+///     "cli_exit_with_code": false,
+///     "network_error_code": false,
+///     "require_signature": true,
+///     "output_dir": "/data/out",
+///     "output_car": "/tmp/app/result.car"
+/// }
+/// ```
+///
+/// Only `entry` is required, the missing keys keep the default of [`BlocklessConfig::new`],
+/// except `stdin` which is empty when not configured.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlocklessConfigFile {
+    /// the config version, `0` runs the `entry` wasm file,
+    /// `1` links the `modules` and calls the `entry` function.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<BlocklessConfigVersion>,
+
+    /// the entry wasm file in version 0, the entry function in version 1.
+    pub entry: String,
+
+    /// the sandbox root path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_root_path: Option<String>,
+
+    /// the root path of the drivers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drivers_root_path: Option<String>,
+
+    /// the root path of the extensions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions_path: Option<String>,
+
+    /// the runtime log file in the `fs_root_path`, log to stdout when not configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime_logger: Option<String>,
+
+    /// one of `trace`, `debug`, `info`, `warn` and `error`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime_logger_level: Option<LoggerLevel>,
+
+    /// the instructions limit of the module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limited_fuel: Option<u64>,
+
+    /// the memory limit, 1 page = 64k.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limited_memory: Option<u64>,
+
+    /// the run time limit in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_time: Option<u64>,
+
+    /// keep the debug info of the module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_info: Option<bool>,
+
+    /// `"inherit"` or the fixed content of stdin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<Stdin>,
+
+    /// `"inherit"`, `"null"` or the file name in the `fs_root_path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<Stdout>,
+
+    /// `"inherit"`, `"null"` or the file name in the `fs_root_path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<Stderr>,
+
+    /// the host directories mapped into the sandbox.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub map_dirs: Vec<MapDir>,
+
+    /// the drivers loaded by the runtime.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drivers: Vec<DriverConfig>,
+
+    /// the modules linked in version 1.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modules: Vec<BlocklessModule>,
+
+    /// the resources the module can access, e.g. `http://httpbin.org/anything`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<Permission>,
+
+    /// the wasmtime optimize options, same as `--opt` in command line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub optimize: Option<OptimizeOpts>,
+
+    /// the quotas of the host resources in the run, same as `--quota` in command line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quotas: Option<ResourceQuotas>,
+
+    /// the shared http client of the app.
+    #[serde(default, skip_serializing_if = "HttpClientConfig::is_empty")]
+    pub http_client: HttpClientConfig,
+
+    /// the environment variables of the app.
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "envs_map")]
+    pub envs: Vec<(String, String)>,
+
+    /// the names of the host environment variables exposed to the app.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub host_envs: Vec<String>,
+
+    /// the arguments of the app.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+
+    /// the preopened tcp listen sockets, `ADDR[::LISTENFD]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "tcp_listens")]
+    pub tcp_listens: Vec<(SocketAddr, Option<u32>)>,
+
+    /// the file system and network permissions: `allow_read`, `allow_write`, `deny_read`,
+    /// `deny_write`, `allow_net`, `deny_net`, `allow_listen`, `allow_connect`, `allow_run`,
+    /// `run_rules`, `allow_env`, `deny_env`, `prompter`, `audit_log` and `allow_all`.
+    #[serde(flatten)]
+    pub permissions_config: PermissionsConfig,
+
+    /// the max linear memory size in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_size: Option<usize>,
+
+    /// the max elements of a table.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_table_elements: Option<u32>,
+
+    /// the max instances in the store.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_instances: Option<usize>,
+
+    /// the max tables in the store.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tables: Option<u32>,
+
+    /// trap instead of returning -1 when the memory or table grow fails.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trap_on_grow_failure: Option<bool>,
+
+    /// enable the wasi threads.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub feature_thread: bool,
+
+    /// enable the wasi neural network imports.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub nn: bool,
+
+    /// the machine learning graphs preloaded for wasi-nn.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nn_graph: Vec<BlsNnGraph>,
+
+    /// write a coredump to the file when the app traps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coredump: Option<String>,
+
+    /// define the unknown imports as functions which trap.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unknown_imports_trap: bool,
+
+    /// enable the `cli-exit-with-code` unstable wasi apis.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cli_exit_with_code: bool,
+
+    /// enable the `network-error-code` unstable wasi apis.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub network_error_code: bool,
+
+    /// the hex ed25519 public keys of the trusted publishers, `bls-runtime` refuses them
+    /// in the configure file and only trusts the `--trusted-key`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_keys: Vec<String>,
+
+    /// refuse the unsigned packages and modules.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_signature: bool,
+
+    /// the guest directory packed into a CAR file after the run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<String>,
+
+    /// the CAR file of the `output_dir`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_car: Option<String>,
+}
+
+/// the envs is a json object in the config file.
+mod envs_map {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        envs: &Vec<(String, String)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(envs.iter().map(|(k, v)| (k, v)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(String, String)>, D::Error> {
+        let envs = BTreeMap::<String, String>::deserialize(deserializer)?;
+        Ok(envs.into_iter().collect())
+    }
+}
+
+/// the tcp listen is `ADDR[::LISTENFD]` in the config file, same as `--tcplisten`.
+mod tcp_listens {
+    use super::*;
+
+    pub fn parse_tcp_listen(s: &str) -> Option<(SocketAddr, Option<u32>)> {
+        if let Ok(addr) = s.parse() {
+            return Some((addr, None));
+        }
+        let (addr, fd) = s.rsplit_once("::")?;
+        Some((addr.parse().ok()?, Some(fd.parse().ok()?)))
+    }
+
+    pub fn serialize<S: Serializer>(
+        listens: &Vec<(SocketAddr, Option<u32>)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(listens.iter().map(|(addr, fd)| match fd {
+            Some(fd) => format!("{addr}::{fd}"),
+            None => addr.to_string(),
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(SocketAddr, Option<u32>)>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|s| {
+                parse_tcp_listen(s)
+                    .ok_or_else(|| de::Error::custom(format!("invalid tcp listen: {s}")))
+            })
+            .collect()
+    }
+}
+
+pub use tcp_listens::parse_tcp_listen;
+
+impl From<BlocklessConfigFile> for BlocklessConfig {
+    fn from(file: BlocklessConfigFile) -> Self {
+        let mut bc = BlocklessConfig::new(&file.entry);
+        file.version.map(|v| bc.set_version(v));
+        bc.set_fs_root_path(file.fs_root_path);
+        bc.set_drivers_root_path(file.drivers_root_path);
+        bc.extensions_path(file.extensions_path);
+        bc.set_runtime_logger(file.runtime_logger);
+        file.runtime_logger_level
+            .map(|l| bc.set_runtime_logger_level(l));
+        bc.limited_fuel(file.limited_fuel);
+        bc.set_run_time(file.run_time);
+        file.debug_info.map(|b| bc.set_debug_info(b));
+        bc.stdio = Stdio {
+            stdin: file.stdin.unwrap_or_else(|| Stdin::Fixed(String::new())),
+            stdout: file.stdout.unwrap_or(Stdout::Inherit),
+            stderr: file.stderr.unwrap_or(Stderr::Inherit),
+        };
+        let (cars, dirs): (Vec<_>, Vec<_>) = file
+            .map_dirs
+            .into_iter()
+            .partition(|d| d.dir_type == MapDirType::Car);
+        bc.set_map_dirs(dirs.into_iter().map(|d| (d.host, d.guest)).collect());
+        bc.set_car_mounts(cars.into_iter().map(|d| (d.host, d.guest)).collect());
+        bc.drivers(file.drivers);
+        bc.set_modules(file.modules);
+        bc.set_permisions(file.permissions);
+        if let Some(opts) = file.optimize {
+            bc.opts = opts;
+        }
+        if let Some(quotas) = file.quotas {
+            bc.quotas = quotas;
+        }
+        bc.http_client = file.http_client;
+        bc.set_envs(file.envs);
+        bc.host_envs = file.host_envs;
+        bc.set_stdin_args(file.args);
+        bc.tcp_listens = file.tcp_listens;
+        bc.permissions_config = file.permissions_config;
+        bc.store_limited = StoreLimited {
+            max_memory_size: file.max_memory_size,
+            max_table_elements: file.max_table_elements,
+            max_instances: file.max_instances,
+            max_tables: file.max_tables,
+            max_memories: file.limited_memory.map(|m| m as _),
+            trap_on_grow_failure: file.trap_on_grow_failure,
+        };
+        bc.set_feature_thread(file.feature_thread);
+        bc.nn = file.nn;
+        bc.nn_graph = file.nn_graph;
+        bc.coredump = file.coredump;
+        bc.unknown_imports_trap = file.unknown_imports_trap;
+        bc.cli_exit_with_code = file.cli_exit_with_code;
+        bc.network_error_code = file.network_error_code;
+        bc.trusted_keys = file.trusted_keys;
+        bc.require_signature = file.require_signature;
+        bc.output_dir = file.output_dir;
+        bc.output_car = file.output_car;
+        bc
+    }
+}
+
+impl From<BlocklessConfig> for BlocklessConfigFile {
+    fn from(bc: BlocklessConfig) -> Self {
+        let stdin = match bc.stdio.stdin {
+            Stdin::Fixed(s) if s.is_empty() => None,
+            stdin => Some(stdin),
+        };
+        let limited_memory = bc.get_limited_memory();
+        BlocklessConfigFile {
+            version: Some(bc.version),
+            entry: bc.entry,
+            fs_root_path: bc.fs_root_path,
+            drivers_root_path: bc.drivers_root_path,
+            extensions_path: bc.extensions_path,
+            runtime_logger: bc.runtime_logger,
+            runtime_logger_level: Some(bc.runtime_logger_level),
+            limited_fuel: bc.limited_fuel,
+            limited_memory,
+            run_time: bc.run_time,
+            debug_info: Some(bc.debug_info),
+            stdin,
+            stdout: Some(bc.stdio.stdout),
+            stderr: Some(bc.stdio.stderr),
+            map_dirs: bc
+                .dirs
+                .into_iter()
+                .map(|(host, guest)| (host, guest, MapDirType::Host))
+                .chain(
+                    bc.car_mounts
+                        .into_iter()
+                        .map(|(host, guest)| (host, guest, MapDirType::Car)),
+                )
+                .map(|(host, guest, dir_type)| MapDir {
+                    host,
+                    guest,
+                    dir_type,
+                })
+                .collect(),
+            drivers: bc.drivers,
+            modules: bc.modules,
+            permissions: bc.permisions,
+            optimize: if bc.opts.is_empty() {
+                None
+            } else {
+                Some(bc.opts)
+            },
+            quotas: if bc.quotas.is_empty() {
+                None
+            } else {
+                Some(bc.quotas)
+            },
+            http_client: bc.http_client,
+            envs: bc.envs,
+            host_envs: bc.host_envs,
+            args: bc.stdin_args,
+            tcp_listens: bc.tcp_listens,
+            permissions_config: bc.permissions_config,
+            max_memory_size: bc.store_limited.max_memory_size,
+            max_table_elements: bc.store_limited.max_table_elements,
+            max_instances: bc.store_limited.max_instances,
+            max_tables: bc.store_limited.max_tables,
+            trap_on_grow_failure: bc.store_limited.trap_on_grow_failure,
+            feature_thread: bc.feature_thread,
+            nn: bc.nn,
+            nn_graph: bc.nn_graph,
+            coredump: bc.coredump,
+            unknown_imports_trap: bc.unknown_imports_trap,
+            cli_exit_with_code: bc.cli_exit_with_code,
+            network_error_code: bc.network_error_code,
+            trusted_keys: bc.trusted_keys,
+            require_signature: bc.require_signature,
+            output_dir: bc.output_dir,
+            output_car: bc.output_car,
+        }
+    }
+}
@@ -0,0 +1,481 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{set_prompter, PermissionPrompter, PromptResponse, TtyPrompter};
+
+/// The prompter asked when a permission is not granted or denied by the flags.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PrompterKind {
+    /// ask the user in the terminal, deny if the runtime is not in a terminal.
+    Tty,
+    /// deny all the requests and log what would have been asked.
+    Deny,
+    /// the json lines over the fd inherited from the supervising agent, e.g. a socket,
+    /// the stdio of the app is never used.
+    Fd(i32),
+    /// the json lines over the unix socket of the supervising agent.
+    Unix(PathBuf),
+    /// the rules in the policy file, the first matched rule wins.
+    Policy(PathBuf),
+}
+
+impl std::str::FromStr for PrompterKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tty" => Ok(PrompterKind::Tty),
+            "deny" => Ok(PrompterKind::Deny),
+            "stdio" => bail!(
+                "the stdio prompter shares the stdin and the stderr with the app, use fd:<N> instead"
+            ),
+            _ => match s.split_once(':') {
+                Some(("fd", fd)) => match fd.parse::<i32>() {
+                    Ok(fd) if fd > 2 => Ok(PrompterKind::Fd(fd)),
+                    _ => bail!("invalid prompter fd `{fd}`, the fd should be greater than 2"),
+                },
+                Some(("unix", path)) if !path.is_empty() => Ok(PrompterKind::Unix(path.into())),
+                Some(("policy", path)) if !path.is_empty() => {
+                    Ok(PrompterKind::Policy(path.into()))
+                }
+                _ => bail!(
+                    "unknown prompter `{s}`, expected tty, deny, fd:<N>, unix:<socket> or policy:<file>"
+                ),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for PrompterKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrompterKind::Tty => write!(f, "tty"),
+            PrompterKind::Deny => write!(f, "deny"),
+            PrompterKind::Fd(fd) => write!(f, "fd:{fd}"),
+            PrompterKind::Unix(path) => write!(f, "unix:{}", path.display()),
+            PrompterKind::Policy(path) => write!(f, "policy:{}", path.display()),
+        }
+    }
+}
+
+impl Serialize for PrompterKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PrompterKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl PrompterKind {
+    /// install the prompter of the kind, the socket and the policy file are opened here
+    /// so the errors are reported before the app starts.
+    pub fn install(&self) -> anyhow::Result<()> {
+        let prompter: Box<dyn PermissionPrompter> = match self {
+            PrompterKind::Tty => Box::new(TtyPrompter),
+            PrompterKind::Deny => Box::new(DenyPrompter),
+            #[cfg(unix)]
+            PrompterKind::Fd(fd) => {
+                use std::os::fd::{BorrowedFd, FromRawFd, OwnedFd};
+                // SAFETY: the fd is only borrowed to check it's open.
+                let borrowed = unsafe { BorrowedFd::borrow_raw(*fd) };
+                rustix::io::fcntl_getfd(borrowed)
+                    .with_context(|| format!("the prompter fd {fd} is not open"))?;
+                // SAFETY: the open fd is handed to the runtime by the agent, nothing else owns it.
+                let file = std::fs::File::from(unsafe { OwnedFd::from_raw_fd(*fd) });
+                let reader = BufReader::new(file.try_clone()?);
+                Box::new(JsonLinesPrompter::new(reader, file))
+            }
+            #[cfg(not(unix))]
+            PrompterKind::Fd(_) => bail!("the fd prompter is only supported on unix"),
+            #[cfg(unix)]
+            PrompterKind::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)
+                    .with_context(|| format!("failed to connect {}", path.display()))?;
+                let reader = BufReader::new(stream.try_clone()?);
+                Box::new(JsonLinesPrompter::new(reader, stream))
+            }
+            #[cfg(not(unix))]
+            PrompterKind::Unix(_) => bail!("the unix socket prompter is only supported on unix"),
+            PrompterKind::Policy(path) => Box::new(PolicyPrompter::load(path)?),
+        };
+        set_prompter(prompter);
+        Ok(())
+    }
+}
+
+/// the target of the prompt message like `net access to "api.example.com:443"`.
+fn prompt_target(message: &str) -> &str {
+    match (message.find('"'), message.rfind('"')) {
+        (Some(start), Some(end)) if start < end => &message[start + 1..end],
+        _ => "",
+    }
+}
+
+/// Deny all the requests, the requests are logged for the operators to grant them by flags.
+pub struct DenyPrompter;
+
+impl PermissionPrompter for DenyPrompter {
+    fn prompt(
+        &mut self,
+        message: &str,
+        name: &str,
+        api_name: Option<&str>,
+        _is_unary: bool,
+    ) -> PromptResponse {
+        log::warn!(
+            "denied {message} requested by `{}`, run with --allow-{name} to grant it",
+            api_name.unwrap_or("unknown")
+        );
+        PromptResponse::Deny
+    }
+}
+
+#[derive(Serialize)]
+struct PromptRequest<'a> {
+    id: u64,
+    message: &'a str,
+    name: &'a str,
+    target: &'a str,
+    api_name: Option<&'a str>,
+    is_unary: bool,
+}
+
+#[derive(Deserialize)]
+struct PromptAnswer {
+    id: u64,
+    response: Response,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Allow,
+    Deny,
+    AllowAll,
+}
+
+/// the time the agent has to answer a request.
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Ask the supervising agent by the json lines, one request line is answered by one line
+/// like `{"id": 1, "response": "allow"}`, any broken or late answer is a deny.
+/// The answers are read by a thread, the late answers of the earlier requests are skipped.
+pub struct JsonLinesPrompter<W> {
+    // the receiver is only used by `ask`, the lock makes the prompter `Sync`.
+    answers: Mutex<Receiver<io::Result<String>>>,
+    writer: W,
+    next_id: u64,
+    timeout: Duration,
+}
+
+impl<W: Write> JsonLinesPrompter<W> {
+    pub fn new<R>(reader: R, writer: W) -> Self
+    where
+        R: BufRead + Send + 'static,
+    {
+        let (tx, answers) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in reader.lines() {
+                let broken = line.is_err();
+                if tx.send(line).is_err() || broken {
+                    break;
+                }
+            }
+        });
+        Self {
+            answers: Mutex::new(answers),
+            writer,
+            next_id: 1,
+            timeout: PROMPT_TIMEOUT,
+        }
+    }
+
+    /// the time the agent has to answer a request, 60 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn ask(&mut self, request: &PromptRequest) -> anyhow::Result<Response> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.flush()?;
+        let deadline = Instant::now() + self.timeout;
+        let answers = self.answers.get_mut().unwrap();
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let answer = match answers.recv_timeout(timeout) {
+                Ok(answer) => answer?,
+                Err(RecvTimeoutError::Timeout) => {
+                    bail!("no answer in {:?}", self.timeout)
+                }
+                Err(RecvTimeoutError::Disconnected) => bail!("the prompter is closed"),
+            };
+            let answer: PromptAnswer = serde_json::from_str(answer.trim())?;
+            // the late answer of the earlier request.
+            if answer.id < request.id {
+                continue;
+            }
+            if answer.id > request.id {
+                bail!(
+                    "the answer {} is not of the request {}",
+                    answer.id,
+                    request.id
+                );
+            }
+            return Ok(answer.response);
+        }
+    }
+}
+
+impl<W> PermissionPrompter for JsonLinesPrompter<W>
+where
+    W: Write + Send + Sync,
+{
+    fn prompt(
+        &mut self,
+        message: &str,
+        name: &str,
+        api_name: Option<&str>,
+        is_unary: bool,
+    ) -> PromptResponse {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = PromptRequest {
+            id,
+            message,
+            name,
+            target: prompt_target(message),
+            api_name,
+            is_unary,
+        };
+        match self.ask(&request) {
+            Ok(Response::Allow) => PromptResponse::Allow,
+            Ok(Response::AllowAll) if is_unary => PromptResponse::AllowAll,
+            Ok(_) => PromptResponse::Deny,
+            Err(e) => {
+                log::error!("failed to prompt {message}: {e}");
+                PromptResponse::Deny
+            }
+        }
+    }
+}
+
+fn any() -> String {
+    "*".into()
+}
+
+/// The rule of the policy file, the globs match the permission name like `net`, the target
+/// like `api.example.com:443` and the api name.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyRule {
+    #[serde(default = "any")]
+    name: String,
+    #[serde(default = "any")]
+    target: String,
+    #[serde(default)]
+    api: Option<String>,
+    response: Response,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    rules: Vec<PolicyRule>,
+}
+
+/// Answer the prompts by the rules of the policy file, the request matched by no rule is denied.
+pub struct PolicyPrompter {
+    rules: Vec<PolicyRule>,
+}
+
+impl PolicyPrompter {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read the policy {}", path.display()))?;
+        let policy: PolicyFile = serde_json::from_str(&data)
+            .with_context(|| format!("invalid policy {}", path.display()))?;
+        Ok(Self {
+            rules: policy.rules,
+        })
+    }
+}
+
+impl PermissionPrompter for PolicyPrompter {
+    fn prompt(
+        &mut self,
+        message: &str,
+        name: &str,
+        api_name: Option<&str>,
+        is_unary: bool,
+    ) -> PromptResponse {
+        let target = prompt_target(message);
+        let rule = self.rules.iter().find(|rule| {
+            glob_match(&rule.name, name)
+                && glob_match(&rule.target, target)
+                && rule
+                    .api
+                    .as_ref()
+                    .map_or(true, |api| glob_match(api, api_name.unwrap_or_default()))
+        });
+        match rule.map(|rule| rule.response) {
+            Some(Response::Allow) => PromptResponse::Allow,
+            Some(Response::AllowAll) if is_unary => PromptResponse::AllowAll,
+            _ => {
+                log::warn!("denied {message} by the policy");
+                PromptResponse::Deny
+            }
+        }
+    }
+}
+
+/// match the text with the glob, `*` matches any characters and `?` matches one character.
+fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut g, mut t) = (0, 0);
+    // the position of the last `*` and the text position it matched to.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, t));
+                g += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_g, star_t)) => {
+                    g = star_g + 1;
+                    t = star_t + 1;
+                    star = Some((star_g, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_prompter_kind() {
+        assert_eq!("deny".parse::<PrompterKind>().unwrap(), PrompterKind::Deny);
+        let kind: PrompterKind = "unix:/run/bls.sock".parse().unwrap();
+        assert_eq!(kind, PrompterKind::Unix("/run/bls.sock".into()));
+        assert_eq!(kind.to_string(), "unix:/run/bls.sock");
+        assert!("policy:".parse::<PrompterKind>().is_err());
+        assert!("ask".parse::<PrompterKind>().is_err());
+        assert_eq!("fd:3".parse::<PrompterKind>().unwrap(), PrompterKind::Fd(3));
+        // the stdio belongs to the app.
+        assert!("stdio".parse::<PrompterKind>().is_err());
+        assert!("fd:1".parse::<PrompterKind>().is_err());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*.example.com:443", "api.example.com:443"));
+        assert!(!glob_match(
+            "*.example.com:443",
+            "api.example.com.evil.net:443"
+        ));
+        assert!(glob_match("/data/*", "/data/a/b"));
+        assert!(glob_match("AWS_?EGION", "AWS_REGION"));
+        assert!(!glob_match("AWS_*", "HOME"));
+    }
+
+    #[test]
+    fn test_json_lines_prompter() {
+        let answers =
+            "{\"id\": 1, \"response\": \"allow\"}\n{\"id\": 5, \"response\": \"allow\"}\n";
+        let mut prompter = JsonLinesPrompter::new(Cursor::new(answers), Vec::new());
+        let message = "net access to \"api.example.com:443\"";
+        let rs = prompter.prompt(message, "net", Some("http_req"), true);
+        assert_eq!(rs, PromptResponse::Allow);
+        // the answer of the other request.
+        let rs = prompter.prompt(message, "net", Some("http_req"), true);
+        assert_eq!(rs, PromptResponse::Deny);
+        // the agent is gone.
+        let rs = prompter.prompt(message, "net", Some("http_req"), true);
+        assert_eq!(rs, PromptResponse::Deny);
+        let requests = String::from_utf8(prompter.writer).unwrap();
+        let first: serde_json::Value =
+            serde_json::from_str(requests.lines().next().unwrap()).unwrap();
+        assert_eq!(first["id"], 1);
+        assert_eq!(first["target"], "api.example.com:443");
+        assert_eq!(first["api_name"], "http_req");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_json_lines_prompter_timeout() {
+        use std::os::unix::net::UnixStream;
+        let (runtime, mut agent) = UnixStream::pair().unwrap();
+        let reader = BufReader::new(runtime.try_clone().unwrap());
+        let mut prompter =
+            JsonLinesPrompter::new(reader, runtime).with_timeout(Duration::from_millis(100));
+        let message = "net access to \"api.example.com:443\"";
+        // the agent doesn't answer in time.
+        let rs = prompter.prompt(message, "net", None, true);
+        assert_eq!(rs, PromptResponse::Deny);
+        // the late answer of the first request is skipped.
+        agent
+            .write_all(
+                b"{\"id\": 1, \"response\": \"allow\"}\n{\"id\": 2, \"response\": \"allow\"}\n",
+            )
+            .unwrap();
+        let rs = prompter.prompt(message, "net", None, true);
+        assert_eq!(rs, PromptResponse::Allow);
+    }
+
+    #[test]
+    fn test_policy_prompter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.json");
+        std::fs::write(
+            &path,
+            r#"{"rules": [
+                {"name": "net", "target": "*.example.com:443", "response": "allow"},
+                {"name": "env", "target": "AWS_*", "api": "env_var_read", "response": "allow"},
+                {"name": "net", "response": "deny"}
+            ]}"#,
+        )
+        .unwrap();
+        let mut prompter = PolicyPrompter::load(&path).unwrap();
+        let rs = prompter.prompt("net access to \"api.example.com:443\"", "net", None, true);
+        assert_eq!(rs, PromptResponse::Allow);
+        let rs = prompter.prompt("net access to \"evil.net:443\"", "net", None, true);
+        assert_eq!(rs, PromptResponse::Deny);
+        let rs = prompter.prompt("env access to \"AWS_REGION\"", "env", None, true);
+        assert_eq!(rs, PromptResponse::Deny);
+        let rs = prompter.prompt(
+            "env access to \"AWS_REGION\"",
+            "env",
+            Some("env_var_read"),
+            true,
+        );
+        assert_eq!(rs, PromptResponse::Allow);
+        let rs = prompter.prompt("read access to \"/etc\"", "read", None, true);
+        assert_eq!(rs, PromptResponse::Deny);
+    }
+}
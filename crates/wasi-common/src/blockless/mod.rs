@@ -1,11 +1,21 @@
+mod audit;
 mod colors;
 mod config;
+mod config_file;
 mod error;
+mod headless_prompter;
 mod permission_parser;
 mod permissions;
 mod prompter;
+mod quota;
+mod vfs;
+pub use audit::*;
 pub use config::*;
+pub use config_file::*;
 pub use error::*;
+pub use headless_prompter::*;
 pub use permission_parser::*;
 pub use permissions::*;
 pub use prompter::*;
+pub use quota::*;
+pub use vfs::*;
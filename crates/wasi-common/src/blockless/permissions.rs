@@ -3,6 +3,7 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use blockless_multiaddr::MultiAddr;
 use bls_permissions::AnyError;
 use bls_permissions::BlsPermissionsContainer;
 use bls_permissions::CheckSpecifierKind;
@@ -14,8 +15,10 @@ use bls_permissions::Permissions;
 use bls_permissions::Permissions as BlsPermissions;
 use bls_permissions::RunQueryDescriptor;
 use bls_permissions::Url;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use super::init_tty_prompter;
+use super::AuditLog;
 use super::EnvCurrentDir;
 use super::PermissionGrant;
 use super::PermissionsConfig;
@@ -28,14 +31,61 @@ pub struct Permission {
 }
 
 impl Permission {
+    /// parse the permission url, the schema is the first part of the url.
+    pub fn parse(url: &str) -> Option<Permission> {
+        let addr = MultiAddr::parse(url.as_bytes()).ok()?;
+        let schema = addr.schema().ok()?;
+        Some(Permission {
+            schema: schema.into(),
+            url: url.into(),
+        })
+    }
+
+    /// the url is permitted if it has the same scheme, host and port as the permission
+    /// and its path is under the path of the permission, e.g. `http://api.example.com`
+    /// permits `http://api.example.com/v1` but not `http://api.example.com.evil.net`.
     pub fn is_permision(&self, url: &str) -> bool {
-        url.to_ascii_lowercase().starts_with(&self.url)
+        let (Ok(allowed), Ok(url)) = (Url::parse(&self.url), Url::parse(url)) else {
+            return false;
+        };
+        let same_host = match (allowed.host_str(), url.host_str()) {
+            (Some(allowed), Some(host)) => allowed.eq_ignore_ascii_case(host),
+            (None, None) => true,
+            _ => false,
+        };
+        if allowed.scheme() != url.scheme()
+            || !same_host
+            || allowed.port_or_known_default() != url.port_or_known_default()
+        {
+            return false;
+        }
+        let prefix = allowed.path().trim_end_matches('/');
+        match url.path().strip_prefix(prefix) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+impl Serialize for Permission {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.url)
+    }
+}
+
+impl<'de> Deserialize<'de> for Permission {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let url = String::deserialize(deserializer)?;
+        Permission::parse(&url)
+            .ok_or_else(|| de::Error::custom(format!("invalid permission url: {url}")))
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct BlsRuntimePermissionsContainer {
     pub inner: bls_permissions::BlsPermissionsContainer,
+    /// the audit of the checks, shared with the child permissions.
+    pub audit: AuditLog,
 }
 
 impl BlsRuntimePermissionsContainer {
@@ -46,6 +96,7 @@ impl BlsRuntimePermissionsContainer {
         init_tty_prompter();
         Self {
             inner: BlsPermissionsContainer::new(descriptor_parser, perms),
+            audit: AuditLog::default(),
         }
     }
 
@@ -80,7 +131,19 @@ impl BlsRuntimePermissionsContainer {
         if let Some(PermissionGrant::All) = config.allow_write {
             permissions.write.granted_global = true;
         }
+        if let Some(PermissionGrant::All) = config.deny_net {
+            permissions.net.flag_denied_global = true;
+        }
+        if let Some(PermissionGrant::All) = config.allow_net {
+            permissions.net.granted_global = true;
+        }
         *self.inner.lock() = permissions;
+        if let Some(prompter) = &config.prompter {
+            prompter.install()?;
+        }
+        if let Some(path) = &config.audit_log {
+            self.audit.open(path)?;
+        }
         Ok(())
     }
 
@@ -94,6 +157,7 @@ impl BlsRuntimePermissionsContainer {
     ) -> Result<BlsRuntimePermissionsContainer, AnyError> {
         Ok(BlsRuntimePermissionsContainer {
             inner: self.inner.create_child_permissions(child_permissions_arg)?,
+            audit: self.audit.clone(),
         })
     }
 
@@ -107,12 +171,17 @@ impl BlsRuntimePermissionsContainer {
         specifier: &ModuleSpecifier,
         kind: CheckSpecifierKind,
     ) -> Result<(), AnyError> {
-        self.inner.check_specifier(specifier, kind)
+        self.audit.audited(
+            specifier.as_str(),
+            "import",
+            self.inner.check_specifier(specifier, kind),
+        )
     }
 
     #[inline(always)]
     pub fn check_read(&self, path: &str, api_name: &str) -> Result<PathBuf, AnyError> {
-        self.inner.check_read(path, api_name)
+        self.audit
+            .audited(path, api_name, self.inner.check_read(path, api_name))
     }
 
     #[inline(always)]
@@ -121,7 +190,8 @@ impl BlsRuntimePermissionsContainer {
         path: &str,
         api_name: Option<&str>,
     ) -> Result<PathBuf, AnyError> {
-        self.inner.check_read_with_api_name(path, api_name)
+        let rs = self.inner.check_read_with_api_name(path, api_name);
+        self.audit.audited(path, api_name.unwrap_or("read"), rs)
     }
 
     #[inline(always)]
@@ -130,7 +200,9 @@ impl BlsRuntimePermissionsContainer {
         path: &'a Path,
         api_name: Option<&str>,
     ) -> Result<Cow<'a, Path>, AnyError> {
-        self.inner.check_read_path(path, api_name)
+        let rs = self.inner.check_read_path(path, api_name);
+        self.audit
+            .audited(&path.to_string_lossy(), api_name.unwrap_or("read"), rs)
     }
 
     /// As `check_read()`, but permission error messages will anonymize the path
@@ -142,12 +214,14 @@ impl BlsRuntimePermissionsContainer {
         display: &str,
         api_name: &str,
     ) -> Result<(), AnyError> {
-        self.inner.check_read_blind(path, display, api_name)
+        let rs = self.inner.check_read_blind(path, display, api_name);
+        self.audit.audited(display, api_name, rs)
     }
 
     #[inline(always)]
     pub fn check_read_all(&self, api_name: &str) -> Result<(), AnyError> {
-        self.inner.check_read_all(api_name)
+        self.audit
+            .audited("*", api_name, self.inner.check_read_all(api_name))
     }
 
     #[inline(always)]
@@ -157,7 +231,8 @@ impl BlsRuntimePermissionsContainer {
 
     #[inline(always)]
     pub fn check_write(&self, path: &str, api_name: &str) -> Result<PathBuf, AnyError> {
-        self.inner.check_write(path, api_name)
+        self.audit
+            .audited(path, api_name, self.inner.check_write(path, api_name))
     }
 
     #[inline(always)]
@@ -166,7 +241,8 @@ impl BlsRuntimePermissionsContainer {
         path: &str,
         api_name: Option<&str>,
     ) -> Result<PathBuf, AnyError> {
-        self.inner.check_write_with_api_name(path, api_name)
+        let rs = self.inner.check_write_with_api_name(path, api_name);
+        self.audit.audited(path, api_name.unwrap_or("write"), rs)
     }
 
     #[inline(always)]
@@ -175,12 +251,14 @@ impl BlsRuntimePermissionsContainer {
         path: &'a Path,
         api_name: &str,
     ) -> Result<Cow<'a, Path>, AnyError> {
-        self.inner.check_write_path(path, api_name)
+        let rs = self.inner.check_write_path(path, api_name);
+        self.audit.audited(&path.to_string_lossy(), api_name, rs)
     }
 
     #[inline(always)]
     pub fn check_write_all(&self, api_name: &str) -> Result<(), AnyError> {
-        self.inner.check_write_all(api_name)
+        self.audit
+            .audited("*", api_name, self.inner.check_write_all(api_name))
     }
 
     /// As `check_write()`, but permission error messages will anonymize the path
@@ -192,22 +270,26 @@ impl BlsRuntimePermissionsContainer {
         display: &str,
         api_name: &str,
     ) -> Result<(), AnyError> {
-        self.inner.check_write_blind(path, display, api_name)
+        let rs = self.inner.check_write_blind(path, display, api_name);
+        self.audit.audited(display, api_name, rs)
     }
 
     #[inline(always)]
     pub fn check_write_partial(&mut self, path: &str, api_name: &str) -> Result<PathBuf, AnyError> {
-        self.inner.check_write_partial(path, api_name)
+        let rs = self.inner.check_write_partial(path, api_name);
+        self.audit.audited(path, api_name, rs)
     }
 
     #[inline(always)]
     pub fn check_run(&mut self, cmd: &RunQueryDescriptor, api_name: &str) -> Result<(), AnyError> {
-        self.inner.check_run(cmd, api_name)
+        let rs = self.inner.check_run(cmd, api_name);
+        self.audit.audited(&format!("{cmd:?}"), api_name, rs)
     }
 
     #[inline(always)]
     pub fn check_run_all(&mut self, api_name: &str) -> Result<(), AnyError> {
-        self.inner.check_run_all(api_name)
+        self.audit
+            .audited("*", api_name, self.inner.check_run_all(api_name))
     }
 
     #[inline(always)]
@@ -217,27 +299,28 @@ impl BlsRuntimePermissionsContainer {
 
     #[inline(always)]
     pub fn check_sys(&self, kind: &str, api_name: &str) -> Result<(), AnyError> {
-        self.inner.check_sys(kind, api_name)
+        self.audit
+            .audited(kind, api_name, self.inner.check_sys(kind, api_name))
     }
 
     #[inline(always)]
     pub fn check_env(&mut self, var: &str) -> Result<(), AnyError> {
-        self.inner.check_env(var)
+        self.audit.audited(var, "env", self.inner.check_env(var))
     }
 
     #[inline(always)]
     pub fn check_env_all(&mut self) -> Result<(), AnyError> {
-        self.inner.check_env_all()
+        self.audit.audited("*", "env", self.inner.check_env_all())
     }
 
     #[inline(always)]
     pub fn check_sys_all(&mut self) -> Result<(), AnyError> {
-        self.inner.check_sys_all()
+        self.audit.audited("*", "sys", self.inner.check_sys_all())
     }
 
     #[inline(always)]
     pub fn check_ffi_all(&mut self) -> Result<(), AnyError> {
-        self.inner.check_ffi_all()
+        self.audit.audited("*", "ffi", self.inner.check_ffi_all())
     }
 
     /// This checks to see if the allow-all flag was passed, not whether all
@@ -250,12 +333,17 @@ impl BlsRuntimePermissionsContainer {
     /// Checks special file access, returning the failed permission type if
     /// not successful.
     pub fn check_special_file(&mut self, path: &Path, api_name: &str) -> Result<(), &'static str> {
-        self.inner.check_special_file(path, api_name)
+        let rs = self.inner.check_special_file(path, api_name);
+        self.audit.audited(&path.to_string_lossy(), api_name, rs)
     }
 
     #[inline(always)]
     pub fn check_net_url(&mut self, url: &Url, api_name: &str) -> Result<(), AnyError> {
-        self.inner.check_net_url(url, api_name)
+        self.audit.audited(
+            url.as_str(),
+            api_name,
+            self.inner.check_net_url(url, api_name),
+        )
     }
 
     #[inline(always)]
@@ -264,12 +352,17 @@ impl BlsRuntimePermissionsContainer {
         host: &(T, Option<u16>),
         api_name: &str,
     ) -> Result<(), AnyError> {
-        self.inner.check_net(host, api_name)
+        let resource = match host.1 {
+            Some(port) => format!("{}:{port}", host.0.as_ref()),
+            None => host.0.as_ref().to_string(),
+        };
+        self.audit
+            .audited(&resource, api_name, self.inner.check_net(host, api_name))
     }
 
     #[inline(always)]
     pub fn check_ffi(&mut self, path: &str) -> Result<PathBuf, AnyError> {
-        self.inner.check_ffi(path)
+        self.audit.audited(path, "ffi", self.inner.check_ffi(path))
     }
 
     #[inline(always)]
@@ -279,7 +372,8 @@ impl BlsRuntimePermissionsContainer {
 
     #[inline(always)]
     pub fn check_ffi_partial_with_path(&mut self, path: &str) -> Result<PathBuf, AnyError> {
-        self.inner.check_ffi_partial_with_path(path)
+        let rs = self.inner.check_ffi_partial_with_path(path);
+        self.audit.audited(path, "ffi", rs)
     }
 
     // query
@@ -393,3 +487,23 @@ impl BlsRuntimePermissionsContainer {
         self.inner.request_ffi(path)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_permission_match() {
+        let perm = Permission::parse("http://api.example.com/v1").unwrap();
+        assert!(perm.is_permision("http://api.example.com/v1"));
+        assert!(perm.is_permision("HTTP://API.example.com:80/v1/users?id=1"));
+        assert!(!perm.is_permision("http://api.example.com/v10"));
+        assert!(!perm.is_permision("http://api.example.com.evil.net/v1"));
+        assert!(!perm.is_permision("http://api.example.com@evil.net/v1"));
+        assert!(!perm.is_permision("http://api.example.com:8080/v1"));
+        assert!(!perm.is_permision("https://api.example.com/v1"));
+        let perm = Permission::parse("http://api.example.com/").unwrap();
+        assert!(perm.is_permision("http://api.example.com"));
+        assert!(perm.is_permision("http://api.example.com/anything"));
+    }
+}
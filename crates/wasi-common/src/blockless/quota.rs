@@ -0,0 +1,455 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use super::ResourceQuotas;
+use crate::file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags};
+use crate::{Error, SystemTimeSpec, WasiFile};
+
+/// The host modules the open handles are counted of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HostModule {
+    Http,
+    Ipfs,
+    S3,
+    Cgi,
+    Socket,
+}
+
+impl HostModule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HostModule::Http => "http",
+            HostModule::Ipfs => "ipfs",
+            HostModule::S3 => "s3",
+            HostModule::Cgi => "cgi",
+            HostModule::Socket => "socket",
+        }
+    }
+}
+
+/// The quota refused the usage, with the name of the quota.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuotaExceeded(pub &'static str);
+
+impl std::fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the quota {} is exceeded", self.0)
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// The usage of the host resources in the run, reported after the app exits.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    pub requests: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub cgi_processes: u32,
+    /// the most concurrent open handles of each host module.
+    pub peak_handles: BTreeMap<&'static str, u32>,
+    /// the usages refused by the quotas.
+    pub refused: u64,
+}
+
+#[derive(Default)]
+struct QuotaTrackerInner {
+    usage: ResourceUsage,
+    open_handles: BTreeMap<HostModule, u32>,
+}
+
+/// The usage counted against the quotas, the clones share the same counts,
+/// so the app and the library modules are counted together.
+#[derive(Clone, Default)]
+pub struct QuotaTracker(Arc<Mutex<QuotaTrackerInner>>);
+
+impl std::fmt::Debug for QuotaTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("QuotaTracker")
+            .field(&self.0.lock().unwrap().usage)
+            .finish()
+    }
+}
+
+impl QuotaTracker {
+    pub fn usage(&self) -> ResourceUsage {
+        self.0.lock().unwrap().usage.clone()
+    }
+}
+
+/// The quotas of the run with the shared usage, checked by the host modules.
+#[derive(Clone, Debug, Default)]
+pub struct Quota {
+    quotas: ResourceQuotas,
+    tracker: QuotaTracker,
+}
+
+impl Quota {
+    pub fn new(quotas: ResourceQuotas, tracker: QuotaTracker) -> Self {
+        Self { quotas, tracker }
+    }
+
+    // apply the usage if it's under the quota, otherwise count it as refused.
+    fn apply<F>(&self, f: F) -> Result<(), QuotaExceeded>
+    where
+        F: FnOnce(&ResourceQuotas, &mut QuotaTrackerInner) -> Result<(), QuotaExceeded>,
+    {
+        let mut inner = self.tracker.0.lock().unwrap();
+        let rs = f(&self.quotas, &mut *inner);
+        if rs.is_err() {
+            inner.usage.refused += 1;
+        }
+        rs
+    }
+
+    // the handle can be opened if it's under the quota.
+    fn check_handle(
+        quotas: &ResourceQuotas,
+        inner: &QuotaTrackerInner,
+        module: HostModule,
+    ) -> Result<u32, QuotaExceeded> {
+        let open = inner.open_handles.get(&module).copied().unwrap_or(0);
+        if quotas.max_open_handles.map_or(false, |max| open >= max) {
+            return Err(QuotaExceeded("max_open_handles"));
+        }
+        Ok(open)
+    }
+
+    fn count_handle(inner: &mut QuotaTrackerInner, module: HostModule, open: u32) {
+        inner.open_handles.insert(module, open + 1);
+        let peak = inner.usage.peak_handles.entry(module.name()).or_default();
+        *peak = (*peak).max(open + 1);
+    }
+
+    /// count a new open handle of the host module.
+    pub fn open_handle(&self, module: HostModule) -> Result<(), QuotaExceeded> {
+        self.apply(|quotas, inner| {
+            let open = Self::check_handle(quotas, inner, module)?;
+            Self::count_handle(inner, module, open);
+            Ok(())
+        })
+    }
+
+    pub fn close_handle(&self, module: HostModule) {
+        let mut inner = self.tracker.0.lock().unwrap();
+        if let Some(open) = inner.open_handles.get_mut(&module) {
+            *open = open.saturating_sub(1);
+        }
+    }
+
+    /// count a new request with the bytes sent by it.
+    pub fn request(&self, bytes_sent: u64) -> Result<(), QuotaExceeded> {
+        self.apply(|quotas, inner| {
+            let usage = &mut inner.usage;
+            if quotas
+                .max_requests
+                .map_or(false, |max| usage.requests >= max)
+            {
+                return Err(QuotaExceeded("max_requests"));
+            }
+            if quotas
+                .max_bytes_sent
+                .map_or(false, |max| usage.bytes_sent + bytes_sent > max)
+            {
+                return Err(QuotaExceeded("max_bytes_sent"));
+            }
+            usage.requests += 1;
+            usage.bytes_sent += bytes_sent;
+            Ok(())
+        })
+    }
+
+    /// count the bytes sent in the opened handle.
+    pub fn send(&self, bytes: u64) -> Result<(), QuotaExceeded> {
+        self.apply(|quotas, inner| {
+            let usage = &mut inner.usage;
+            if quotas
+                .max_bytes_sent
+                .map_or(false, |max| usage.bytes_sent + bytes > max)
+            {
+                return Err(QuotaExceeded("max_bytes_sent"));
+            }
+            usage.bytes_sent += bytes;
+            Ok(())
+        })
+    }
+
+    /// the bytes can be received at most into the buffer, it's limited by the
+    /// remaining of the quota, count the received with [`Quota::received`].
+    pub fn receive_limit(&self, buf_len: u32) -> Result<u32, QuotaExceeded> {
+        let mut len = buf_len;
+        self.apply(|quotas, inner| {
+            if let Some(max) = quotas.max_bytes_received {
+                let remaining = max.saturating_sub(inner.usage.bytes_received);
+                if remaining == 0 && buf_len > 0 {
+                    return Err(QuotaExceeded("max_bytes_received"));
+                }
+                len = remaining.min(buf_len as u64) as u32;
+            }
+            Ok(())
+        })?;
+        Ok(len)
+    }
+
+    pub fn received(&self, bytes: u64) {
+        self.tracker.0.lock().unwrap().usage.bytes_received += bytes;
+    }
+
+    /// count a new spawned cgi process with its open handle, nothing is
+    /// counted if any of the quotas refuses it.
+    pub fn cgi_process(&self) -> Result<(), QuotaExceeded> {
+        self.apply(|quotas, inner| {
+            if quotas
+                .max_cgi_processes
+                .map_or(false, |max| inner.usage.cgi_processes >= max)
+            {
+                return Err(QuotaExceeded("max_cgi_processes"));
+            }
+            let open = Self::check_handle(quotas, inner, HostModule::Cgi)?;
+            inner.usage.cgi_processes += 1;
+            Self::count_handle(inner, HostModule::Cgi, open);
+            Ok(())
+        })
+    }
+
+    /// the counted cgi process failed to spawn.
+    pub fn cgi_process_failed(&self) {
+        let mut inner = self.tracker.0.lock().unwrap();
+        inner.usage.cgi_processes = inner.usage.cgi_processes.saturating_sub(1);
+        if let Some(open) = inner.open_handles.get_mut(&HostModule::Cgi) {
+            *open = open.saturating_sub(1);
+        }
+    }
+}
+
+/// the file counted as an open handle of the host module, the handle is
+/// closed when the file is dropped, e.g. by the `fd_close` of the guest.
+pub struct QuotaFile {
+    file: Box<dyn WasiFile>,
+    quota: Quota,
+    module: HostModule,
+}
+
+impl QuotaFile {
+    /// count the open handle, the file is dropped if the quota refuses it.
+    pub fn open(
+        file: Box<dyn WasiFile>,
+        quota: Quota,
+        module: HostModule,
+    ) -> Result<Self, QuotaExceeded> {
+        quota.open_handle(module)?;
+        Ok(Self {
+            file,
+            quota,
+            module,
+        })
+    }
+}
+
+impl Drop for QuotaFile {
+    fn drop(&mut self) {
+        self.quota.close_handle(self.module);
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for QuotaFile {
+    fn as_any(&self) -> &dyn Any {
+        self.file.as_any()
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.file.get_filetype().await
+    }
+
+    #[cfg(unix)]
+    fn pollable(&self) -> Option<rustix::fd::BorrowedFd> {
+        self.file.pollable()
+    }
+
+    #[cfg(windows)]
+    fn pollable(&self) -> Option<io_extras::os::windows::RawHandleOrSocket> {
+        self.file.pollable()
+    }
+
+    fn isatty(&self) -> bool {
+        self.file.isatty()
+    }
+
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        self.file.sock_accept(fdflags).await
+    }
+
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [std::io::IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        self.file.sock_recv(ri_data, ri_flags).await
+    }
+
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[std::io::IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        self.file.sock_send(si_data, si_flags).await
+    }
+
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.file.sock_shutdown(how).await
+    }
+
+    async fn datasync(&self) -> Result<(), Error> {
+        self.file.datasync().await
+    }
+
+    async fn sync(&self) -> Result<(), Error> {
+        self.file.sync().await
+    }
+
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.file.get_fdflags().await
+    }
+
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.file.set_fdflags(flags).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.file.get_filestat().await
+    }
+
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.file.set_filestat_size(size).await
+    }
+
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.file.advise(offset, len, advice).await
+    }
+
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.file.set_times(atime, mtime).await
+    }
+
+    async fn read_vectored<'a>(&self, bufs: &mut [std::io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.file.read_vectored(bufs).await
+    }
+
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [std::io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.file.read_vectored_at(bufs, offset).await
+    }
+
+    async fn write_vectored<'a>(&self, bufs: &[std::io::IoSlice<'a>]) -> Result<u64, Error> {
+        self.file.write_vectored(bufs).await
+    }
+
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[std::io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.file.write_vectored_at(bufs, offset).await
+    }
+
+    async fn seek(&self, pos: std::io::SeekFrom) -> Result<u64, Error> {
+        self.file.seek(pos).await
+    }
+
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.file.peek(buf).await
+    }
+
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.file.num_ready_bytes()
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        self.file.readable().await
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        self.file.writable().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quota() {
+        let quotas = ResourceQuotas {
+            max_open_handles: Some(1),
+            max_requests: Some(2),
+            max_bytes_sent: Some(10),
+            max_bytes_received: Some(8),
+            max_cgi_processes: Some(1),
+        };
+        let tracker = QuotaTracker::default();
+        let quota = Quota::new(quotas, tracker.clone());
+
+        assert!(quota.open_handle(HostModule::Http).is_ok());
+        assert!(quota.open_handle(HostModule::Ipfs).is_ok());
+        assert_eq!(
+            quota.open_handle(HostModule::Http),
+            Err(QuotaExceeded("max_open_handles"))
+        );
+        quota.close_handle(HostModule::Http);
+        assert!(quota.open_handle(HostModule::Http).is_ok());
+
+        assert!(quota.request(6).is_ok());
+        assert_eq!(quota.request(6), Err(QuotaExceeded("max_bytes_sent")));
+        assert!(quota.request(4).is_ok());
+        assert_eq!(quota.request(0), Err(QuotaExceeded("max_requests")));
+
+        assert_eq!(quota.receive_limit(5), Ok(5));
+        quota.received(5);
+        assert_eq!(quota.receive_limit(5), Ok(3));
+        quota.received(3);
+        assert_eq!(
+            quota.receive_limit(5),
+            Err(QuotaExceeded("max_bytes_received"))
+        );
+
+        assert!(quota.cgi_process().is_ok());
+        quota.cgi_process_failed();
+        assert!(quota.cgi_process().is_ok());
+        assert_eq!(quota.cgi_process(), Err(QuotaExceeded("max_cgi_processes")));
+
+        let usage = tracker.usage();
+        assert_eq!(usage.requests, 2);
+        assert_eq!(usage.bytes_sent, 10);
+        assert_eq!(usage.bytes_received, 8);
+        assert_eq!(usage.cgi_processes, 1);
+        assert_eq!(usage.peak_handles.get("http"), Some(&1));
+        assert_eq!(usage.peak_handles.get("ipfs"), Some(&1));
+        assert_eq!(usage.refused, 5);
+
+        // the cgi process isn't counted if its handle is refused.
+        let quotas = ResourceQuotas {
+            max_open_handles: Some(0),
+            ..Default::default()
+        };
+        let tracker = QuotaTracker::default();
+        let quota = Quota::new(quotas, tracker.clone());
+        assert_eq!(quota.cgi_process(), Err(QuotaExceeded("max_open_handles")));
+        assert_eq!(tracker.usage().cgi_processes, 0);
+
+        // the unlimited quotas only count the usage.
+        let quota = Quota::default();
+        assert_eq!(quota.receive_limit(1024), Ok(1024));
+        assert!(quota.request(1 << 20).is_ok());
+    }
+}
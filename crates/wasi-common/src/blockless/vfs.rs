@@ -0,0 +1,735 @@
+//! The read-only virtual file system, e.g. the package served from the CAR blocks.
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    io::{self, IoSliceMut, SeekFrom},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::dir::{OpenResult, ReaddirCursor, ReaddirEntity, WasiDir};
+use crate::file::{Advice, FdFlags, FileType, Filestat, OFlags, WasiFile};
+use crate::{Error, ErrorExt, SystemTimeSpec};
+
+/// The content of the virtual file.
+pub trait FileContent: Send + Sync {
+    fn len(&self) -> io::Result<u64>;
+
+    /// read the content at the offset, 0 is returned at the end of the content.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// read the whole content.
+    fn read_all(&self) -> io::Result<Vec<u8>> {
+        let mut data = vec![0u8; self.len()?.try_into().map_err(io::Error::other)?];
+        let mut pos = 0;
+        while pos < data.len() {
+            let n = self.read_at(&mut data[pos..], pos.try_into().map_err(io::Error::other)?)?;
+            if n == 0 {
+                break;
+            }
+            pos += n;
+        }
+        data.truncate(pos);
+        Ok(data)
+    }
+}
+
+impl FileContent for Vec<u8> {
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.as_slice().len() as u64)
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let len = self.as_slice().len();
+        let start = usize::try_from(offset).map_or(len, |o| o.min(len));
+        let n = buf.len().min(len - start);
+        buf[..n].copy_from_slice(&self[start..start + n]);
+        Ok(n)
+    }
+}
+
+/// The content read by the blocks on demand, e.g. the file in the CAR blocks.
+/// Only the lengths of the blocks are kept, they are read on the first access,
+/// and the last read block is kept for the sequential reads.
+/// The size is known without reading the lengths if it's given.
+pub struct BlockContent {
+    size: Option<u64>,
+    index: Box<dyn Fn() -> io::Result<Vec<u64>> + Send + Sync>,
+    read_block: Box<dyn Fn(usize) -> io::Result<Vec<u8>> + Send + Sync>,
+    // the start offsets of the blocks and the size.
+    offsets: OnceLock<(Vec<u64>, u64)>,
+    last: Mutex<Option<(usize, Arc<Vec<u8>>)>>,
+}
+
+impl BlockContent {
+    pub fn new<I, R>(size: Option<u64>, index: I, read_block: R) -> Self
+    where
+        I: Fn() -> io::Result<Vec<u64>> + Send + Sync + 'static,
+        R: Fn(usize) -> io::Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        Self {
+            size,
+            index: Box::new(index),
+            read_block: Box::new(read_block),
+            offsets: OnceLock::new(),
+            last: Mutex::new(None),
+        }
+    }
+
+    fn offsets(&self) -> io::Result<&(Vec<u64>, u64)> {
+        if let Some(offsets) = self.offsets.get() {
+            return Ok(offsets);
+        }
+        let mut offsets = Vec::new();
+        let mut size = 0;
+        for len in (self.index)()? {
+            offsets.push(size);
+            size += len;
+        }
+        Ok(self.offsets.get_or_init(|| (offsets, size)))
+    }
+
+    fn block(&self, i: usize) -> io::Result<Arc<Vec<u8>>> {
+        let mut last = self.last.lock().unwrap();
+        if let Some((n, ref data)) = *last {
+            if n == i {
+                return Ok(data.clone());
+            }
+        }
+        let data = Arc::new((self.read_block)(i)?);
+        *last = Some((i, data.clone()));
+        Ok(data)
+    }
+}
+
+impl FileContent for BlockContent {
+    fn len(&self) -> io::Result<u64> {
+        match self.size {
+            Some(size) if self.offsets.get().is_none() => Ok(size),
+            _ => Ok(self.offsets()?.1),
+        }
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let (offsets, size) = self.offsets()?;
+        // the last block starting before the offset.
+        let mut i = offsets
+            .partition_point(|start| *start <= offset)
+            .saturating_sub(1);
+        let mut readn = 0;
+        while readn < buf.len() && i < offsets.len() {
+            let pos = offset + readn as u64;
+            if pos >= *size {
+                break;
+            }
+            let data = self.block(i)?;
+            let start = (pos - offsets[i]) as usize;
+            if start < data.len() {
+                let n = (data.len() - start).min(buf.len() - readn);
+                buf[readn..readn + n].copy_from_slice(&data[start..start + n]);
+                readn += n;
+            }
+            i += 1;
+        }
+        Ok(readn)
+    }
+}
+
+/// The `Read` and `Seek` of the content, e.g. the CAR file in the package is
+/// opened without reading it into the memory.
+pub struct ContentReader {
+    content: Arc<dyn FileContent>,
+    pos: u64,
+}
+
+impl ContentReader {
+    pub fn new(content: Arc<dyn FileContent>) -> Self {
+        Self { content, pos: 0 }
+    }
+}
+
+impl io::Read for ContentReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.content.read_at(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl io::Seek for ContentReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.content.len()?.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum VirtualEntry {
+    File(Arc<dyn FileContent>),
+    Dir(Arc<VirtualDir>),
+}
+
+impl VirtualEntry {
+    fn filetype(&self) -> FileType {
+        match self {
+            VirtualEntry::File(_) => FileType::RegularFile,
+            VirtualEntry::Dir(_) => FileType::Directory,
+        }
+    }
+
+    fn filestat(&self) -> Result<Filestat, Error> {
+        let (size, nlink) = match self {
+            VirtualEntry::File(content) => (content.len()?, 1),
+            VirtualEntry::Dir(dir) => (0, 2 + dir.entries.len() as u64),
+        };
+        Ok(Filestat {
+            device_id: 0,
+            inode: 0,
+            filetype: self.filetype(),
+            nlink,
+            size,
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+}
+
+/// The directory of the virtual file system.
+#[derive(Clone, Default)]
+pub struct VirtualDir {
+    entries: BTreeMap<String, VirtualEntry>,
+}
+
+impl VirtualDir {
+    /// insert the entry with the path relative to the directory, e.g. `assets/logo.png`,
+    /// the parent directories are created.
+    pub fn insert(&mut self, path: &str, entry: VirtualEntry) {
+        let path = path.trim_matches('/');
+        match path.split_once('/') {
+            None => {
+                self.entries.insert(path.to_string(), entry);
+            }
+            Some((name, rest)) => {
+                let dir = match self.entries.get_mut(name) {
+                    Some(VirtualEntry::Dir(dir)) => dir,
+                    _ => {
+                        let dir = VirtualEntry::Dir(Arc::new(VirtualDir::default()));
+                        self.entries.insert(name.to_string(), dir);
+                        match self.entries.get_mut(name) {
+                            Some(VirtualEntry::Dir(dir)) => dir,
+                            _ => unreachable!("the directory is just inserted"),
+                        }
+                    }
+                };
+                Arc::make_mut(dir).insert(rest, entry);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&VirtualEntry> {
+        self.entries.get(name)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &VirtualEntry)> {
+        self.entries.iter()
+    }
+
+    /// write the directory into the host directory, the files are loaded.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        std::fs::create_dir_all(path)?;
+        for (name, entry) in self.entries.iter() {
+            match entry {
+                VirtualEntry::File(content) => {
+                    // the content is copied by the blocks, not loaded as a whole.
+                    let mut file = std::fs::File::create(path.join(name))?;
+                    io::copy(&mut ContentReader::new(content.clone()), &mut file)?;
+                }
+                VirtualEntry::Dir(dir) => dir.write_to(path.join(name))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// the entry of the path relative to the directory.
+    pub fn lookup(self: &Arc<Self>, path: impl AsRef<Path>) -> io::Result<VirtualEntry> {
+        let mut entry = VirtualEntry::Dir(self.clone());
+        for component in path.as_ref().components() {
+            let name = match component {
+                Component::Normal(name) => name.to_str().unwrap_or_default(),
+                Component::CurDir => continue,
+                _ => return Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+            };
+            entry = match entry {
+                VirtualEntry::Dir(dir) => dir
+                    .entries
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?,
+                VirtualEntry::File(_) => return Err(io::Error::from(io::ErrorKind::NotADirectory)),
+            };
+        }
+        Ok(entry)
+    }
+}
+
+/// The package mounted at the host path without touching the host file system,
+/// the host paths under the root are resolved in the package.
+#[derive(Clone)]
+pub struct PackageFs {
+    root: PathBuf,
+    dir: Arc<VirtualDir>,
+}
+
+impl PackageFs {
+    pub fn new(root: impl Into<PathBuf>, dir: VirtualDir) -> Self {
+        Self {
+            root: root.into(),
+            dir: Arc::new(dir),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// resolve the host path in the package, `None` if the path is out of the package.
+    /// The ancestors of the root are the directories only containing the package.
+    pub fn resolve(&self, host: impl AsRef<Path>) -> Option<io::Result<VirtualEntry>> {
+        let host = host.as_ref();
+        if let Ok(rest) = host.strip_prefix(&self.root) {
+            return Some(self.dir.lookup(rest));
+        }
+        let rest = self.root.strip_prefix(host).ok()?;
+        let mut entry = VirtualEntry::Dir(self.dir.clone());
+        for name in rest.iter().rev() {
+            let mut dir = VirtualDir::default();
+            dir.insert(name.to_str()?, entry);
+            entry = VirtualEntry::Dir(Arc::new(dir));
+        }
+        Some(Ok(entry))
+    }
+
+    /// open the file in the package, `None` if the path is out of the package.
+    pub fn open_file(&self, host: impl AsRef<Path>) -> Option<io::Result<Arc<dyn FileContent>>> {
+        let entry = self.resolve(host)?;
+        Some(entry.and_then(|entry| match entry {
+            VirtualEntry::File(content) => Ok(content),
+            VirtualEntry::Dir(_) => Err(io::Error::from(io::ErrorKind::IsADirectory)),
+        }))
+    }
+
+    /// read the file in the package, `None` if the path is out of the package.
+    pub fn read_file(&self, host: impl AsRef<Path>) -> Option<io::Result<Vec<u8>>> {
+        let content = self.open_file(host)?;
+        Some(content.and_then(|content| content.read_all()))
+    }
+
+    /// open the directory in the package as the read-only `WasiDir`,
+    /// `None` if the path is out of the package.
+    pub fn open_dir(&self, host: impl AsRef<Path>) -> Option<io::Result<Box<dyn WasiDir>>> {
+        let entry = self.resolve(host)?;
+        Some(entry.and_then(|entry| match entry {
+            VirtualEntry::Dir(dir) => Ok(Box::new(ReadOnlyDir::new(dir)) as Box<dyn WasiDir>),
+            VirtualEntry::File(_) => Err(io::Error::from(io::ErrorKind::NotADirectory)),
+        }))
+    }
+}
+
+/// The read-only `WasiDir` of the virtual directory.
+pub struct ReadOnlyDir {
+    // the preopened directory, the guest can't leave it with `..`.
+    root: Arc<VirtualDir>,
+    path: Vec<String>,
+}
+
+impl ReadOnlyDir {
+    pub fn new(root: Arc<VirtualDir>) -> Self {
+        Self {
+            root,
+            path: Vec::new(),
+        }
+    }
+
+    /// the path from the preopened directory.
+    fn join(&self, path: &str) -> Result<Vec<String>, Error> {
+        if path.starts_with('/') {
+            return Err(Error::perm());
+        }
+        let mut full = self.path.clone();
+        for name in path.split('/') {
+            match name {
+                "" | "." => {}
+                ".." => {
+                    full.pop().ok_or_else(Error::perm)?;
+                }
+                name => full.push(name.to_string()),
+            }
+        }
+        Ok(full)
+    }
+
+    fn lookup(&self, path: &[String]) -> Result<VirtualEntry, Error> {
+        let mut entry = VirtualEntry::Dir(self.root.clone());
+        for name in path {
+            entry = match entry {
+                VirtualEntry::Dir(dir) => dir.get(name).cloned().ok_or_else(Error::not_found)?,
+                VirtualEntry::File(_) => return Err(Error::not_dir()),
+            };
+        }
+        Ok(entry)
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiDir for ReadOnlyDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn open_file(
+        &self,
+        _symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        _read: bool,
+        write: bool,
+        _fdflags: FdFlags,
+    ) -> Result<OpenResult, Error> {
+        let path = self.join(path)?;
+        let entry = self.lookup(&path);
+        if oflags.contains(OFlags::CREATE | OFlags::EXCLUSIVE) && entry.is_ok() {
+            return Err(Error::exist());
+        }
+        if write || oflags.intersects(OFlags::CREATE | OFlags::TRUNCATE) {
+            return Err(Error::perm());
+        }
+        match entry? {
+            VirtualEntry::Dir(_) => Ok(OpenResult::Dir(Box::new(ReadOnlyDir {
+                root: self.root.clone(),
+                path,
+            }))),
+            VirtualEntry::File(_) if oflags.contains(OFlags::DIRECTORY) => Err(Error::not_dir()),
+            VirtualEntry::File(content) => {
+                Ok(OpenResult::File(Box::new(VirtualFile::new(content))))
+            }
+        }
+    }
+
+    async fn create_dir(&self, _path: &str) -> Result<(), Error> {
+        Err(Error::perm())
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        let dir = match self.lookup(&self.path)? {
+            VirtualEntry::Dir(dir) => dir,
+            VirtualEntry::File(_) => return Err(Error::not_dir()),
+        };
+        let entries = [(".", FileType::Directory), ("..", FileType::Directory)]
+            .into_iter()
+            .map(|(name, filetype)| (name.to_string(), filetype))
+            .chain(
+                dir.entries()
+                    .map(|(name, entry)| (name.clone(), entry.filetype())),
+            )
+            .enumerate()
+            .map(|(i, (name, filetype))| {
+                Ok(ReaddirEntity {
+                    next: (i as u64 + 1).into(),
+                    inode: 0,
+                    name,
+                    filetype,
+                })
+            })
+            .skip(u64::from(cursor) as usize)
+            .collect::<Vec<_>>();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    async fn symlink(&self, _old_path: &str, _new_path: &str) -> Result<(), Error> {
+        Err(Error::perm())
+    }
+
+    async fn remove_dir(&self, _path: &str) -> Result<(), Error> {
+        Err(Error::perm())
+    }
+
+    async fn unlink_file(&self, _path: &str) -> Result<(), Error> {
+        Err(Error::perm())
+    }
+
+    async fn read_link(&self, _path: &str) -> Result<PathBuf, Error> {
+        Err(Error::invalid_argument())
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.lookup(&self.path)?.filestat()
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        _follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        self.lookup(&self.join(path)?)?.filestat()
+    }
+
+    async fn rename(
+        &self,
+        _path: &str,
+        _dest_dir: &dyn WasiDir,
+        _dest_path: &str,
+    ) -> Result<(), Error> {
+        Err(Error::perm())
+    }
+
+    async fn hard_link(
+        &self,
+        _path: &str,
+        _target_dir: &dyn WasiDir,
+        _target_path: &str,
+    ) -> Result<(), Error> {
+        Err(Error::perm())
+    }
+
+    async fn set_times(
+        &self,
+        _path: &str,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+        _follow_symlinks: bool,
+    ) -> Result<(), Error> {
+        Err(Error::perm())
+    }
+}
+
+/// The read-only `WasiFile` of the virtual file.
+pub struct VirtualFile {
+    content: Arc<dyn FileContent>,
+    position: Mutex<u64>,
+}
+
+impl VirtualFile {
+    pub fn new(content: Arc<dyn FileContent>) -> Self {
+        Self {
+            content,
+            position: Mutex::new(0),
+        }
+    }
+}
+
+#[wiggle::async_trait]
+impl WasiFile for VirtualFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        VirtualEntry::File(self.content.clone()).filestat()
+    }
+
+    async fn advise(&self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn read_vectored<'a>(&self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut position = self.position.lock().unwrap();
+        let n = self.read_vectored_at(bufs, *position).await?;
+        *position += n;
+        Ok(n)
+    }
+
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let mut total = 0u64;
+        for buf in bufs.iter_mut() {
+            let n = self.content.read_at(buf, offset + total)?;
+            total += u64::try_from(n)?;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    async fn seek(&self, pos: SeekFrom) -> Result<u64, Error> {
+        let mut position = self.position.lock().unwrap();
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (0, i128::from(offset)),
+            SeekFrom::Current(delta) => (*position, i128::from(delta)),
+            SeekFrom::End(delta) => (self.content.len()?, i128::from(delta)),
+        };
+        let offset =
+            u64::try_from(i128::from(base) + delta).map_err(|_| Error::invalid_argument())?;
+        *position = offset;
+        Ok(offset)
+    }
+
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        let n = self.content.read_at(buf, position)?;
+        Ok(n.try_into()?)
+    }
+
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        let position = *self.position.lock().unwrap();
+        Ok(self.content.len()?.saturating_sub(position))
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn package() -> PackageFs {
+        let mut dir = VirtualDir::default();
+        dir.insert("main.wasm", VirtualEntry::File(Arc::new(b"main".to_vec())));
+        dir.insert(
+            "assets/img/logo.png",
+            VirtualEntry::File(Arc::new(b"logo".to_vec())),
+        );
+        PackageFs::new("/env/bafy", dir)
+    }
+
+    #[test]
+    fn test_package_resolve() {
+        let package = package();
+        let data = package.read_file("/env/bafy/main.wasm").unwrap().unwrap();
+        assert_eq!(data, b"main");
+        assert!(package.read_file("/env/bafy/no.wasm").unwrap().is_err());
+        assert!(package.read_file("/env/bafy/assets").unwrap().is_err());
+        assert!(package.read_file("/env/other/main.wasm").is_none());
+        // the ancestor of the package root only contains the package.
+        match package.resolve("/env").unwrap().unwrap() {
+            VirtualEntry::Dir(dir) => {
+                let names = dir.entries().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+                assert_eq!(names, vec!["bafy"]);
+            }
+            VirtualEntry::File(_) => panic!("should be the directory"),
+        }
+    }
+
+    #[test]
+    fn test_block_content() {
+        let blocks = vec![b"abc".to_vec(), Vec::new(), b"defg".to_vec()];
+        let reads = Arc::new(Mutex::new(Vec::new()));
+        let counter = reads.clone();
+        let content = BlockContent::new(
+            Some(7),
+            || Ok(vec![3, 0, 4]),
+            move |i| {
+                counter.lock().unwrap().push(i);
+                Ok(blocks[i].clone())
+            },
+        );
+        assert_eq!(content.len().unwrap(), 7);
+        assert!(reads.lock().unwrap().is_empty());
+        // only the blocks of the range are read.
+        let mut buf = [0u8; 2];
+        assert_eq!(content.read_at(&mut buf, 4).unwrap(), 2);
+        assert_eq!(&buf, b"ef");
+        assert_eq!(*reads.lock().unwrap(), vec![2]);
+        // the last block is kept for the sequential reads.
+        assert_eq!(content.read_at(&mut buf, 6).unwrap(), 1);
+        assert_eq!(*reads.lock().unwrap(), vec![2]);
+        let mut buf = [0u8; 4];
+        assert_eq!(content.read_at(&mut buf, 2).unwrap(), 4);
+        assert_eq!(&buf, b"cdef");
+        assert_eq!(content.read_at(&mut buf, 7).unwrap(), 0);
+        assert_eq!(content.read_all().unwrap(), b"abcdefg");
+
+        let mut reader = ContentReader::new(Arc::new(content));
+        let mut data = Vec::new();
+        io::Seek::seek(&mut reader, SeekFrom::End(-3)).unwrap();
+        io::Read::read_to_end(&mut reader, &mut data).unwrap();
+        assert_eq!(data, b"efg");
+        assert!(io::Seek::seek(&mut reader, SeekFrom::Current(-8)).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut vdir = VirtualDir::default();
+        vdir.insert("a/b.txt", VirtualEntry::File(Arc::new(b"data".to_vec())));
+        vdir.write_to(dir.path()).unwrap();
+        assert_eq!(std::fs::read(dir.path().join("a/b.txt")).unwrap(), b"data");
+    }
+
+    #[tokio::test]
+    async fn test_read_only_dir() {
+        let dir = package().open_dir("/env").unwrap().unwrap();
+        let file = match dir
+            .open_file(
+                false,
+                "bafy/assets/../main.wasm",
+                OFlags::empty(),
+                true,
+                false,
+                FdFlags::empty(),
+            )
+            .await
+            .unwrap()
+        {
+            OpenResult::File(file) => file,
+            OpenResult::Dir(_) => panic!("should be the file"),
+        };
+        let mut buf = [0u8; 8];
+        let n = file
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(&buf[..n as usize], b"main");
+        assert_eq!(file.seek(SeekFrom::End(-2)).await.unwrap(), 2);
+
+        let flags = FdFlags::empty();
+        assert!(dir
+            .open_file(false, "bafy/main.wasm", OFlags::empty(), true, true, flags)
+            .await
+            .is_err());
+        assert!(dir
+            .open_file(false, "../etc/passwd", OFlags::empty(), true, false, flags)
+            .await
+            .is_err());
+        assert!(dir.create_dir("tmp").await.is_err());
+
+        let sub = match dir
+            .open_file(false, "bafy", OFlags::DIRECTORY, true, false, flags)
+            .await
+            .unwrap()
+        {
+            OpenResult::Dir(dir) => dir,
+            OpenResult::File(_) => panic!("should be the directory"),
+        };
+        let names = sub
+            .readdir(0.into())
+            .await
+            .unwrap()
+            .map(|e| e.unwrap().name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![".", "..", "assets", "main.wasm"]);
+        let stat = sub.get_path_filestat("main.wasm", true).await.unwrap();
+        assert_eq!(stat.size, 4);
+    }
+}
@@ -4,8 +4,12 @@ use crate::file::{FileAccessMode, FileEntry, WasiFile};
 use crate::sched::WasiSched;
 use crate::string_array::StringArray;
 use crate::table::Table;
-use crate::{BlocklessConfig, BlsRuntimePermissionsContainer, PermissionsConfig};
+use crate::{
+    BlocklessConfig, BlsRuntimePermissionsContainer, DeniedAccess, HttpClientConfig,
+    PermissionsConfig, Quota,
+};
 use crate::{Error, StringArrayError};
+use bls_permissions::{PermissionState, Url};
 use cap_rand::RngCore;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
@@ -74,13 +78,137 @@ impl WasiCtx {
             .and_then(|l| l.fix_stdin_ref().map(String::from))
     }
 
-    pub fn resource_permission(&self, resource: &str) -> bool {
+    fn config_resource_permission(&self, resource: &str) -> bool {
         match self.blockless_config.lock().unwrap().deref() {
             Some(ref c) => c.resource_permission(resource),
             None => false,
         }
     }
 
+    /// the resource is granted by the `permissions` urls, the decision is audited.
+    pub fn resource_permission(&self, resource: &str, api_name: &str) -> bool {
+        let allowed = self.config_resource_permission(resource);
+        self.perms_container
+            .audit
+            .record(resource, api_name, allowed);
+        allowed
+    }
+
+    /// the permission decisions of this context are audited by the other one.
+    pub fn forward_audit_to(&self, other: &WasiCtx) {
+        self.perms_container
+            .audit
+            .forward_to(&other.perms_container.audit);
+    }
+
+    /// the summary of the denied accesses of the guest.
+    pub fn denied_accesses(&self) -> Vec<DeniedAccess> {
+        self.perms_container.audit.denied_summary()
+    }
+
+    /// the quotas of the host resources, unlimited if there is no configure.
+    pub fn quota(&self) -> Quota {
+        match self.blockless_config.lock().unwrap().deref() {
+            Some(ref c) => Quota::new(c.quotas.clone(), c.resource_usage.clone()),
+            None => Quota::default(),
+        }
+    }
+
+    /// the settings of the shared http client.
+    pub fn http_client_config(&self) -> HttpClientConfig {
+        match self.blockless_config.lock().unwrap().deref() {
+            Some(ref c) => c.http_client.clone(),
+            None => HttpClientConfig::default(),
+        }
+    }
+
+    /// the host environment variables exposed to the guest.
+    pub fn exposed_host_envs(&self) -> Vec<(String, String)> {
+        match self.blockless_config.lock().unwrap().deref() {
+            Some(ref c) => c.exposed_host_envs(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn run_permission(
+        &self,
+        alias: &str,
+        args: &[String],
+        envs: &[(String, String)],
+        api_name: &str,
+    ) -> bool {
+        let allowed = match self.blockless_config.lock().unwrap().deref() {
+            Some(ref c) => c.run_permission(alias, args, envs),
+            None => false,
+        };
+        self.perms_container.audit.record(alias, api_name, allowed);
+        allowed
+    }
+
+    pub fn listen_permission(&self, addr: &str, api_name: &str) -> bool {
+        let allowed = match self.blockless_config.lock().unwrap().deref() {
+            Some(ref c) => c.listen_permission(addr),
+            None => false,
+        };
+        self.perms_container.audit.record(addr, api_name, allowed);
+        allowed
+    }
+
+    pub fn connect_permission(&self, addr: &str, api_name: &str) -> bool {
+        let allowed = match self.blockless_config.lock().unwrap().deref() {
+            Some(ref c) => c.connect_permission(addr),
+            None => false,
+        };
+        self.perms_container.audit.record(addr, api_name, allowed);
+        allowed
+    }
+
+    /// the permission requests not granted by the flags are asked only if the
+    /// `prompter` is configured, otherwise they are denied without blocking the guest.
+    fn prompter_configured(&self) -> bool {
+        match self.blockless_config.lock().unwrap().deref() {
+            Some(ref c) => c.permissions_config.prompter.is_some(),
+            None => false,
+        }
+    }
+
+    /// check the network access of the guest to the url, the access is allowed by the
+    /// resource permissions or the `allow_net` hosts, the `deny_net` hosts are never allowed.
+    /// The access not granted is asked by the prompter if it's configured.
+    pub fn check_net_url(&self, url: &str, api_name: &str) -> Result<(), anyhow::Error> {
+        let parsed = Url::parse(url)?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("the url {url} has no host"))?;
+        let addr = match parsed.port_or_known_default() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        };
+        let audit = &self.perms_container.audit;
+        if let PermissionState::Denied = self.perms_container.query_net(Some(&addr))? {
+            audit.record(url, api_name, false);
+            anyhow::bail!("{api_name}: the network access to {addr} is denied");
+        }
+        if self.config_resource_permission(url) {
+            audit.record(url, api_name, true);
+            return Ok(());
+        }
+        if !self.prompter_configured() {
+            let granted = matches!(
+                self.perms_container.query_net(Some(&addr))?,
+                PermissionState::Granted
+            );
+            audit.record(url, api_name, granted);
+            if !granted {
+                anyhow::bail!("{api_name}: the network access to {addr} is not granted");
+            }
+            return Ok(());
+        }
+        self.perms_container
+            .clone()
+            .check_net_url(&parsed, api_name)
+    }
+
     pub fn set_permissions_config(
         &mut self,
         config: &PermissionsConfig,
@@ -28,7 +28,7 @@ pub use clocks::clocks_ctx;
 pub use sched::sched_ctx;
 
 use self::net::Socket;
-use crate::{file::FileAccessMode, table::Table, Error, WasiCtx, WasiFile};
+use crate::{file::FileAccessMode, table::Table, Error, WasiCtx, WasiDir, WasiFile};
 use cap_rand::{Rng, RngCore, SeedableRng};
 use std::mem;
 use std::path::Path;
@@ -122,6 +122,15 @@ impl WasiCtxBuilder {
         self.ctx.push_preopened_dir(dir, guest_path)?;
         Ok(self)
     }
+    /// preopen the `WasiDir` not backed by the host directory, e.g. the virtual directory.
+    pub fn preopened_wasi_dir(
+        &mut self,
+        dir: Box<dyn WasiDir>,
+        guest_path: impl AsRef<Path>,
+    ) -> Result<&mut Self, Error> {
+        self.ctx.push_preopened_dir(dir, guest_path)?;
+        Ok(self)
+    }
     pub fn push_prepush_socket(&mut self, socket: impl Into<Socket>) -> Result<&mut Self, Error> {
         let socket: Socket = socket.into();
         let file: Box<dyn WasiFile> = socket.into();